@@ -1,25 +1,36 @@
 use clap::{Parser, Subcommand};
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
 use log::warn;
 use oss_porter_core::{
   check::check_project,
   config::{get_default_config_path, load_config, save_config},
-  extract::{extract_clean_slate, extract_preserve_history},
+  extract::{extract_clean_slate, extract_preserve_history, plan_extraction},
+  identity::Identity,
+  patch::{parse_edited_mbox, BoundaryMove, CommitPatch, PatchSelection},
+  policy::{run_policy_update, UpdatePolicy, UpdateStatus},
+  relocation::{find_subdir_relocation, follow_subdir_relocation, SubdirRelocation},
   remote::push_to_remote,
+  selection::SelectionRules,
+  session::{abort_update_session, clear_update_session, load_update_session, UpdateSession},
   state::{
-    commit_state_file_change, get_internal_state_file_path, read_last_synced_commit,
-    write_last_synced_commit, STATE_FILE_NAME,
+    commit_state_file_change, find_sync_records, get_internal_state_file_path,
+    read_last_synced_commit, read_pending_commits, read_skipped_commits, read_sync_history,
+    record_sync_decision, skip_commit, unskip_commit, write_last_synced_commit,
+    write_pending_commits, SyncDecision, SyncRecord, STATE_FILE_NAME,
   },
   update::{
-    apply_commit_to_output, get_commit_diff_relative, get_internal_commits_since, ApplyResult,
-    CommitInfo,
+    abort_apply_session, advance_sync_cursor, apply_in_progress, apply_patch_to_output,
+    build_commit_patch, build_squashed_patch, continue_apply_session, get_internal_commits_since,
+    output_head, ApplyResult, CommitInfo, PublicPatchIds,
   },
-  ConfigFile, HistoryMode, PorterError, ProjectConfig,
+  ConfigFile, ExtractionPlan, HistoryMode, HistoryRewriter, IdentityMapConfig, LicenseHeaderConfig,
+  MergeStrategy, PorterError, ProjectConfig, ScrubConfig, SelectionAction,
 };
 use std::{
-  fs,
+  collections::HashMap,
+  env, fs,
   path::{Path, PathBuf},
-  process::exit,
+  process::{exit, Command},
 }; // For exiting on error
 
 #[derive(Parser, Debug)]
@@ -44,11 +55,14 @@ enum Commands {
     project_id: String,
     #[arg(long, value_enum, help = "Specify history mode (overrides config)")]
     mode: Option<oss_porter_core::HistoryMode>,
+    #[arg(
+      long,
+      help = "Only report what would be extracted; don't touch the output path"
+    )]
+    dry_run: bool,
   },
   /// Run checks (secrets, dependencies, license) on an extracted project
-  Check {
-    project_id: String,
-  },
+  Check { project_id: String },
   Push {
     project_id: String,
     #[arg(short, long, help = "Skip confirmation prompt before pushing")]
@@ -56,6 +70,63 @@ enum Commands {
   },
   Update {
     project_id: String,
+    #[arg(
+      long = "continue",
+      conflicts_with = "abort",
+      help = "Finish the conflicted commit and resume the interrupted update session"
+    )]
+    continue_session: bool,
+    #[arg(long, help = "Roll back the interrupted update session")]
+    abort: bool,
+    #[arg(
+      long,
+      conflicts_with_all = ["continue_session", "abort"],
+      help = "Publish all new commits as a single public commit"
+    )]
+    squash: bool,
+    #[arg(
+      long,
+      value_name = "FILE",
+      conflicts_with_all = ["continue_session", "abort", "squash"],
+      help = "Run without prompts, applying the commits selected by this policy file"
+    )]
+    policy: Option<PathBuf>,
+    #[arg(
+      long,
+      value_name = "FILE",
+      requires = "policy",
+      help = "Write the JSON summary of a --policy run to this file instead of stdout"
+    )]
+    summary: Option<PathBuf>,
+    #[arg(
+      long,
+      requires = "policy",
+      help = "Commit the updated sync state after a --policy run"
+    )]
+    commit_state: bool,
+  },
+  /// List or un-skip internal commits marked "skip always" during update
+  Skipped {
+    #[command(subcommand)]
+    action: SkippedAction,
+  },
+  /// Show the recorded internal -> public commit mapping of a project
+  History {
+    project_id: String,
+    #[arg(help = "Only show entries for this internal or public commit (hash or prefix)")]
+    commit: Option<String>,
+  },
+}
+
+#[derive(Subcommand, Debug)]
+enum SkippedAction {
+  /// List the skipped commits of a project
+  List { project_id: String },
+  /// Offer a skipped commit again on the next update
+  Remove {
+    project_id: String,
+    #[arg(help = "Internal commit hash (or unique prefix)")]
+    commit: String,
   },
 }
 
@@ -90,10 +161,47 @@ const DEFAULT_CONFIG_CONTENT: &str = r#"# oss-porter Configuration File
 # project_subdir = "path/relative/to/repo/root/of/the/project" # REQUIRED: Subdirectory within the repo to extract (use "." if it's the whole repo)
 # output_path = "/path/to/where/you/want/the/public_version"    # REQUIRED: Directory where the clean OSS version will be created
 # public_repo_url = "git@github.com:your-username/my_cool_library.git" # Optional: URL for the public remote repo
-# history_mode = "clean_slate" # Optional: "clean_slate" (default) or "preserve" (requires git-filter-repo)
-# license = "MIT" # Optional: License for this specific project (overrides default_license)
+# history_mode = "clean_slate" # Optional: "clean_slate" (default) or "preserve"
+# history_rewriter = "native"   # Optional: how "preserve" rewrites history, "native" (default, needs only git) or "filter_repo" (requires git-filter-repo)
+# license = "MIT OR Apache-2.0" # Optional: SPDX expression; extract writes the license text(s) and sets Cargo.toml `license`
+# copyright_holder = "My Org"     # Optional: name in the license texts (defaults to the crate's first author)
 # internal_branch = "main" # Default, can be omitted
+# merge_strategy = "first_parent" # Default: update offers mainline commits only; "flatten" offers each commit of merged branches, "squash" each merge as one commit
 # public_branch = "main"   # Default, can be omitted
+# include = ["src/**", "Cargo.toml", "README.md"] # Optional: only publish matching files (globs relative to project_subdir)
+# exclude = ["internal_docs/**", "*.env", "deploy/"] # Optional: never publish matching files
+# [projects.my_cool_library.scrub] # Optional: strip internal-only code (off unless enabled)
+# enabled = true                   # Required to turn scrubbing on
+# begin_marker = "OSS-STRIP-BEGIN" # Lines from a line containing this...
+# end_marker = "OSS-STRIP-END"     # ...through a line containing this are removed
+# cfg_names = ["internal"]         # Rust items under #[cfg(internal)] are removed
+# [projects.my_cool_library.license_header] # Optional: SPDX header at the top of source files
+# mode = "add" # "off" (default), "add", "replace" or "verify"
+# template = ["Copyright (c) {year} {holder}", "SPDX-License-Identifier: {license}"] # Optional
+# [projects.my_cool_library.identity_map] # Optional: public identities for internal authors/committers
+# entries = ["Jane Doe <jane@example.org> <jane.doe@corp.internal>"] # git mailmap lines
+# file = ".mailmap-oss"                 # Optional mailmap file, relative to internal_repo_path
+# fallback = "Team <oss@company.com>"   # Everyone not listed above
+# [[projects.my_cool_library.message_rules]] # Optional: rewrite published commit messages (in order)
+# pattern = '\b[A-Z][A-Z0-9]+-\d+\b' # e.g. strip Jira keys (replace defaults to "")
+# [[projects.my_cool_library.message_rules]]
+# pattern = '^Reviewed-on:'          # drop whole trailer lines
+# drop_line = true
+# [[projects.my_cool_library.message_rules]]
+# pattern = 'go/(\S+)'
+# replace = 'internal link'
+# [[projects.my_cool_library.selection_rules]] # Optional: let commits opt in/out of publication (first match wins)
+# trailer = "OSS: no"   # "Key: value" trailer, or just "Key"
+# action = "skip"       # "apply", "skip" or "review" (ask, the default for unmatched commits)
+# [[projects.my_cool_library.selection_rules]]
+# tag = "[no-oss]"      # text anywhere in the message
+# action = "skip"
+# [[projects.my_cool_library.selection_rules]]
+# trailer = "OSS: yes"
+# action = "apply"
+# [projects.my_cool_library.dependency_rewrites] # Optional: turn internal path deps into published ones
+# my-internal-util = { version = "0.3" }
+# other-lib = { git = "https://github.com/my-org/other-lib.git", tag = "v1.0" }
 "#;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -152,12 +260,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
   // Execute the command (excluding init, which was handled above)
   let result = match cli.command {
     Commands::Config { action } => handle_config_action_read_only(action, &config_file),
-    Commands::Extract { project_id, mode } => handle_extract(project_id, mode, &config_file),
+    Commands::Extract {
+      project_id,
+      mode,
+      dry_run,
+    } => handle_extract(project_id, mode, dry_run, &config_file),
     Commands::Check { project_id } => handle_check(project_id, &config_file),
     Commands::Push { project_id, force } => handle_push(project_id, force, &config_file),
-    Commands::Update { project_id } => {
-      handle_update(project_id, &config_file, cli.config.as_deref())
-    }
+    Commands::Update {
+      project_id,
+      policy: Some(policy),
+      summary,
+      commit_state,
+      ..
+    } => handle_policy_update(
+      project_id,
+      &policy,
+      summary.as_deref(),
+      commit_state,
+      &config_file,
+    ),
+    Commands::Update {
+      project_id,
+      continue_session,
+      abort,
+      squash,
+      policy: None,
+      ..
+    } => handle_update(
+      project_id,
+      continue_session,
+      abort,
+      squash,
+      &config_file,
+      cli.config.as_deref(),
+    ),
+    Commands::Skipped { action } => handle_skipped(action, &config_file),
+    Commands::History { project_id, commit } => handle_history(project_id, commit, &config_file),
   };
 
   if let Err(e) = result {
@@ -258,7 +397,7 @@ fn handle_config_action_read_only(
       eprintln!("'config add' requires modification - Refactoring needed in main loop.");
       // Placeholder - Requires adjustment in main's structure
     }
-    ConfigAction::Remove { project_id } => {
+    ConfigAction::Remove { project_id: _ } => {
       // Need to reload config mutably or pass mutable ref from main
       eprintln!("'config remove' requires modification - Refactoring needed in main loop.");
       // Placeholder - Requires adjustment in main's structure
@@ -271,6 +410,7 @@ fn handle_config_action_read_only(
 fn handle_extract(
   project_id: String,
   mode_override: Option<HistoryMode>,
+  dry_run: bool,
   config_file: &ConfigFile,
 ) -> Result<(), Box<dyn std::error::Error>> {
   // Return Result
@@ -284,11 +424,22 @@ fn handle_extract(
   let history_mode = mode_override.unwrap_or(project_config.history_mode);
   log::info!("Using history mode: {:?}", history_mode);
 
+  if dry_run {
+    let plan = plan_extraction(&project_id, project_config, history_mode)?;
+    print_extraction_plan(&plan);
+    return Ok(());
+  }
+
   // !! Optional: Add a warning/confirmation for preserve mode !!
   if history_mode == HistoryMode::Preserve {
-    println!("\nWARNING: History preservation mode ('preserve') uses 'git-filter-repo'.");
-    println!(" - This rewrites history and operates on a temporary clone.");
-    println!(" - Ensure 'git-filter-repo' is installed and accessible.");
+    if project_config.history_rewriter == HistoryRewriter::FilterRepo {
+      println!("\nWARNING: History preservation mode ('preserve') uses 'git-filter-repo'.");
+      println!(" - This rewrites history and operates on a temporary clone.");
+      println!(" - Ensure 'git-filter-repo' is installed and accessible.");
+    } else {
+      println!("\nWARNING: History preservation mode ('preserve') rewrites the internal history.");
+      println!(" - This operates on a temporary clone, using only git.");
+    }
     println!(" - Review the resulting repository carefully for any unintentionally exposed history or secrets.");
     // Add an interactive confirmation prompt here if desired
     // e.g., use a crate like `dialoguer` or simple stdin read.
@@ -318,6 +469,12 @@ fn handle_extract(
       for msg in extraction_result.messages {
         println!("- {}", msg);
       }
+      if !extraction_result.stripped_regions.is_empty() {
+        println!("\nStripped internal-only regions:");
+        for region in &extraction_result.stripped_regions {
+          println!("- {}", region);
+        }
+      }
       if !extraction_result.secrets_found.is_empty() {
         println!("\nWARNING: Potential secrets found during scan of FINAL code state:");
         for finding in extraction_result.secrets_found {
@@ -327,6 +484,34 @@ fn handle_extract(
           "Please review the code AND HISTORY in the output directory carefully before publishing."
         );
       }
+
+      match extraction_result.synced_commit.as_deref() {
+        Some(commit) => {
+          if Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+              "Commit the initial sync state ({}) to the internal repository '{}'?",
+              commit,
+              project_config.internal_repo_path.display()
+            ))
+            .default(true)
+            .interact()?
+          {
+            match commit_state_file_change(project_config, Some(commit)) {
+              Ok(()) => println!("State file committed successfully."),
+              Err(e) => eprintln!("Error committing state file to internal repo: {}", e),
+            }
+          } else {
+            println!(
+              "Reminder: Commit '{}' to the internal repository before running 'update'.",
+              get_internal_state_file_path(project_config).display()
+            );
+          }
+        }
+        None => println!(
+          "\nNo sync state was recorded; create '{}' manually before running 'update'.",
+          get_internal_state_file_path(project_config).display()
+        ),
+      }
     }
     Err(e) => {
       return Err(Box::new(e));
@@ -335,6 +520,178 @@ fn handle_extract(
   Ok(())
 }
 
+/// Prints the result of `extract --dry-run`.
+fn print_extraction_plan(plan: &ExtractionPlan) {
+  println!(
+    "\nDry run for project '{}' (nothing was written).",
+    plan.project_id
+  );
+  println!("Mode: {:?}", plan.history_mode);
+  println!("Output location: {}", plan.output_path.display());
+  if let Some(count) = plan.surviving_commits {
+    println!("Commits surviving history filtering: {}", count);
+  }
+  if !plan.warnings.is_empty() {
+    println!("\nWARNING: the extraction would currently fail:");
+    for warning in &plan.warnings {
+      println!("- {}", warning);
+    }
+  }
+
+  println!("\nFiles to copy ({}):", plan.files_to_copy.len());
+  for file in &plan.files_to_copy {
+    println!("  {}", file.display());
+  }
+  if !plan.files_excluded.is_empty() {
+    println!(
+      "\nFiles excluded by filters ({}):",
+      plan.files_excluded.len()
+    );
+    for file in &plan.files_excluded {
+      println!("  {}", file.display());
+    }
+  }
+
+  println!("\nLicense and .gitignore:");
+  for action in &plan.license_actions {
+    println!("- {}", action);
+  }
+  println!("- {}", plan.gitignore_action);
+  if !plan.transform_notes.is_empty() {
+    println!("\nContent transforms:");
+    for note in &plan.transform_notes {
+      println!("- {}", note);
+    }
+  }
+  if !plan.stripped_regions.is_empty() {
+    println!("\nInternal-only regions that would be stripped:");
+    for region in &plan.stripped_regions {
+      println!("- {}", region);
+    }
+  }
+
+  println!("\n--- Secrets Scan ---");
+  if plan.secrets_found.is_empty() {
+    println!("No potential secrets found.");
+  } else {
+    for finding in &plan.secrets_found {
+      println!("- {}", finding);
+    }
+  }
+  println!("\n--- Dependency Check ---");
+  if plan.dependency_findings.is_empty() {
+    println!("No internal path dependencies or workspace inheritance found.");
+  } else {
+    for finding in &plan.dependency_findings {
+      println!("- {}", finding);
+    }
+  }
+}
+
+fn handle_skipped(
+  action: SkippedAction,
+  config_file: &ConfigFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let project_id = match &action {
+    SkippedAction::List { project_id } | SkippedAction::Remove { project_id, .. } => project_id,
+  };
+  let project_config = config_file
+    .projects
+    .get(project_id)
+    .ok_or_else(|| format!("Project '{}' not found in configuration.", project_id))?;
+
+  match action {
+    SkippedAction::List { .. } => {
+      let skipped = read_skipped_commits(project_config)?;
+      if skipped.is_empty() {
+        println!("No skipped commits recorded for project '{}'.", project_id);
+      }
+      for entry in skipped {
+        println!(
+          "{} (skipped {}){}",
+          entry.commit,
+          entry.timestamp,
+          entry.reason.map(|r| format!(": {}", r)).unwrap_or_default()
+        );
+      }
+    }
+    SkippedAction::Remove { commit, .. } => match unskip_commit(project_config, &commit)? {
+      Some(removed) => {
+        println!(
+          "Commit {} will be offered again on the next 'update'.",
+          removed.commit
+        );
+        if Confirm::with_theme(&ColorfulTheme::default())
+          .with_prompt("Commit this state change to the internal repository?")
+          .interact()?
+        {
+          let last_synced = read_last_synced_commit(project_config)?;
+          commit_state_file_change(project_config, last_synced.as_deref())?;
+          println!("State file committed successfully.");
+        } else {
+          println!(
+            "Reminder: Commit the change in '{}' manually.",
+            get_internal_state_file_path(project_config).display()
+          );
+        }
+      }
+      None => println!("No skipped commit matches '{}'.", commit),
+    },
+  }
+  Ok(())
+}
+
+fn handle_history(
+  project_id: String,
+  commit: Option<String>,
+  config_file: &ConfigFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let project_config = config_file
+    .projects
+    .get(&project_id)
+    .ok_or_else(|| format!("Project '{}' not found in configuration.", project_id))?;
+
+  let records = match &commit {
+    Some(commit) => find_sync_records(project_config, commit)?,
+    None => read_sync_history(project_config)?,
+  };
+  if records.is_empty() {
+    match commit {
+      Some(commit) => println!("No sync history entry for commit '{}'.", commit),
+      None => println!("No sync history recorded for project '{}' yet.", project_id),
+    }
+    return Ok(());
+  }
+
+  println!(
+    "{:<22} {:<17} {:<40} Public commit",
+    "Timestamp", "Decision", "Internal commit"
+  );
+  for record in records {
+    let decision = match record.decision {
+      SyncDecision::Applied => "applied",
+      SyncDecision::Skipped => "skipped",
+      SyncDecision::Edited => "edited",
+      SyncDecision::PreviouslySynced => "previously synced",
+      SyncDecision::AlreadyApplied => "already applied",
+      SyncDecision::Relocated => "subdir moved",
+      SyncDecision::Squashed => "squashed",
+      SyncDecision::Partial => "partial",
+    };
+    println!(
+      "{:<22} {:<17} {:<40} {}",
+      record.timestamp,
+      decision,
+      record.internal_commit,
+      record.public_commit.as_deref().unwrap_or("-")
+    );
+    for part in &record.omitted {
+      println!("{:<22} left out: {}", "", part);
+    }
+  }
+  Ok(())
+}
+
 fn handle_check(
   project_id: String,
   config_file: &ConfigFile,
@@ -391,6 +748,33 @@ fn handle_check(
         );
       }
 
+      // Workspace Inheritance
+      if check_result.workspace_inherited_found.is_empty() {
+        println!("[✓] Workspace Check: No manifest keys inherited from an internal workspace.");
+      } else {
+        println!(
+          "[!] Workspace Check: Found {} manifest keys still set to 'workspace = true':",
+          check_result.workspace_inherited_found.len()
+        );
+        for finding in check_result.workspace_inherited_found {
+          println!("  - {}", finding);
+        }
+        println!("    Re-run extraction or inline these values before publishing.");
+      }
+
+      // License Headers
+      if check_result.missing_headers.is_empty() {
+        println!("[✓] Header Check: No source files missing the expected license header.");
+      } else {
+        println!(
+          "[!] Header Check: {} source files lack the expected license header:",
+          check_result.missing_headers.len()
+        );
+        for path in check_result.missing_headers {
+          println!("  - {}", path);
+        }
+      }
+
       // License Check
       if check_result.license_ok {
         println!("[✓] License Check: Found a file starting with 'LICENSE' or 'COPYING'.");
@@ -598,7 +982,7 @@ fn handle_config_add_reload(
     .with_prompt("History Mode")
     .items(&[
       "clean-slate (Recommended Default)",
-      "preserve (Requires git-filter-repo)",
+      "preserve (Rewrites the internal history)",
     ])
     .default(0)
     .interact()?;
@@ -607,6 +991,21 @@ fn handle_config_add_reload(
   } else {
     HistoryMode::Preserve
   };
+  let history_rewriter = if history_mode == HistoryMode::Preserve
+    && Select::with_theme(&theme)
+      .with_prompt("History rewriter")
+      .items(&[
+        "native (Built in, needs only git)",
+        "filter-repo (Requires git-filter-repo)",
+      ])
+      .default(0)
+      .interact()?
+      == 1
+  {
+    HistoryRewriter::FilterRepo
+  } else {
+    HistoryRewriter::Native
+  };
 
   // --- Branch Configuration Input ---
   let default_branch_name = "main"; // Use a constant for clarity
@@ -651,7 +1050,7 @@ fn handle_config_add_reload(
   };
 
   let license: String = Input::with_theme(&theme)
-    .with_prompt("License (Optional, SPDX ID like 'MIT' or 'Apache-2.0')")
+    .with_prompt("License (Optional, SPDX expression like 'MIT' or 'MIT OR Apache-2.0')")
     .allow_empty(true)
     .interact_text()?;
   let license = if license.trim().is_empty() {
@@ -660,6 +1059,35 @@ fn handle_config_add_reload(
     Some(license.trim().to_string())
   };
 
+  let copyright_holder = if license.is_some() {
+    let holder: String = Input::with_theme(&theme)
+      .with_prompt(
+        "Copyright holder for the license text (Optional, defaults to the crate's first author)",
+      )
+      .allow_empty(true)
+      .interact_text()?;
+    Some(holder.trim().to_string()).filter(|h| !h.is_empty())
+  } else {
+    None
+  };
+
+  let fallback_identity: String = Input::with_theme(&theme)
+    .with_prompt("Public identity for internal authors (Optional, e.g. 'Team <oss@company.com>')")
+    .allow_empty(true)
+    .validate_with(|input: &String| -> Result<(), String> {
+      if input.trim().is_empty() {
+        return Ok(());
+      }
+      Identity::parse(input)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    })
+    .interact_text()?;
+  let identity_map = IdentityMapConfig {
+    fallback: Some(fallback_identity.trim().to_string()).filter(|f| !f.is_empty()),
+    ..IdentityMapConfig::default()
+  };
+
   // --- Construct and Confirm ---
   let new_project = ProjectConfig {
     internal_repo_path,
@@ -667,9 +1095,20 @@ fn handle_config_add_reload(
     output_path,
     public_repo_url,
     history_mode,
+    history_rewriter,
     internal_branch, // Add new fields
-    public_branch,   // Add new fields
+    merge_strategy: MergeStrategy::default(),
+    public_branch, // Add new fields
     license,
+    copyright_holder,
+    include: Vec::new(), // Path filters are edited in the config file directly
+    exclude: Vec::new(),
+    scrub: ScrubConfig::default(),
+    license_header: LicenseHeaderConfig::default(),
+    identity_map,
+    message_rules: Vec::new(),
+    selection_rules: Vec::new(),
+    dependency_rewrites: HashMap::new(),
   };
 
   println!("\n--- New project configuration ---");
@@ -749,21 +1188,310 @@ fn handle_config_remove_reload(
   Ok(())
 }
 
+/// Opens `patch` (as a `format-patch` mailbox) in `$VISUAL`/`$EDITOR` until it parses again.
+/// Returns `None` if the reviewer gives up on an invalid edit.
+fn edit_patch(patch: &CommitPatch) -> Result<Option<CommitPatch>, Box<dyn std::error::Error>> {
+  let editor = env::var("VISUAL")
+    .or_else(|_| env::var("EDITOR"))
+    .unwrap_or_else(|_| "vi".to_string());
+  let mut words = editor.split_whitespace();
+  let program = words.next().ok_or("$EDITOR is empty")?;
+  let editor_args: Vec<&str> = words.collect();
+  // Private, unpredictable path; removed when `file` is dropped
+  let file = tempfile::Builder::new()
+    .prefix("oss-porter-")
+    .suffix(".patch")
+    .tempfile()?;
+  let path = file.path();
+
+  let mut text = patch.to_mbox();
+  loop {
+    fs::write(path, &text)?;
+    if !Command::new(program)
+      .args(&editor_args)
+      .arg(path)
+      .status()?
+      .success()
+    {
+      return Err(format!("Editor '{}' failed", editor).into());
+    }
+    text = fs::read_to_string(path)?;
+    match parse_edited_mbox(patch, &text) {
+      Ok(edited) => return Ok(Some(edited)),
+      Err(e) => {
+        eprintln!("{}", e);
+        if !Confirm::with_theme(&ColorfulTheme::default())
+          .with_prompt("Edit the patch again?")
+          .default(true)
+          .interact()?
+        {
+          return Ok(None);
+        }
+      }
+    }
+  }
+}
+
+/// Lets the reviewer pick the files and hunks of `patch` to publish (all preselected).
+/// Returns `None` if nothing was selected.
+fn select_patch_parts(
+  patch: &CommitPatch,
+) -> Result<Option<PatchSelection>, Box<dyn std::error::Error>> {
+  // One item per hunk; files without hunks (binary, mode or empty changes) are one item
+  let mut parts = Vec::new();
+  let mut labels = Vec::new();
+  for (file_index, file) in patch.files.iter().enumerate() {
+    if file.hunks.is_empty() {
+      parts.push((file_index, None));
+      labels.push(format!("{} (whole file)", file.path()));
+    } else if file.old_path.is_none() {
+      parts.push((file_index, None));
+      labels.push(format!(
+        "{} (new file, {} lines)",
+        file.path(),
+        file.hunks[0].new_lines
+      ));
+    }
+    for (hunk_index, hunk) in file
+      .hunks
+      .iter()
+      .enumerate()
+      .filter(|_| file.old_path.is_some())
+    {
+      let added = hunk
+        .lines
+        .iter()
+        .filter(|line| line.starts_with('+'))
+        .count();
+      let removed = hunk
+        .lines
+        .iter()
+        .filter(|line| line.starts_with('-'))
+        .count();
+      parts.push((file_index, Some(hunk_index)));
+      labels.push(format!(
+        "{} {} (+{} -{})",
+        file.path(),
+        hunk.header(),
+        added,
+        removed
+      ));
+    }
+  }
+  let defaults = vec![true; parts.len()];
+  let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+    .with_prompt("Select the changes to publish (space toggles, enter confirms)")
+    .items(&labels)
+    .defaults(&defaults)
+    .interact()?;
+
+  let mut selection = PatchSelection::default();
+  for index in chosen {
+    match parts[index] {
+      (file_index, None) => selection.keep_file(file_index),
+      (file_index, Some(hunk_index)) => selection.keep_hunk(file_index, hunk_index),
+    }
+  }
+  Ok(Some(selection).filter(|selection| !selection.is_empty()))
+}
+
+/// Flags files a commit moves across the project boundary: moved in, they may pull internal
+/// code into the public repo; moved out, the public repo only sees a deletion.
+fn print_boundary_moves(moves: &[BoundaryMove]) {
+  if moves.is_empty() {
+    return;
+  }
+  println!("[!] This commit moves files across the project boundary:");
+  for boundary_move in moves {
+    let hint = if boundary_move.into_project {
+      "published in full, check for internal code"
+    } else {
+      "published as a deletion"
+    };
+    println!("[!]   {} ({})", boundary_move, hint);
+  }
+}
+
+/// Prints a diff with added/removed lines colored (ANSI).
+fn print_diff(diff: &str) {
+  for line in diff.lines() {
+    if line.starts_with("+++") || line.starts_with("---") || line.starts_with("diff --git") {
+      println!("\x1b[1m{}\x1b[0m", line);
+    } else if line.starts_with('+') {
+      println!("\x1b[32m{}\x1b[0m", line);
+    } else if line.starts_with('-') {
+      println!("\x1b[31m{}\x1b[0m", line);
+    } else if line.starts_with("@@") {
+      println!("\x1b[36m{}\x1b[0m", line);
+    } else {
+      println!("{}", line);
+    }
+  }
+}
+
 fn handle_update(
   project_id: String,
+  continue_session: bool,
+  abort: bool,
+  squash: bool,
   config_file: &ConfigFile,
-  config_path_override: Option<&Path>, // Needed for state commit prompt potentially
+  config_path_override: Option<&Path>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-  println!("\nStarting interactive update for project: {}", project_id);
+  let mut project_config = config_file
+    .projects
+    .get(&project_id)
+    .ok_or_else(|| format!("Project '{}' not found in configuration.", project_id))?;
 
+  if abort {
+    return handle_update_abort(&project_id, project_config);
+  }
+  let relocated_config;
+  if !continue_session {
+    if let Some(relocation) = find_subdir_relocation(project_config)? {
+      relocated_config = follow_relocation(
+        &project_id,
+        project_config,
+        &relocation,
+        config_file,
+        config_path_override,
+      )?;
+      project_config = &relocated_config;
+    }
+  }
+  if squash {
+    return handle_squash_update(&project_id, project_config);
+  }
+  let session = if continue_session {
+    resume_update_session(&project_id, project_config)?
+  } else {
+    if load_update_session(project_config)?.is_some() {
+      return Err(format!(
+        "An update session for '{}' is already in progress. Resume it with 'oss-porter update {} --continue' or roll it back with 'oss-porter update {} --abort'.",
+        project_id, project_id, project_id
+      )
+      .into());
+    }
+    match start_update_session(&project_id, project_config)? {
+      Some(session) => session,
+      None => return Ok(()), // Nothing to review
+    }
+  };
+  review_update_session(&project_id, project_config, session)
+}
+
+/// Offers to follow a move of the project subdir inside the internal repo: on confirmation the
+/// move is recorded in the sync state and `project_subdir` is updated in the configuration file.
+/// The public repo is not touched.
+fn follow_relocation(
+  project_id: &str,
+  project_config: &ProjectConfig,
+  relocation: &SubdirRelocation,
+  config_file: &ConfigFile,
+  config_path_override: Option<&Path>,
+) -> Result<ProjectConfig, Box<dyn std::error::Error>> {
+  println!(
+    "\nThe project subdir '{}' was moved to '{}' in the internal repo by commit {} ({}).",
+    relocation.from.display(),
+    relocation.to.display(),
+    relocation.commit,
+    relocation.subject
+  );
+  if !relocation.changed.is_empty() {
+    println!("[!] The move commit also changed these files; those changes are NOT published:");
+    for path in &relocation.changed {
+      println!("[!]   {}", path);
+    }
+    println!(
+      "[!] Review them with: git -C {} diff {}^:{} {}:{}",
+      project_config.internal_repo_path.display(),
+      relocation.commit,
+      relocation.from.display(),
+      relocation.commit,
+      relocation.to.display()
+    );
+  }
+  if !Confirm::with_theme(&ColorfulTheme::default())
+    .with_prompt(format!(
+      "Set project_subdir of '{}' to '{}' and continue syncing from there?",
+      project_id,
+      relocation.to.display()
+    ))
+    .default(true)
+    .interact()?
+  {
+    return Err(
+      format!(
+        "'{}' no longer exists on '{}'; update project_subdir to continue.",
+        relocation.from.display(),
+        project_config.internal_branch
+      )
+      .into(),
+    );
+  }
+
+  let relocated = follow_subdir_relocation(project_config, relocation)?;
+  let mut updated_config_file = config_file.clone();
+  updated_config_file
+    .projects
+    .insert(project_id.to_string(), relocated.clone());
+  save_config(&updated_config_file, config_path_override)?;
+  println!(
+    "Configuration updated: project_subdir = \"{}\". Commit {} is recorded as the move.",
+    relocated.project_subdir.display(),
+    relocation.commit
+  );
+  Ok(relocated)
+}
+
+/// Runs a non-interactive update driven by a policy file and emits its JSON summary.
+/// Fails (non-zero exit) unless the run's status is `ok`.
+fn handle_policy_update(
+  project_id: String,
+  policy_path: &Path,
+  summary_path: Option<&Path>,
+  commit_state: bool,
+  config_file: &ConfigFile,
+) -> Result<(), Box<dyn std::error::Error>> {
   let project_config = config_file
     .projects
     .get(&project_id)
     .ok_or_else(|| format!("Project '{}' not found in configuration.", project_id))?;
+  let policy = UpdatePolicy::load(policy_path)?;
+
+  let summary = run_policy_update(&project_id, project_config, &policy)?;
+  let json = serde_json::to_string_pretty(&summary)?;
+  match summary_path {
+    Some(path) => {
+      fs::write(path, format!("{}\n", json))?;
+      eprintln!("Update summary written to {}", path.display());
+    }
+    None => println!("{}", json),
+  }
+
+  if commit_state {
+    commit_state_file_change(project_config, summary.synced_commit.as_deref())?;
+  }
+
+  if summary.status != UpdateStatus::Ok {
+    let status = serde_json::to_value(summary.status)?;
+    return Err(format!("Non-interactive update finished with status {}.", status).into());
+  }
+  Ok(())
+}
+
+/// Finds the commits to review and saves a new update session for them.
+/// Returns `None` if the project is up-to-date.
+fn start_update_session(
+  project_id: &str,
+  project_config: &ProjectConfig,
+) -> Result<Option<UpdateSession>, Box<dyn std::error::Error>> {
+  println!("\nStarting interactive update for project: {}", project_id);
 
   // --- 1. Get Last Synced State ---
   let last_synced_ref = match read_last_synced_commit(project_config)? {
-    Some(commit) => commit,
+    Some(commit) => Some(commit),
+    // An unskipped root commit clears the cursor; the history shows a sync happened.
+    None if !read_sync_history(project_config)?.is_empty() => None,
     None => {
       eprintln!(
         "Error: No previous sync state found for project '{}' in the internal repository.",
@@ -772,106 +1500,585 @@ fn handle_update(
       eprintln!("       Please ensure '{}' exists within '{}' and contains the hash of the last commit synced.",
                      STATE_FILE_NAME, project_config.internal_repo_path.join(&project_config.project_subdir).display());
       eprintln!(
-        "       If this is the first sync after an initial extract, manually create the state file"
+        "       'extract' records it automatically; for projects extracted by hand, create the state file"
       );
       eprintln!("       with the initial commit hash from the internal repo that corresponds to the extract point.");
       // Alternatively, could prompt user for the initial hash here.
       return Err("Missing initial sync state.".into()); // Use Box<dyn Error> for simple errors
     }
   };
-  println!("Last synced internal commit: {}", last_synced_ref);
+  match &last_synced_ref {
+    Some(commit) => println!("Last synced internal commit: {}", commit),
+    None => println!("The sync cursor is before the first commit; reviewing from the start."),
+  }
+  let previously_pending = read_pending_commits(project_config)?;
+  if !previously_pending.is_empty() {
+    println!(
+      "{} commit(s) were left pending by the previous update and will be offered again.",
+      previously_pending.len()
+    );
+  }
 
   // --- 2. Identify New Commits ---
-  let mut commits_to_review = get_internal_commits_since(project_config, Some(&last_synced_ref))?;
+  let commits_to_review = get_internal_commits_since(project_config, last_synced_ref.as_deref())?;
 
   if commits_to_review.is_empty() {
     println!(
       "Project is up-to-date. No new commits found since {}.",
-      last_synced_ref
+      last_synced_ref.as_deref().unwrap_or("the start")
     );
-    return Ok(());
+    return Ok(None);
   }
   println!(
     "Found {} new candidate commits to review.",
     commits_to_review.len()
   );
 
+  let session = UpdateSession::start(
+    project_config,
+    last_synced_ref.as_deref(),
+    commits_to_review,
+  )?;
+  session.save(project_config)?;
+  Ok(Some(session))
+}
+
+/// Resumes the saved update session after a conflict: finishes the stopped `git am`
+/// (the user has resolved and staged the conflicts) and records the resolved commit.
+fn resume_update_session(
+  project_id: &str,
+  project_config: &ProjectConfig,
+) -> Result<UpdateSession, Box<dyn std::error::Error>> {
+  let mut session = load_update_session(project_config)?.ok_or_else(|| {
+    format!(
+      "No update session in progress for project '{}'.",
+      project_id
+    )
+  })?;
+  println!("\nResuming update session for project: {}", project_id);
+
+  if let Some(conflicted) = session.current.take() {
+    let commit_hash = conflicted.commit.hash.clone();
+    let result = if apply_in_progress(project_config)? {
+      continue_apply_session(project_config)?
+    } else {
+      // `git am` was finished (or skipped) by hand
+      let head = output_head(project_config)?;
+      if head != conflicted.output_head {
+        ApplyResult::Success(head)
+      } else {
+        println!(
+          "Commit {} was not applied in '{}'; it will be offered again.",
+          commit_hash,
+          project_config.output_path.display()
+        );
+        session.queue.push_front(conflicted.commit.clone());
+        session.save(project_config)?;
+        return Ok(session);
+      }
+    };
+    match result {
+      ApplyResult::Success(public_commit) => {
+        println!(
+          "Applied commit {} as {}.",
+          commit_hash,
+          public_commit.as_deref().unwrap_or("<no commit>")
+        );
+        let record = conflicted.record(public_commit.as_deref());
+        record_session_decision(project_config, &mut session, record)?;
+      }
+      ApplyResult::Conflict | ApplyResult::Failure(_) => {
+        if let ApplyResult::Failure(stderr) = &result {
+          eprintln!("{}", stderr);
+        }
+        eprintln!(
+          "Error: 'git am --continue' failed for commit {}. Resolve the remaining conflicts and stage them in:",
+          commit_hash
+        );
+        eprintln!("  cd {}", project_config.output_path.display());
+        session.current = Some(conflicted);
+        session.save(project_config)?;
+        return Err("Conflicts are not resolved yet.".into());
+      }
+    }
+  }
+  session.save(project_config)?;
+  Ok(session)
+}
+
+/// Publishes all new commits as one public commit (`update --squash`): commits skipped by a
+/// selection rule are left out, the rest are combined into a single patch that is shown for
+/// confirmation and applied; every absorbed commit is recorded as `squashed`.
+fn handle_squash_update(
+  project_id: &str,
+  project_config: &ProjectConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+  if load_update_session(project_config)?.is_some() || apply_in_progress(project_config)? {
+    return Err(format!(
+      "An update for '{}' is already in progress; finish it with 'update --continue' or '--abort' first.",
+      project_id
+    )
+    .into());
+  }
+  println!("\nStarting squashed update for project: {}", project_id);
+  let last_synced_ref = read_last_synced_commit(project_config)?;
+  if last_synced_ref.is_none() && read_sync_history(project_config)?.is_empty() {
+    return Err(
+      format!(
+        "No previous sync state found for project '{}' in '{}'.",
+        project_id,
+        get_internal_state_file_path(project_config).display()
+      )
+      .into(),
+    );
+  }
+  let since = last_synced_ref.as_deref().unwrap_or("<none>");
+  println!("Last synced internal commit: {}", since);
+
+  let selection_rules = SelectionRules::from_config(project_config)?;
+  let mut selected = Vec::new();
+  for commit in get_internal_commits_since(project_config, last_synced_ref.as_deref())? {
+    let rule = selection_rules
+      .as_ref()
+      .and_then(|rules| rules.matching_rule(&commit));
+    if let Some(rule) = rule.filter(|rule| rule.action == SelectionAction::Skip) {
+      println!(
+        "Skipping commit {} permanently (selection rule {}).",
+        commit.hash, rule
+      );
+      let reason = format!("selection rule: {}", rule);
+      skip_commit(project_config, &commit.hash, Some(&reason))?;
+      record_sync_decision(
+        project_config,
+        SyncRecord::now(&commit.hash, None, SyncDecision::Skipped),
+      )?;
+    } else {
+      selected.push(commit);
+    }
+  }
+  if selected.is_empty() {
+    println!(
+      "Project is up-to-date. No new commits to squash since {}.",
+      since
+    );
+    return Ok(());
+  }
+
+  let squashed = build_squashed_patch(project_config, &selected)?;
+  if !squashed.already_applied.is_empty() {
+    println!("Commits whose change is already present at the output HEAD:");
+    for (internal_commit, public_commit) in &squashed.already_applied {
+      println!(
+        " - {} (as public commit {})",
+        internal_commit, public_commit
+      );
+    }
+    if !Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt("Leave them out and record them as already applied?")
+      .default(true)
+      .interact()?
+    {
+      println!(
+        "Squashed update cancelled; run 'update' without --squash to review them one by one."
+      );
+      return Ok(());
+    }
+  }
+  let patch = &squashed.patch;
+  if !patch.is_empty() {
+    println!("\nCommits combined into one public commit:");
+    for commit in selected
+      .iter()
+      .filter(|commit| squashed.absorbed.contains(&commit.hash))
+    {
+      println!(" - {} {}", commit.hash, commit.subject);
+    }
+    println!("\nPublished message:");
+    for line in patch.message.lines() {
+      println!("    {}", line);
+    }
+    print_diff(&patch.diff_text());
+    if !patch.stripped_regions.is_empty() {
+      println!("Stripped internal-only regions (not part of the patch):");
+      for region in &patch.stripped_regions {
+        println!("  - {}", region);
+      }
+    }
+    for note in &patch.transform_notes {
+      println!("Note: {}", note);
+    }
+    print_boundary_moves(&patch.boundary_moves);
+    if !Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt(format!(
+        "Apply these {} commit(s) as one commit to '{}'?",
+        squashed.absorbed.len(),
+        project_config.output_path.display()
+      ))
+      .default(true)
+      .interact()?
+    {
+      println!("Squashed update cancelled; nothing was applied.");
+      return Ok(());
+    }
+  }
+
+  let public_commit = match apply_patch_to_output(project_config, patch)? {
+    ApplyResult::Success(public_commit) => public_commit,
+    ApplyResult::Conflict => {
+      abort_apply_session(project_config)?;
+      return Err(
+        "The combined patch conflicts with the public repo; nothing was applied. Run 'update' without --squash to resolve the commits one by one."
+          .into(),
+      );
+    }
+    ApplyResult::Failure(stderr) => {
+      return Err(format!("Failed to apply the combined patch: {}", stderr.trim()).into())
+    }
+  };
+  for (internal_commit, public_commit) in &squashed.already_applied {
+    let record = SyncRecord::now(
+      internal_commit,
+      Some(public_commit),
+      SyncDecision::AlreadyApplied,
+    );
+    record_sync_decision(project_config, record)?;
+  }
+  for internal_commit in &squashed.absorbed {
+    let record = SyncRecord::now(
+      internal_commit,
+      public_commit.as_deref(),
+      SyncDecision::Squashed,
+    );
+    record_sync_decision(project_config, record)?;
+  }
+  match &public_commit {
+    Some(public_commit) => println!(
+      "Published {} internal commit(s) as {}.",
+      squashed.absorbed.len(),
+      public_commit
+    ),
+    None => println!("Nothing publishable in the new commits; recorded them as synced."),
+  }
+
+  let sync_cursor = advance_sync_cursor(project_config, last_synced_ref.as_deref())?;
+  let final_synced_commit = sync_cursor.cursor.as_deref();
+  println!(
+    "Last synced internal commit is now: {}",
+    final_synced_commit.unwrap_or("<none>")
+  );
+  write_last_synced_commit(project_config, final_synced_commit)?;
+  write_pending_commits(project_config, &sync_cursor.pending)?;
+  prompt_commit_state(project_config, final_synced_commit)?;
+  Ok(())
+}
+
+/// Offers to commit the updated state file to the internal repository.
+fn prompt_commit_state(
+  project_config: &ProjectConfig,
+  synced_commit: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  if Confirm::with_theme(&ColorfulTheme::default())
+    .with_prompt(format!(
+      "Commit this sync state update ({}) to the internal repository '{}'?",
+      synced_commit.unwrap_or("<none>"),
+      project_config.internal_repo_path.display()
+    ))
+    .interact()?
+  {
+    match commit_state_file_change(project_config, synced_commit) {
+      Ok(()) => println!("State file committed successfully."),
+      Err(e) => eprintln!("Error committing state file to internal repo: {}", e), // Don't fail entire command for this
+    }
+  } else {
+    println!("Skipped committing state file update to internal repository.");
+    println!(
+      "Reminder: Commit the change in '{}' manually.",
+      get_internal_state_file_path(project_config).display()
+    );
+  }
+  Ok(())
+}
+
+/// Rolls back an interrupted update session after confirmation.
+fn handle_update_abort(
+  project_id: &str,
+  project_config: &ProjectConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let Some(session) = load_update_session(project_config)? else {
+    if apply_in_progress(project_config)? {
+      abort_apply_session(project_config)?;
+      println!(
+        "Aborted the 'git am' session in '{}'.",
+        project_config.output_path.display()
+      );
+    } else {
+      println!(
+        "No update session in progress for project '{}'.",
+        project_id
+      );
+    }
+    return Ok(());
+  };
+
+  let applied = session
+    .decisions
+    .iter()
+    .filter(|record| record.public_commit.is_some())
+    .count();
+  println!(
+    "Rolling back the update session started at internal commit {}:",
+    session.since_commit.as_deref().unwrap_or("<none>")
+  );
+  if let Some(conflicted) = &session.current {
+    println!(
+      " - abort the pending 'git am' of {}",
+      conflicted.commit.hash
+    );
+  }
+  match &session.output_head {
+    Some(head) => println!(
+      " - reset '{}' to {}, removing the {} commit(s) applied in this session",
+      project_config.output_path.display(),
+      head,
+      applied
+    ),
+    None => println!(
+      " - leave '{}' alone (it had no commits when the session started)",
+      project_config.output_path.display()
+    ),
+  }
+  println!(
+    " - restore '{}' and forget the {} decision(s) taken in this session",
+    get_internal_state_file_path(project_config).display(),
+    session.decisions.len()
+  );
+  if !Confirm::with_theme(&ColorfulTheme::default())
+    .with_prompt("Abort the update session?")
+    .default(false)
+    .interact()?
+  {
+    println!("Update session left in place.");
+    return Ok(());
+  }
+  abort_update_session(project_config, &session)?;
+  println!("Update session aborted.");
+  Ok(())
+}
+
+/// Records a decision in the sync history and in the session, and saves the session.
+fn record_session_decision(
+  project_config: &ProjectConfig,
+  session: &mut UpdateSession,
+  record: SyncRecord,
+) -> Result<(), Box<dyn std::error::Error>> {
+  record_sync_decision(project_config, record.clone())?;
+  session.decisions.push(record);
+  session.save(project_config)?;
+  Ok(())
+}
+
+/// Runs the interactive review over the session's queue, then records the sync cursor.
+/// A conflict leaves the session saved for `update --continue` / `--abort`.
+fn review_update_session(
+  project_id: &str,
+  project_config: &ProjectConfig,
+  mut session: UpdateSession,
+) -> Result<(), Box<dyn std::error::Error>> {
   // --- 3. Interactive Review Loop ---
-  let mut successfully_applied_commit: Option<String> = Some(last_synced_ref.clone()); // Track last successful apply
-  let mut apply_all_mode = false;
   let mut user_quit = false;
   let mut skipped_commits: Vec<CommitInfo> = Vec::new(); // Track explicitly skipped ('n')
+  let selection_rules = SelectionRules::from_config(project_config)?;
+  let public_patch_ids = PublicPatchIds::load(project_config)?;
 
-  while let Some(commit_info) = commits_to_review.pop_front() {
+  while let Some(commit_info) = session.queue.pop_front() {
     // Process oldest first
     let current_commit_hash = commit_info.hash.clone();
     println!("\n--- Reviewing Commit: {} ---", current_commit_hash);
     println!("Subject: {}", commit_info.subject);
 
-    let choice: usize;
+    // Trailers/tags in the internal message may decide without asking
+    let rule = selection_rules
+      .as_ref()
+      .and_then(|rules| rules.matching_rule(&commit_info));
+    let action = rule.map_or(SelectionAction::Review, |rule| rule.action);
+    if let (Some(rule), SelectionAction::Skip) = (rule, action) {
+      println!(
+        "Skipping commit {} permanently (selection rule {}).",
+        current_commit_hash, rule
+      );
+      let reason = format!("selection rule: {}", rule);
+      skip_commit(project_config, &current_commit_hash, Some(&reason))?;
+      let record = SyncRecord::now(&current_commit_hash, None, SyncDecision::Skipped);
+      record_session_decision(project_config, &mut session, record)?;
+      skipped_commits.push(commit_info);
+      continue;
+    }
 
-    if !apply_all_mode {
-      // Show Diff
-      match get_commit_diff_relative(project_config, &current_commit_hash) {
-        Ok(diff) => {
-          // Simple print, consider paging or better display for large diffs
-          println!("{}", diff);
-          // Check if diff is empty - might indicate changes outside subdir pathspec logic?
-          if diff.trim().is_empty() {
-            warn!("Commit {} produced an empty diff relative to '{}'. Check pathspec logic or commit content.",
-                                  current_commit_hash, project_config.project_subdir.display());
-          }
-        }
-        Err(e) => {
-          eprintln!(
-            "Error getting diff for commit {}: {}",
-            current_commit_hash, e
-          );
-          // Offer to skip or quit?
-          if Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("Failed to get diff. Skip this commit?")
+    // Build the patch exactly as it would be published (filtered and scrubbed)
+    let patch = match build_commit_patch(project_config, &current_commit_hash) {
+      Ok(patch) => patch,
+      Err(e) => {
+        eprintln!(
+          "Error preparing patch for commit {}: {}",
+          current_commit_hash, e
+        );
+        // Offer to skip or quit?
+        if !session.apply_all
+          && Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Failed to prepare patch. Skip this commit?")
             .interact()?
-          {
-            skipped_commits.push(commit_info); // Treat as skipped ('n')
-            continue;
-          } else {
-            user_quit = true;
-            break;
-          }
+        {
+          let reason = format!("failed to prepare patch: {}", e);
+          skip_commit(project_config, &current_commit_hash, Some(&reason))?;
+          let record = SyncRecord::now(&current_commit_hash, None, SyncDecision::Skipped);
+          record_session_decision(project_config, &mut session, record)?;
+          skipped_commits.push(commit_info); // Treat as skipped ('n')
+          continue;
+        } else {
+          user_quit = true;
+          break;
         }
       }
+    };
+
+    // The same change may already be public (cherry-picked by hand, interrupted update)
+    if let Some(public_commit) = public_patch_ids.find(project_config, &patch)? {
+      println!(
+        "Commit {} has the same change as public commit {}, which is present at the output HEAD.",
+        current_commit_hash, public_commit
+      );
+      if Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Record it as already applied and skip it?")
+        .default(true)
+        .interact()?
+      {
+        let record = SyncRecord::now(
+          &current_commit_hash,
+          Some(public_commit),
+          SyncDecision::AlreadyApplied,
+        );
+        record_session_decision(project_config, &mut session, record)?;
+        continue;
+      }
+    }
+
+    let choice = if !session.apply_all && action == SelectionAction::Review {
+      if patch.original_message.is_some() {
+        println!("Published message (rewritten by message rules):");
+        for line in patch.message.lines() {
+          println!("    {}", line);
+        }
+      }
+      // Show Diff
+      // Simple print, consider paging or better display for large diffs
+      let diff = patch.diff_text();
+      print_diff(&diff);
+      // Check if diff is empty - might indicate changes outside subdir pathspec logic?
+      if diff.trim().is_empty() {
+        warn!("Commit {} produced an empty diff relative to '{}'. Check pathspec logic or commit content.",
+                              current_commit_hash, project_config.project_subdir.display());
+      }
+      if !patch.stripped_regions.is_empty() {
+        println!("Stripped internal-only regions (not part of the patch):");
+        for region in &patch.stripped_regions {
+          println!("  - {}", region);
+        }
+      }
+      for note in &patch.transform_notes {
+        println!("Note: {}", note);
+      }
+      print_boundary_moves(&patch.boundary_moves);
 
       // Prompt User
-      choice = Select::with_theme(&ColorfulTheme::default())
+      Select::with_theme(&ColorfulTheme::default())
         .with_prompt(format!(
           "Apply commit {} to '{}'?",
           current_commit_hash,
           project_config.output_path.display()
         ))
         .items(&[
-          "Yes",                 // 0
-          "No (skip always)",    // 1
-          "Skip for now",        // 2
-          "Apply ALL remaining", // 3
-          "Quit update",         // 4
+          "Yes",                             // 0
+          "Apply only selected files/hunks", // 1
+          "Edit patch/message in $EDITOR",   // 2
+          "No (skip always)",                // 3
+          "Skip for now",                    // 4
+          "Apply ALL remaining",             // 5
+          "Quit update",                     // 6
         ])
         .default(0)
-        .interact()?;
+        .interact()?
     } else {
-      // In Apply All mode, implicitly choose Yes
-      println!("Applying non-interactively (Apply All mode)...");
-      choice = 0; // Simulate "Yes"
-    }
+      // In Apply All mode or for commits marked by a selection rule, implicitly choose Yes
+      print_boundary_moves(&patch.boundary_moves);
+      match rule {
+        Some(rule) if action == SelectionAction::Apply => {
+          println!("Applying without review (selection rule {})...", rule)
+        }
+        _ => println!("Applying non-interactively (Apply All mode)..."),
+      }
+      0 // Simulate "Yes"
+    };
 
     match choice {
-      // --- Yes ---
-      0 => {
-        match apply_commit_to_output(project_config, &current_commit_hash)? {
-          // Calls the new patch-based function
-          ApplyResult::Success => {
-            successfully_applied_commit = Some(current_commit_hash.to_string()); // Update latest success
+      // --- Yes / Apply only selected files/hunks / Edit ---
+      0..=2 => {
+        let (patch, record) = if choice == 2 {
+          let Some(edited) = edit_patch(&patch)? else {
+            println!(
+              "Edit cancelled; commit {} is offered again.",
+              current_commit_hash
+            );
+            session.queue.push_front(commit_info);
+            continue;
+          };
+          // An unchanged patch is still a verbatim sync
+          let decision = if edited.to_mbox() == patch.to_mbox() {
+            SyncDecision::Applied
+          } else {
+            SyncDecision::Edited
+          };
+          (
+            edited,
+            SyncRecord::now(&current_commit_hash, None, decision),
+          )
+        } else if choice == 1 {
+          let Some(selection) = select_patch_parts(&patch)? else {
+            println!(
+              "Nothing selected; commit {} is offered again.",
+              current_commit_hash
+            );
+            session.queue.push_front(commit_info);
+            continue;
+          };
+          let (selected, omitted) = patch.filtered(&selection);
+          if !omitted.is_empty() {
+            println!("Left out of the published patch:");
+            for part in &omitted {
+              println!("  - {}", part);
+            }
+          }
+          let decision = if omitted.is_empty() {
+            SyncDecision::Applied
+          } else {
+            SyncDecision::Partial
+          };
+          let record = SyncRecord::now(&current_commit_hash, None, decision).with_omitted(omitted);
+          (selected, record)
+        } else {
+          let record = SyncRecord::now(&current_commit_hash, None, SyncDecision::Applied);
+          (patch, record)
+        };
+        match apply_patch_to_output(project_config, &patch)? {
+          ApplyResult::Success(public_commit) => {
+            let record = SyncRecord {
+              public_commit,
+              ..record
+            };
+            record_session_decision(project_config, &mut session, record)?;
           }
           ApplyResult::Conflict => {
+            session.mark_conflict(project_config, commit_info, &record)?;
+            session.save(project_config)?;
             eprintln!(
               "\nError: Patch application conflict detected for commit {}.",
               current_commit_hash
@@ -881,14 +2088,16 @@ fn handle_update(
             eprintln!(
               "  # (Review conflicts with 'git status', 'git diff', edit files, 'git add .')"
             );
-            eprintln!("  git am --continue"); // Updated instruction
             eprintln!(
-              "Once resolved, re-run 'oss-porter update {}' to process remaining commits.",
+              "Once resolved, run 'oss-porter update {} --continue' to finish this commit and review the {} remaining commit(s).",
+              project_id,
+              session.queue.len()
+            );
+            eprintln!(
+              "To roll back the whole update session instead: oss-porter update {} --abort",
               project_id
             );
-            eprintln!("To abort the conflicting patch application: git am --abort"); // Updated instruction
-            user_quit = true; // Force quit after conflict
-            break; // Exit review loop
+            return Ok(()); // The session stays open; the sync cursor is recorded when it ends
           }
           ApplyResult::Failure(stderr) => {
             eprintln!(
@@ -903,53 +2112,49 @@ fn handle_update(
         }
       }
       // --- No (skip always) ---
-      1 => {
+      3 => {
+        let reason: String = Input::with_theme(&ColorfulTheme::default())
+          .with_prompt("Reason for skipping (optional)")
+          .allow_empty(true)
+          .interact_text()?;
+        skip_commit(project_config, &current_commit_hash, Some(&reason))?;
         println!(
-          "Skipping commit {} permanently for this session.",
+          "Skipping commit {} permanently; it won't be offered again (see 'oss-porter skipped').",
           current_commit_hash
         );
+        let record = SyncRecord::now(&current_commit_hash, None, SyncDecision::Skipped);
+        record_session_decision(project_config, &mut session, record)?;
         skipped_commits.push(commit_info);
-        // Do NOT update successfully_applied_commit beyond the previous one
       }
       // --- Skip for now ---
-      2 => {
+      4 => {
         println!("Skipping commit {} for now.", current_commit_hash);
-        commits_to_review.push_back(commit_info); // Put it at the end of the queue
-                                                  // Do NOT update successfully_applied_commit
+        session.queue.push_back(commit_info); // Put it at the end of the queue
+        session.save(project_config)?;
       }
       // --- Apply ALL remaining ---
-      3 => {
+      5 => {
         println!("Entering non-interactive 'Apply All' mode...");
-        apply_all_mode = true;
+        session.apply_all = true;
         // Re-add the current commit to the front to apply it first in 'All' mode
-        commits_to_review.push_front(commit_info);
+        session.queue.push_front(commit_info);
+        session.save(project_config)?;
       }
       // --- Quit update ---
-      4 => {
+      6 => {
         println!("Quitting update process as requested.");
         user_quit = true;
         break; // Exit review loop
       }
       _ => unreachable!(),
     }
-
-    // If we hit a conflict or failure in 'Apply All' mode, break immediately
-    if apply_all_mode
-      && choice == 0
-      && successfully_applied_commit != Some(current_commit_hash.to_string())
-    {
-      // Check if the last apply action wasn't successful (conflict or failure occurred)
-      println!("Stopping 'Apply All' mode due to conflict or failure.");
-      user_quit = true; // Treat as quit to save state correctly
-      break;
-    }
   } // End while loop
 
   // --- 4. Completion ---
   println!("\n---------------------------------");
   if user_quit {
     println!("Update process exited or was aborted.");
-  } else if apply_all_mode {
+  } else if session.apply_all {
     println!("Update process finished (Apply All mode completed).");
     println!("[WARN] Commits were applied non-interactively. Please review changes carefully.");
   } else {
@@ -957,42 +2162,33 @@ fn handle_update(
   }
 
   if !skipped_commits.is_empty() {
-    println!("Explicitly skipped commits (will need review on next run):");
+    println!("Permanently skipped commits (list or restore them with 'oss-porter skipped'):");
     for skipped in skipped_commits {
       println!(" - {} {}", skipped.hash, skipped.subject);
     }
   }
 
-  // Save the state corresponding to the *last successfully applied* commit
-  let final_synced_commit = successfully_applied_commit.as_deref();
+  // The cursor only moves over the contiguous run of decided commits; anything skipped
+  // for now (or not reached) stays pending so it is offered again next time.
+  let sync_cursor = advance_sync_cursor(project_config, session.since_commit.as_deref())?;
+  let final_synced_commit = sync_cursor.cursor.as_deref();
   println!(
-    "Last successfully synced internal commit is now: {}",
-    final_synced_commit.unwrap_or("<none - state cleared or no commits applied>")
+    "Last synced internal commit is now: {}",
+    final_synced_commit.unwrap_or("<none>")
   );
+  if !sync_cursor.pending.is_empty() {
+    println!("Commits still pending review (offered again by the next update):");
+    for pending in &sync_cursor.pending {
+      println!(" - {}", pending);
+    }
+  }
 
-  // Write state file (non-optional, always record last success)
+  // Write state file (non-optional, always record the cursor and pending commits)
   write_last_synced_commit(project_config, final_synced_commit)?;
+  write_pending_commits(project_config, &sync_cursor.pending)?;
+  clear_update_session(project_config)?; // The session is finished
 
-  // Prompt to commit state file change
-  if Confirm::with_theme(&ColorfulTheme::default())
-    .with_prompt(format!(
-      "Commit this sync state update ({}) to the internal repository '{}'?",
-      final_synced_commit.unwrap_or("<none>"),
-      project_config.internal_repo_path.display()
-    ))
-    .interact()?
-  {
-    match commit_state_file_change(project_config, final_synced_commit) {
-      Ok(()) => println!("State file committed successfully."),
-      Err(e) => eprintln!("Error committing state file to internal repo: {}", e), // Don't fail entire command for this
-    }
-  } else {
-    println!("Skipped committing state file update to internal repository.");
-    println!(
-      "Reminder: Commit the change in '{}' manually.",
-      get_internal_state_file_path(project_config).display()
-    );
-  }
+  prompt_commit_state(project_config, final_synced_commit)?;
 
   println!("\nUpdate interaction complete.");
   if !user_quit {
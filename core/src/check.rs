@@ -1,12 +1,16 @@
 use crate::extract::scan_secrets_basic; // Reuse the secrets scan helper
+use crate::filter::PathFilter;
+use crate::header::LicenseHeaders;
+use crate::manifest::{find_inherited_keys, is_manifest};
 use crate::{CheckResult, PorterError, ProjectConfig, Result}; // Added CheckResult, ProjectConfig
 use cargo_toml::{Dependency, Manifest};
 use log::{debug, info, warn};
 use std::fs;
 use std::path::Path; // Needed for path canonicalization
+use walkdir::WalkDir;
 
 /// Checks a Cargo.toml manifest for path dependencies pointing outside the project directory.
-fn check_internal_dependencies(output_path: &Path) -> Result<Vec<String>> {
+pub(crate) fn check_internal_dependencies(output_path: &Path) -> Result<Vec<String>> {
   info!(
     "Checking for internal path dependencies in {}",
     output_path.display()
@@ -19,7 +23,13 @@ fn check_internal_dependencies(output_path: &Path) -> Result<Vec<String>> {
     return Ok(findings);
   }
 
-  let manifest = Manifest::from_path(&cargo_toml_path).map_err(|e| {
+  // Parsed without resolving workspace inheritance, which fails outside the workspace;
+  // leftover `workspace = true` keys are reported by `check_workspace_inheritance`.
+  let content = fs::read(&cargo_toml_path).map_err(|err| PorterError::Io {
+    source: err,
+    path: cargo_toml_path.clone(),
+  })?;
+  let manifest = Manifest::from_slice(&content).map_err(|e| {
     PorterError::Config(format!(
       "Failed to parse {}: {}",
       cargo_toml_path.display(),
@@ -28,7 +38,10 @@ fn check_internal_dependencies(output_path: &Path) -> Result<Vec<String>> {
   })?;
 
   // Canonicalize output path for reliable comparison
-  let canonical_output_path = fs::canonicalize(output_path).map_err(|err| PorterError::Io { source: err, path: output_path.to_path_buf() })?;
+  let canonical_output_path = fs::canonicalize(output_path).map_err(|err| PorterError::Io {
+    source: err,
+    path: output_path.to_path_buf(),
+  })?;
 
   let mut check_dep = |name: &str, dep: &Dependency, section: &str| -> Result<()> {
     if let Dependency::Detailed(details) = dep {
@@ -90,6 +103,77 @@ fn check_internal_dependencies(output_path: &Path) -> Result<Vec<String>> {
   Ok(findings)
 }
 
+/// Finds keys in any published Cargo.toml still inherited from a workspace (`workspace = true`).
+pub(crate) fn check_workspace_inheritance(
+  output_path: &Path,
+  filter: &PathFilter,
+) -> Result<Vec<String>> {
+  info!(
+    "Checking for workspace-inherited manifest keys in {}",
+    output_path.display()
+  );
+  let mut findings = Vec::new();
+  let walker = WalkDir::new(output_path)
+    .into_iter()
+    .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "target");
+  for entry in walker.filter_map(|e| e.ok()) {
+    let rel_path = entry
+      .path()
+      .strip_prefix(output_path)
+      .unwrap_or(entry.path());
+    if !entry.file_type().is_file() || !is_manifest(rel_path) || !filter.is_included(rel_path) {
+      continue;
+    }
+    let content = fs::read_to_string(entry.path()).map_err(|err| PorterError::Io {
+      source: err,
+      path: entry.path().to_path_buf(),
+    })?;
+    for key in find_inherited_keys(rel_path, &content)? {
+      let finding = format!(
+        "'{}' in {} still has 'workspace = true'; it only resolves inside the internal workspace",
+        key,
+        rel_path.display()
+      );
+      warn!("{}", finding);
+      findings.push(finding);
+    }
+  }
+  Ok(findings)
+}
+
+/// Lists published source files without the expected license header (if headers are enabled).
+fn check_license_headers(config: &ProjectConfig, filter: &PathFilter) -> Result<Vec<String>> {
+  let Some(headers) = LicenseHeaders::from_config(config)? else {
+    return Ok(Vec::new());
+  };
+  info!(
+    "Checking license headers in {}",
+    config.output_path.display()
+  );
+  let mut findings = Vec::new();
+  let walker = WalkDir::new(&config.output_path)
+    .into_iter()
+    .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "target");
+  for entry in walker.filter_map(|e| e.ok()) {
+    let rel_path = entry
+      .path()
+      .strip_prefix(&config.output_path)
+      .unwrap_or(entry.path());
+    if !entry.file_type().is_file() || !filter.is_included(rel_path) {
+      continue;
+    }
+    let Ok(content) = fs::read_to_string(entry.path()) else {
+      continue; // Binary or non-UTF-8 files have no header
+    };
+    if !headers.has_expected_header(rel_path, &content) {
+      debug!("Missing license header: {}", rel_path.display());
+      findings.push(rel_path.display().to_string());
+    }
+  }
+  findings.sort();
+  Ok(findings)
+}
+
 /// Runs various checks on the extracted project in the output directory.
 pub fn check_project(project_id: &str, config: &ProjectConfig) -> Result<CheckResult> {
   info!(
@@ -102,12 +186,18 @@ pub fn check_project(project_id: &str, config: &ProjectConfig) -> Result<CheckRe
     return Err(PorterError::PathNotFound(config.output_path.clone()));
   }
 
-  let secrets = scan_secrets_basic(&config.output_path)?;
+  let filter = PathFilter::from_config(config)?;
+  let secrets = scan_secrets_basic(&config.output_path, &filter)?;
   let internal_deps = check_internal_dependencies(&config.output_path)?;
+  let workspace_inherited = check_workspace_inheritance(&config.output_path, &filter)?;
+  let missing_headers = check_license_headers(config, &filter)?;
 
   // Check for license file existence
   let license_exists = fs::read_dir(&config.output_path)
-    .map_err(|err| PorterError::Io { source: err, path: config.output_path.to_path_buf() })?
+    .map_err(|err| PorterError::Io {
+      source: err,
+      path: config.output_path.to_path_buf(),
+    })?
     .filter_map(|entry| entry.ok())
     .any(|entry| {
       let file_name = entry.file_name().to_string_lossy().to_lowercase();
@@ -121,6 +211,8 @@ pub fn check_project(project_id: &str, config: &ProjectConfig) -> Result<CheckRe
     project_id: project_id.to_string(),
     secrets_found: secrets,
     internal_deps_found: internal_deps,
+    workspace_inherited_found: workspace_inherited,
+    missing_headers,
     license_ok: license_exists, // Simple check for now
   })
 }
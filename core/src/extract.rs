@@ -1,5 +1,19 @@
-use crate::{state::STATE_FILE_NAME, ExtractionResult, PorterError, ProjectConfig, Result};
-use fs_extra::dir::{copy as copy_dir, move_dir, CopyOptions}; // Added move_dir, Transit*
+use crate::check::{check_internal_dependencies, check_workspace_inheritance};
+use crate::filter::PathFilter;
+use crate::history::{read_commits, rewrite_history};
+use crate::identity::{history_identities, local_identity_args, IdentityMap};
+use crate::license::write_license_files;
+use crate::message::MessageRules;
+use crate::scrub::{Scrubber, StrippedRegion};
+use crate::transform::ContentTransforms;
+use crate::{
+  state::{
+    get_internal_state_file_path, read_last_synced_commit, write_last_synced_commit,
+    write_pending_commits, STATE_FILE_NAME,
+  },
+  ExtractionPlan, ExtractionResult, HistoryMode, HistoryRewriter, PorterError, ProjectConfig,
+  Result,
+};
 use log::{debug, error, info, warn};
 use regex::Regex;
 use std::{
@@ -76,8 +90,9 @@ pub(crate) fn run_git_command(args: &[&str], cwd: &Path) -> Result<Output> {
 }
 
 /// Very basic secret scanning using simple regexes.
+/// Files rejected by `filter` are not scanned, since they are never published.
 /// Returns a list of findings (file path + potential secret type).
-pub(crate) fn scan_secrets_basic(dir: &Path) -> Result<Vec<String>> {
+pub(crate) fn scan_secrets_basic(dir: &Path, filter: &PathFilter) -> Result<Vec<String>> {
   info!("Starting basic secret scan in {}", dir.display());
   let mut findings = Vec::new();
   // Example patterns (very naive - enhance later or use external tools)
@@ -96,6 +111,10 @@ pub(crate) fn scan_secrets_basic(dir: &Path) -> Result<Vec<String>> {
       {
         continue;
       }
+      let rel_path = path.strip_prefix(dir).unwrap_or(path);
+      if !filter.is_included(rel_path) {
+        continue;
+      }
       if let Ok(content) = fs::read_to_string(path) {
         for (i, line) in content.lines().enumerate() {
           for pattern in &patterns {
@@ -117,27 +136,20 @@ pub(crate) fn scan_secrets_basic(dir: &Path) -> Result<Vec<String>> {
   Ok(findings)
 }
 
-/// Adds a license file if specified and doesn't exist.
-fn add_license_file(license_id: Option<&str>, output_path: &Path) -> Result<()> {
-  if let Some(id) = license_id {
-    // Very basic check - assumes license name matches file name (e.g., "MIT" -> "LICENSE-MIT")
-    // A better approach would use SPDX IDs and fetch/generate license text.
-    let license_file_name = format!("LICENSE-{}", id.to_uppercase());
-    let license_path = output_path.join(&license_file_name);
-    let generic_license_path = output_path.join("LICENSE");
-
-    if !license_path.exists() && !generic_license_path.exists() {
-      info!("Adding license file for {} (placeholder)", id);
-      // Placeholder content - replace with actual license text fetching later
-      let content = format!("Placeholder for {} License Text.", id);
-      fs::write(&license_path, content).map_err(|err| PorterError::Io {
-        source: err,
-        path: license_path.to_path_buf(),
-      })?;
-      info!("Created license file: {}", license_path.display());
-    } else {
-      info!("License file already exists, skipping creation.");
-    }
+/// Adds the license file(s) for the project's SPDX expression, if one is configured.
+fn add_license_file(
+  project_id: &str,
+  config: &ProjectConfig,
+  messages: &mut Vec<String>,
+) -> Result<()> {
+  if let Some(expression) = config.license.as_deref() {
+    info!("Adding license file(s) for '{}'", expression);
+    messages.extend(write_license_files(
+      project_id,
+      expression,
+      config.copyright_holder.as_deref(),
+      &config.output_path,
+    )?);
   }
   Ok(())
 }
@@ -160,6 +172,387 @@ fn ensure_gitignore(output_path: &Path) -> Result<()> {
   Ok(())
 }
 
+/// Lists the files below `source` (relative paths, sorted), split into those accepted by
+/// `filter` and those it rejects. `.git` directories are never listed.
+fn list_published_files(
+  source: &Path,
+  filter: &PathFilter,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+  let mut included = Vec::new();
+  let mut excluded = Vec::new();
+  let walker = WalkDir::new(source)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|entry| entry.file_name() != ".git");
+  for entry in walker {
+    let entry = entry.map_err(|e| PorterError::Io {
+      path: e.path().unwrap_or(source).to_path_buf(),
+      source: e.into(),
+    })?;
+    if entry.file_type().is_dir() {
+      continue;
+    }
+    let rel_path = entry.path().strip_prefix(source).unwrap_or(entry.path());
+    if filter.is_included(rel_path) {
+      included.push(rel_path.to_path_buf());
+    } else {
+      debug!("Excluding '{}' from extraction.", rel_path.display());
+      excluded.push(rel_path.to_path_buf());
+    }
+  }
+  Ok((included, excluded))
+}
+
+/// Copies every file below `source` accepted by `filter` into `dest`, keeping the layout.
+/// `.git` directories are never copied. Returns the number of copied and excluded files.
+fn copy_filtered(source: &Path, dest: &Path, filter: &PathFilter) -> Result<(usize, usize)> {
+  let (included, excluded) = list_published_files(source, filter)?;
+  copy_files(source, dest, &included)?;
+  Ok((included.len(), excluded.len()))
+}
+
+/// Copies the given relative paths from `source` into `dest`.
+fn copy_files(source: &Path, dest: &Path, files: &[PathBuf]) -> Result<()> {
+  for rel_path in files {
+    let target = dest.join(rel_path);
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent).map_err(|e| PorterError::Io {
+        source: e,
+        path: parent.to_path_buf(),
+      })?;
+    }
+    copy_file_or_symlink(&source.join(rel_path), &target)?;
+  }
+  Ok(())
+}
+
+/// Copies the file `from` to `to`. A symlink is recreated as a symlink instead of being
+/// followed, so its target (possibly outside the project) is never copied.
+fn copy_file_or_symlink(from: &Path, to: &Path) -> Result<()> {
+  let io_error = |e: std::io::Error| PorterError::Io {
+    source: e,
+    path: from.to_path_buf(),
+  };
+  if fs::symlink_metadata(from)
+    .map_err(io_error)?
+    .file_type()
+    .is_symlink()
+  {
+    let link_target = fs::read_link(from).map_err(io_error)?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&link_target, to).map_err(io_error)?;
+    #[cfg(not(unix))]
+    warn!(
+      "Skipping symlink '{}' -> '{}': symlinks can only be recreated on Unix.",
+      from.display(),
+      link_target.display()
+    );
+    return Ok(());
+  }
+  fs::copy(from, to).map_err(io_error)?;
+  Ok(())
+}
+
+/// Runs `git-filter-repo` over the temporary clone so that only the published part of
+/// the project remains: re-roots history at `project_subdir`, then applies the
+/// include/exclude globs. Each invocation needs `--force` since we rerun it on the same clone.
+fn run_filter_repo(clone_path: &Path, config: &ProjectConfig, filter: &PathFilter) -> Result<()> {
+  let subdir_arg = config.project_subdir.to_string_lossy().to_string();
+  if config.project_subdir != Path::new(".") {
+    run_command_capture(
+      "git-filter-repo",
+      &["--subdirectory-filter", &subdir_arg, "--force"],
+      clone_path,
+    )?;
+  }
+
+  if !filter.include_patterns().is_empty() {
+    let mut args = Vec::new();
+    for pattern in filter.include_patterns() {
+      args.push("--path-glob");
+      args.push(pattern.as_str());
+    }
+    args.push("--force");
+    run_command_capture("git-filter-repo", &args, clone_path)?;
+  }
+
+  // The state file is always stripped, even when no exclude globs are configured.
+  let mut args = vec!["--invert-paths", "--path", STATE_FILE_NAME];
+  for pattern in filter.exclude_patterns() {
+    args.push("--path-glob");
+    args.push(pattern.as_str());
+  }
+  args.push("--force");
+  run_command_capture("git-filter-repo", &args, clone_path)?;
+  Ok(())
+}
+
+/// Rewrites author/committer/tagger identities in the filtered clone with the project's
+/// identity map. The map is expanded into an explicit mailmap over every identity in the
+/// history, so the fallback identity applies too. Returns the number of identities changed.
+fn rewrite_identities(clone_path: &Path, config: &ProjectConfig) -> Result<usize> {
+  let Some(map) = IdentityMap::from_config(config)? else {
+    return Ok(0);
+  };
+  let identities = history_identities(clone_path)?;
+  let mailmap = map.expand_to_mailmap(&identities);
+  let changed = mailmap.lines().count();
+  if changed == 0 {
+    return Ok(0);
+  }
+  let mailmap_file =
+    tempfile::NamedTempFile::new().map_err(|e| PorterError::TempDir { source: e })?;
+  fs::write(mailmap_file.path(), mailmap).map_err(|err| PorterError::Io {
+    source: err,
+    path: mailmap_file.path().to_path_buf(),
+  })?;
+  let mailmap_arg = mailmap_file.path().to_string_lossy().to_string();
+  run_command_capture(
+    "git-filter-repo",
+    &["--mailmap", &mailmap_arg, "--force"],
+    clone_path,
+  )?;
+  Ok(changed)
+}
+
+/// Rewrites every commit message in the filtered clone with the project's message rules.
+/// The new messages are computed here (so preserve mode and `update` share one regex
+/// engine) and handed to `git filter-repo --message-callback` as an exact
+/// old-message -> new-message table. Returns the number of distinct messages changed.
+fn rewrite_messages(clone_path: &Path, config: &ProjectConfig) -> Result<usize> {
+  let Some(rules) = MessageRules::from_config(config)? else {
+    return Ok(0);
+  };
+  let rev_list = run_git_command(&["rev-list", "--all"], clone_path)?;
+
+  let mut table = String::new();
+  let mut changed = 0;
+  let mut seen = std::collections::HashSet::new();
+  for commit in read_commits(clone_path, &rev_list.stdout)? {
+    let raw = commit.message.as_slice();
+    let Ok(message) = std::str::from_utf8(raw) else {
+      continue; // Non-UTF-8 messages are left alone
+    };
+    if !seen.insert(raw.to_vec()) {
+      continue;
+    }
+    let mut rewritten = rules.apply(message);
+    if rewritten == message {
+      continue;
+    }
+    if message.ends_with('\n') {
+      rewritten.push('\n');
+    }
+    if rewritten != message {
+      table.push_str(&format!(
+        "{} {}\n",
+        to_hex(raw),
+        to_hex(rewritten.as_bytes())
+      ));
+      changed += 1;
+    }
+  }
+  if changed == 0 {
+    return Ok(0);
+  }
+
+  let table_file =
+    tempfile::NamedTempFile::new().map_err(|e| PorterError::TempDir { source: e })?;
+  fs::write(table_file.path(), table).map_err(|err| PorterError::Io {
+    source: err,
+    path: table_file.path().to_path_buf(),
+  })?;
+  let callback = format!(
+    "global _oss_porter_messages\n\
+     if '_oss_porter_messages' not in globals():\n\
+     \x20 with open(bytes.fromhex('{}').decode(), 'r') as f:\n\
+     \x20   _oss_porter_messages = dict((bytes.fromhex(a), bytes.fromhex(b)) for a, b in (line.split() for line in f))\n\
+     return _oss_porter_messages.get(message, message)",
+    to_hex(table_file.path().to_string_lossy().as_bytes())
+  );
+  run_command_capture(
+    "git-filter-repo",
+    &["--message-callback", &callback, "--force"],
+    clone_path,
+  )?;
+  Ok(changed)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Commits the staged changes in the output repo, recording the local user under their
+/// mapped public identity.
+fn commit_output(config: &ProjectConfig, message: &str) -> Result<()> {
+  let identity_args = local_identity_args(config, &config.output_path)?;
+  let mut args: Vec<&str> = identity_args.iter().map(String::as_str).collect();
+  args.extend(["commit", "-m", message]);
+  run_git_command(&args, &config.output_path)?;
+  Ok(())
+}
+
+/// Runs the project's content transforms (scrubbing, license headers, manifest rewrites) over the output directory.
+fn transform_output(
+  config: &ProjectConfig,
+  filter: &PathFilter,
+  messages: &mut Vec<String>,
+) -> Result<Vec<StrippedRegion>> {
+  let transforms = ContentTransforms::from_config(config)?;
+  let report = transforms.apply_to_dir(&config.output_path, filter)?;
+  if !report.stripped_regions.is_empty() {
+    messages.push(format!(
+      "Stripped {} internal-only region(s) from the extracted files.",
+      report.stripped_regions.len()
+    ));
+  }
+  if !report.headers_changed.is_empty() {
+    messages.push(format!(
+      "Added or replaced the license header in {} file(s).",
+      report.headers_changed.len()
+    ));
+  }
+  if !report.headers_missing.is_empty() {
+    messages.push(format!(
+      "Warning: {} file(s) lack the expected license header (see `check` for the list).",
+      report.headers_missing.len()
+    ));
+  }
+  messages.extend(report.notes);
+  Ok(report.stripped_regions)
+}
+
+/// Returns true if `path` exists and contains anything.
+fn is_non_empty_dir(path: &Path) -> Result<bool> {
+  if !path.exists() {
+    return Ok(false);
+  }
+  let mut entries = fs::read_dir(path).map_err(|err| PorterError::Io {
+    source: err,
+    path: path.to_path_buf(),
+  })?;
+  Ok(entries.next().is_some())
+}
+
+/// Rewrites paths below `from` in scan findings so they point below `to`.
+fn relocate_findings(findings: Vec<String>, from: &Path, to: &Path) -> Vec<String> {
+  let from = from.display().to_string();
+  let to = to.display().to_string();
+  findings
+    .into_iter()
+    .map(|finding| finding.replace(&from, &to))
+    .collect()
+}
+
+/// A temporary directory next to `output_path` that an extraction is built in. `finish`
+/// renames it to `output_path` once everything succeeded; dropping it instead removes all
+/// partial results, so a failed extraction leaves `output_path` untouched and can be retried.
+struct StagingDir {
+  dir: TempDir,
+  output_path: PathBuf,
+}
+
+impl StagingDir {
+  /// Fails with `OutputPathExists` if `output_path` is not empty. The staging directory
+  /// shares the parent of `output_path`, so the final rename stays on one filesystem.
+  fn new(output_path: &Path) -> Result<Self> {
+    if is_non_empty_dir(output_path)? {
+      return Err(PorterError::OutputPathExists(output_path.to_path_buf()));
+    }
+    let parent = match output_path.parent() {
+      Some(parent) if !parent.as_os_str().is_empty() => parent,
+      _ => Path::new("."),
+    };
+    fs::create_dir_all(parent).map_err(|err| PorterError::Io {
+      source: err,
+      path: parent.to_path_buf(),
+    })?;
+    let dir = tempfile::Builder::new()
+      .prefix(".oss-porter-extract-")
+      .tempdir_in(parent)
+      .map_err(|source| PorterError::TempDir { source })?;
+    debug!("Staging extraction in {}", dir.path().display());
+    Ok(StagingDir {
+      dir,
+      output_path: output_path.to_path_buf(),
+    })
+  }
+
+  fn path(&self) -> &Path {
+    self.dir.path()
+  }
+
+  /// A copy of `config` whose `output_path` is the staging directory.
+  fn config(&self, config: &ProjectConfig) -> ProjectConfig {
+    let mut staged = config.clone();
+    staged.output_path = self.path().to_path_buf();
+    staged
+  }
+
+  /// Moves the staged result into place. An empty `output_path` directory is replaced.
+  fn finish(self) -> Result<()> {
+    if self.output_path.exists() {
+      fs::remove_dir(&self.output_path).map_err(|err| PorterError::Io {
+        source: err,
+        path: self.output_path.clone(),
+      })?;
+    }
+    let staged = self.dir.into_path();
+    if let Err(err) = fs::rename(&staged, &self.output_path) {
+      let _ = fs::remove_dir_all(&staged);
+      return Err(PorterError::Io {
+        source: err,
+        path: self.output_path,
+      });
+    }
+    info!("Moved extraction into {}", self.output_path.display());
+    Ok(())
+  }
+}
+
+/// Resolves `internal_branch` to a commit in the internal repository. Returns `None` (and
+/// adds a warning) if the branch doesn't exist, in which case no sync state is recorded.
+fn resolve_internal_branch(config: &ProjectConfig, messages: &mut Vec<String>) -> Option<String> {
+  let rev = format!("refs/heads/{}^{{commit}}", config.internal_branch);
+  match run_git_command(
+    &["rev-parse", "--verify", "-q", &rev],
+    &config.internal_repo_path,
+  ) {
+    Ok(output) => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+    Err(e) => {
+      debug!("Could not resolve internal branch: {}", e);
+      messages.push(format!(
+        "Warning: internal_branch '{}' not found in {}; no sync state was recorded.",
+        config.internal_branch,
+        config.internal_repo_path.display()
+      ));
+      None
+    }
+  }
+}
+
+/// Writes `commit` to the internal state file as the last synced commit, so the first
+/// `update` starts right after the extracted state. The change is not committed.
+fn record_sync_state(
+  config: &ProjectConfig,
+  commit: &str,
+  messages: &mut Vec<String>,
+) -> Result<()> {
+  if let Some(previous) = read_last_synced_commit(config).ok().flatten() {
+    if previous != commit {
+      warn!("Replacing previous sync state {} with {}", previous, commit);
+    }
+  }
+  write_last_synced_commit(config, Some(commit))?;
+  write_pending_commits(config, &[])?; // Everything up to `commit` is in the new repo
+  messages.push(format!(
+    "Recorded internal commit {} as the last synced commit in {} (not committed yet).",
+    commit,
+    get_internal_state_file_path(config).display()
+  ));
+  Ok(())
+}
+
 // --- Public Extraction Function ---
 
 /// Extracts a project using the "clean slate" method (copy files, new git history).
@@ -169,29 +562,46 @@ pub fn extract_clean_slate(project_id: &str, config: &ProjectConfig) -> Result<E
     project_id
   );
   let mut messages = Vec::new();
+  let filter = PathFilter::from_config(config)?;
 
   // 1. Validate paths
   let source_path = config.internal_repo_path.join(&config.project_subdir);
   if !source_path.exists() {
     return Err(PorterError::PathNotFound(source_path));
   }
-  if config.output_path.exists()
-    && fs::read_dir(&config.output_path)
-      .map_err(|err| PorterError::Io {
-        source: err,
-        path: config.output_path.to_path_buf(),
-      })?
-      .next()
-      .is_some()
-  {
-    // Check if directory exists and is not empty
-    return Err(PorterError::OutputPathExists(config.output_path.clone()));
-  } else if !config.output_path.exists() {
-    fs::create_dir_all(&config.output_path).map_err(|err| PorterError::Io {
-      source: err,
-      path: config.output_path.to_path_buf(),
-    })?;
-    info!("Created output directory: {}", config.output_path.display());
+
+  // 1b. Build everything in a staging directory; output_path is only populated on success
+  let output_path = config.output_path.clone();
+  let staging = StagingDir::new(&output_path)?;
+  let config = &staging.config(config);
+
+  // 1c. The commit the extracted files correspond to. The working tree is copied, so warn
+  // if it may not match internal_branch.
+  let synced_commit = resolve_internal_branch(config, &mut messages);
+  if synced_commit.is_some() {
+    let head = run_git_command(
+      &["symbolic-ref", "-q", "--short", "HEAD"],
+      &config.internal_repo_path,
+    )
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    .unwrap_or_default();
+    let subdir = config.project_subdir.to_string_lossy().to_string();
+    let state_file = format!(
+      ":(exclude){}",
+      Path::new(&subdir).join(STATE_FILE_NAME).display()
+    );
+    let dirty = run_git_command(
+      &["status", "--porcelain", "--", &subdir, &state_file],
+      &config.internal_repo_path,
+    )
+    .map(|output| !output.stdout.is_empty())
+    .unwrap_or(false);
+    if head != config.internal_branch || dirty {
+      messages.push(format!(
+        "Warning: the internal working tree is not a clean checkout of '{}'; the recorded sync state may not match the extracted files.",
+        config.internal_branch
+      ));
+    }
   }
 
   // 2. Copy files
@@ -200,30 +610,14 @@ pub fn extract_clean_slate(project_id: &str, config: &ProjectConfig) -> Result<E
     source_path.display(),
     config.output_path.display()
   );
-  let mut copy_options = CopyOptions::new();
-  copy_options.content_only = true; // Copy contents of source_path, not the dir itself
-  copy_options.overwrite = false; // Should fail if output exists and isn't empty (checked above)
-  copy_options.skip_exist = false;
-
-  copy_dir(&source_path, &config.output_path, &copy_options)?;
+  let (copied, excluded) = copy_filtered(&source_path, &config.output_path, &filter)?;
   messages.push(format!(
-    "Copied project files from {}",
-    source_path.display()
+    "Copied {} project files from {} ({} excluded by filters)",
+    copied,
+    source_path.display(),
+    excluded
   ));
 
-  // 2b. Explicitly remove state file from output path after copying
-  let state_file_in_output = config.output_path.join(STATE_FILE_NAME);
-  if state_file_in_output.exists() {
-    info!(
-      "Removing internal state file '{}' from clean slate output.",
-      STATE_FILE_NAME
-    );
-    fs::remove_file(&state_file_in_output).map_err(|e| PorterError::Io {
-      source: e,
-      path: state_file_in_output,
-    })?;
-  }
-
   // 3. Initialize Git repo
   info!(
     "Initializing Git repository in {}",
@@ -234,12 +628,15 @@ pub fn extract_clean_slate(project_id: &str, config: &ProjectConfig) -> Result<E
   // run_git_command(&["branch", "-M", "main"], &config.output_path)?;
   messages.push("Initialized Git repository.".to_string());
 
+  // 3b. Strip internal-only regions and rewrite manifests before anything is committed
+  let stripped_regions = transform_output(config, &filter, &mut messages)?;
+
   // 4. Add License & .gitignore
-  add_license_file(config.license.as_deref(), &config.output_path)?;
+  add_license_file(project_id, config, &mut messages)?;
   ensure_gitignore(&config.output_path)?;
 
   // 5. Basic Secret Scan (before commit)
-  let secrets_found = scan_secrets_basic(&config.output_path)?;
+  let secrets_found = scan_secrets_basic(&config.output_path, &filter)?;
   if !secrets_found.is_empty() {
     messages.push(format!(
       "Warning: {} potential secrets found during basic scan.",
@@ -254,18 +651,33 @@ pub fn extract_clean_slate(project_id: &str, config: &ProjectConfig) -> Result<E
   run_git_command(&["add", "."], &config.output_path)?;
   let commit_message = format!("Initial commit of open source project '{}'", project_id);
   info!("Creating initial commit.");
-  run_git_command(&["commit", "-m", &commit_message], &config.output_path)?;
+  commit_output(config, &commit_message)?;
   messages.push("Created initial Git commit.".to_string());
 
+  // 7. Move the finished repository into place
+  let secrets_found = relocate_findings(secrets_found, staging.path(), &output_path);
+  staging.finish()?;
+  messages.push(format!(
+    "Moved the new repository into {}",
+    output_path.display()
+  ));
+
+  // 8. Record where the next `update` starts
+  if let Some(commit) = &synced_commit {
+    record_sync_state(config, commit, &mut messages)?;
+  }
+
   info!(
     "Clean slate extraction completed for project: {}",
     project_id
   );
   Ok(ExtractionResult {
     project_id: project_id.to_string(),
-    output_path: config.output_path.clone(),
+    output_path,
     messages,
     secrets_found,
+    stripped_regions,
+    synced_commit,
   })
 }
 
@@ -280,11 +692,16 @@ pub fn extract_preserve_history(
     project_id
   );
   let mut messages = Vec::new();
+  let filter = PathFilter::from_config(config)?;
 
   // 1. Prerequisite Check
   check_tool_exists("git")?; // Ensure git itself exists
-  check_tool_exists("git-filter-repo")?;
-  messages.push("Checked prerequisites (git, git-filter-repo).".to_string());
+  if config.history_rewriter == HistoryRewriter::FilterRepo {
+    check_tool_exists("git-filter-repo")?;
+    messages.push("Checked prerequisites (git, git-filter-repo).".to_string());
+  } else {
+    messages.push("Checked prerequisites (git).".to_string());
+  }
 
   // 2. Validate paths (similar to clean_slate)
   let source_repo_path = &config.internal_repo_path; // Path to the repo root
@@ -301,78 +718,108 @@ pub fn extract_preserve_history(
       source_repo_path.join(project_subdir_relative),
     ));
   }
-  if config.output_path.exists()
-    && fs::read_dir(&config.output_path)
-      .map_err(|err| PorterError::Io {
-        source: err,
-        path: config.output_path.to_path_buf(),
-      })?
-      .next()
-      .is_some()
-  {
-    return Err(PorterError::OutputPathExists(config.output_path.clone()));
-  } else if !config.output_path.exists() {
-    fs::create_dir_all(&config.output_path).map_err(|err| PorterError::Io {
-      source: err,
-      path: config.output_path.to_path_buf(),
-    })?;
-    info!("Created output directory: {}", config.output_path.display());
-  }
 
-  // 3. Create Temporary Clone
-  let temp_dir = TempDir::new().map_err(|source| PorterError::TempDir { source })?;
-  let temp_clone_path = temp_dir.path();
+  // 3. Clone into a staging directory next to output_path; it's only moved into place on success
+  let output_path = config.output_path.clone();
+  let staging = StagingDir::new(&output_path)?;
+  let config = &staging.config(config);
+  let clone_path = staging.path();
   info!(
-    "Creating temporary clone of {} in {}",
+    "Cloning {} into staging directory {}",
     source_repo_path.display(),
-    temp_clone_path.display()
+    clone_path.display()
   );
 
   // Use file:// protocol for local clones if necessary, adjust if internal repo is remote
   let repo_url = source_repo_path.to_string_lossy(); // Assuming local path for now
-  run_git_command(
-    &["clone", "--no-local", "--bare", &repo_url, "."],
-    temp_clone_path,
-  )?; // Use bare clone then checkout? Or full clone? Full clone is simpler.
-      // Let's try a full clone first
-  run_git_command(&["clone", "--no-local", &repo_url, "."], temp_clone_path)?;
-  messages.push(format!(
-    "Created temporary clone in {}",
-    temp_clone_path.display()
-  ));
-
-  // 4. Run git-filter-repo
-  info!(
-    "Running git-filter-repo for subdir '{}'",
-    project_subdir_relative.display()
-  );
-  let subdir_arg = project_subdir_relative.to_string_lossy(); // Ensure correct format for command arg
-                                                              // Use --force because we are operating in a temporary clone
-  run_command_capture(
-    "git-filter-repo",
-    &["--path", &subdir_arg, "--force"],
-    temp_clone_path,
-  )?;
-  messages.push(format!("Ran git-filter-repo on path '{}'", subdir_arg));
-
-  // 5. Move Filtered Repo Contents to Output Path
-  info!(
-    "Moving filtered repository contents to {}",
-    config.output_path.display()
-  );
-  // We need to move the *contents* of temp_clone_path to output_path
-  let mut move_options = CopyOptions::new();
-  move_options.content_only = true;
-  move_options.overwrite = false; // Should be fine as output_path was empty
-
-  // fs_extra::dir::move_dir requires a callback, even if trivial
-  move_dir(temp_clone_path, &config.output_path, &move_options)?;
+  let synced_commit = resolve_internal_branch(config, &mut messages);
+  let mut clone_args = vec!["clone", "--no-local"];
+  if synced_commit.is_some() {
+    clone_args.extend(["--branch", config.internal_branch.as_str()]);
+  }
+  clone_args.extend([repo_url.as_ref(), "."]);
+  run_git_command(&clone_args, clone_path)?;
   messages.push(format!(
-    "Moved filtered content to {}",
-    config.output_path.display()
+    "Cloned {} into a staging directory",
+    source_repo_path.display()
   ));
 
-  // TempDir is dropped here, cleaning up the empty temp directory
+  // 4. Rewrite the history down to the published files
+  let (identities_changed, messages_changed, history_regions) = match config.history_rewriter {
+    HistoryRewriter::Native => {
+      info!(
+        "Rewriting history for subdir '{}'",
+        project_subdir_relative.display()
+      );
+      let report = rewrite_history(clone_path, config, &filter)?;
+      messages.push(format!(
+        "Rewrote history of path '{}' ({} include / {} exclude patterns): kept {} commit(s), dropped {} without published changes",
+        project_subdir_relative.display(),
+        filter.include_patterns().len(),
+        filter.exclude_patterns().len(),
+        report.commits_kept,
+        report.commits_dropped
+      ));
+      if report.tags_kept > 0 {
+        messages.push(format!(
+          "Moved {} tag(s) onto the rewritten history (as lightweight tags).",
+          report.tags_kept
+        ));
+      }
+      if report.files_transformed > 0 {
+        messages.push(format!(
+          "Applied the content transforms to {} file version(s) in the preserved history, stripping {} internal-only region(s).",
+          report.files_transformed,
+          report.stripped_regions.len()
+        ));
+      }
+      (
+        report.identities_changed,
+        report.messages_changed,
+        report.stripped_regions,
+      )
+    }
+    HistoryRewriter::FilterRepo => {
+      info!(
+        "Running git-filter-repo for subdir '{}'",
+        project_subdir_relative.display()
+      );
+      run_filter_repo(clone_path, config, &filter)?;
+      if let Some(scrubber) = Scrubber::from_config(config)? {
+        let regions = scrubber.scan_history(clone_path)?;
+        if let Some(first) = regions.first() {
+          return Err(PorterError::Scrub(format!(
+            "the history left by git-filter-repo contains {} internal-only region(s) (e.g. {}), which it cannot strip. Use history_rewriter = \"native\" or the clean_slate mode.",
+            regions.len(),
+            first
+          )));
+        }
+      }
+      messages.push(format!(
+        "Ran git-filter-repo on path '{}' ({} include / {} exclude patterns)",
+        project_subdir_relative.display(),
+        filter.include_patterns().len(),
+        filter.exclude_patterns().len()
+      ));
+      (
+        rewrite_identities(clone_path, config)?,
+        rewrite_messages(clone_path, config)?,
+        Vec::new(),
+      )
+    }
+  };
+  if identities_changed > 0 {
+    messages.push(format!(
+      "Rewrote {} author/committer identities in the preserved history.",
+      identities_changed
+    ));
+  }
+  if messages_changed > 0 {
+    messages.push(format!(
+      "Rewrote {} commit message(s) in the preserved history with the message rules.",
+      messages_changed
+    ));
+  }
 
   // 5b. Explicitly remove state file from output path if it exists
   let state_file_in_output = config.output_path.join(STATE_FILE_NAME);
@@ -392,10 +839,7 @@ pub fn extract_preserve_history(
         info!("Committing removal of internal state file from output repository.");
         // Use the actual state file name constant here
         run_git_command(&["add", STATE_FILE_NAME], &config.output_path)?;
-        run_git_command(
-          &["commit", "-m", "chore: Remove internal sync state file"],
-          &config.output_path,
-        )?;
+        commit_output(config, "chore: Remove internal sync state file")?;
         messages.push(format!(
           "Committed removal of internal state file '{}' from output.",
           STATE_FILE_NAME
@@ -422,32 +866,34 @@ pub fn extract_preserve_history(
     Err(e) => warn!("Could not remove 'origin' remote (might not exist): {}", e), // Don't fail if remote doesn't exist
   };
 
+  // 6a2. Strip internal-only regions and rewrite manifests in the final code state
+  let mut stripped_regions = history_regions;
+  stripped_regions.extend(transform_output(config, &filter, &mut messages)?);
+
   // 6b. Add License & .gitignore (if they weren't correctly handled by filter-repo or history)
-  add_license_file(config.license.as_deref(), &config.output_path)?;
+  add_license_file(project_id, config, &mut messages)?;
   ensure_gitignore(&config.output_path)?;
 
-  // 6c. Check if license/gitignore were added/modified and need committing
+  // 6c. Check if license/gitignore/scrubbed files were added/modified and need committing
   let git_status = run_git_command(&["status", "--porcelain"], &config.output_path)?;
   let status_output = String::from_utf8_lossy(&git_status.stdout);
   if !status_output.trim().is_empty() {
-    info!("Detected changes after filtering (likely license/gitignore), creating cleanup commit.");
-    run_git_command(&["add", "LICENSE*", ".gitignore"], &config.output_path)?; // Add specific files
-    run_git_command(
-      &[
-        "commit",
-        "-m",
-        "chore: Add license and/or gitignore after history filtering",
-      ],
-      &config.output_path,
+    info!(
+      "Detected changes after filtering (license/gitignore/scrubbing), creating cleanup commit."
+    );
+    run_git_command(&["add", "-A"], &config.output_path)?;
+    commit_output(
+      config,
+      "chore: Post-filtering cleanup (license, gitignore, internal-only code)",
     )?;
-    messages.push("Created cleanup commit for license/gitignore.".to_string());
+    messages.push("Created cleanup commit for license/gitignore/scrubbed files.".to_string());
   } else {
     info!("No changes detected after filtering, no cleanup commit needed.");
   }
 
   // 7. Final Secrets Scan (on the resulting code state)
   // Note: This does NOT scan the rewritten history itself.
-  let secrets_found = scan_secrets_basic(&config.output_path)?;
+  let secrets_found = scan_secrets_basic(&config.output_path, &filter)?;
   if !secrets_found.is_empty() {
     messages.push(format!(
       "Warning: {} potential secrets found during basic scan of final code state.",
@@ -455,14 +901,318 @@ pub fn extract_preserve_history(
     ));
   }
 
+  // 8. Move the finished repository into place
+  let secrets_found = relocate_findings(secrets_found, staging.path(), &output_path);
+  staging.finish()?;
+  messages.push(format!(
+    "Moved filtered repository into {}",
+    output_path.display()
+  ));
+
+  // 9. Record where the next `update` starts
+  if let Some(commit) = &synced_commit {
+    record_sync_state(config, commit, &mut messages)?;
+  }
+
   info!(
     "History preservation extraction completed for project: {}",
     project_id
   );
   Ok(ExtractionResult {
     project_id: project_id.to_string(),
-    output_path: config.output_path.clone(),
+    output_path,
     messages,
     secrets_found, // Only reports secrets in final code state
+    stripped_regions,
+    synced_commit,
   })
 }
+
+// --- Dry Run ---
+
+/// Counts the commits of `internal_branch` (or `HEAD` if it doesn't exist, as the clone
+/// would) that change a published file, i.e. a path below `project_subdir` accepted by the
+/// filter. Merges count if they change one relative to their first parent.
+fn count_surviving_commits(config: &ProjectConfig, filter: &PathFilter) -> Result<usize> {
+  let subdir = config.project_subdir.to_string_lossy().replace('\\', "/");
+  let prefix = match subdir.trim_matches('/') {
+    "" | "." => String::new(),
+    dir => format!("{}/", dir),
+  };
+  let branch = format!("refs/heads/{}", config.internal_branch);
+  let rev = match run_git_command(
+    &["rev-parse", "--verify", "-q", &branch],
+    &config.internal_repo_path,
+  ) {
+    Ok(_) => branch,
+    Err(_) => "HEAD".to_string(),
+  };
+  let pathspec = format!(":(top){}", if prefix.is_empty() { "." } else { &prefix });
+  let output = run_git_command(
+    &[
+      "log",
+      "--format=%x01%H",
+      "-z",
+      "--name-only",
+      "--no-renames",
+      "--diff-merges=first-parent",
+      &rev,
+      "--",
+      &pathspec,
+    ],
+    &config.internal_repo_path,
+  )?;
+  let log = String::from_utf8_lossy(&output.stdout);
+  let count = log
+    .split('\x01')
+    .filter(|commit| {
+      commit
+        .split('\0')
+        .skip(1) // The commit hash
+        .map(|path| path.trim_start_matches('\n'))
+        .filter_map(|path| path.strip_prefix(prefix.as_str()))
+        .any(|rel_path| !rel_path.is_empty() && filter.is_included(Path::new(rel_path)))
+    })
+    .count();
+  Ok(count)
+}
+
+/// Works out what `extract` would do for `project_id` without touching `output_path`: the
+/// published files are copied to a temporary directory and transformed there, so license,
+/// secret and dependency findings match a real run. Preconditions the real extraction
+/// would fail on (non-empty output path, missing `git-filter-repo`) become warnings.
+pub fn plan_extraction(
+  project_id: &str,
+  config: &ProjectConfig,
+  history_mode: HistoryMode,
+) -> Result<ExtractionPlan> {
+  info!(
+    "Planning {:?} extraction for project: {}",
+    history_mode, project_id
+  );
+  let filter = PathFilter::from_config(config)?;
+  let mut warnings = Vec::new();
+
+  let source_path = config.internal_repo_path.join(&config.project_subdir);
+  if !source_path.exists() {
+    return Err(PorterError::PathNotFound(source_path));
+  }
+  if is_non_empty_dir(&config.output_path)? {
+    warnings.push(PorterError::OutputPathExists(config.output_path.clone()).to_string());
+  }
+
+  let surviving_commits = if history_mode == HistoryMode::Preserve {
+    if !config.internal_repo_path.join(".git").exists() {
+      return Err(PorterError::GitOperation(format!(
+        "Internal repo path '{}' does not appear to be a git repository root.",
+        config.internal_repo_path.display()
+      )));
+    }
+    if config.history_rewriter == HistoryRewriter::FilterRepo {
+      if let Err(e) = check_tool_exists("git-filter-repo") {
+        warnings.push(e.to_string());
+      }
+    }
+    Some(count_surviving_commits(config, &filter)?)
+  } else {
+    None
+  };
+
+  // Stage the published files outside output_path and run the real transforms on them.
+  let (files_to_copy, files_excluded) = list_published_files(&source_path, &filter)?;
+  let staging = TempDir::new().map_err(|source| PorterError::TempDir { source })?;
+  let staged = staging.path();
+  copy_files(&source_path, staged, &files_to_copy)?;
+
+  let transforms = ContentTransforms::from_config(config)?;
+  let report = transforms.apply_to_dir(staged, &filter)?;
+  let mut transform_notes = Vec::new();
+  if !report.headers_changed.is_empty() {
+    transform_notes.push(format!(
+      "The license header would be added or replaced in {} file(s).",
+      report.headers_changed.len()
+    ));
+  }
+  if !report.headers_missing.is_empty() {
+    transform_notes.push(format!(
+      "{} file(s) lack the expected license header.",
+      report.headers_missing.len()
+    ));
+  }
+  transform_notes.extend(report.notes);
+
+  let mut license_actions = Vec::new();
+  if let Some(expression) = config.license.as_deref() {
+    let holder = config.copyright_holder.as_deref();
+    for message in write_license_files(project_id, expression, holder, staged)? {
+      let message = message.replacen("Added ", "Would add ", 1);
+      license_actions.push(message.replacen("Kept ", "Would keep ", 1));
+    }
+  } else {
+    license_actions.push("No license configured; no license file would be added.".to_string());
+  }
+  let gitignore_action = if staged.join(".gitignore").exists() {
+    "Would keep the existing .gitignore.".to_string()
+  } else {
+    "Would create a basic .gitignore (/target, Cargo.lock).".to_string()
+  };
+
+  // Findings mention the staging directory; report them against output_path instead.
+  let relocate = |findings| relocate_findings(findings, staged, &config.output_path);
+  let secrets_found = relocate(scan_secrets_basic(staged, &filter)?);
+  let mut dependency_findings = relocate(check_internal_dependencies(staged)?);
+  dependency_findings.extend(relocate(check_workspace_inheritance(staged, &filter)?));
+
+  Ok(ExtractionPlan {
+    project_id: project_id.to_string(),
+    output_path: config.output_path.clone(),
+    history_mode,
+    files_to_copy,
+    files_excluded,
+    license_actions,
+    gitignore_action,
+    transform_notes,
+    stripped_regions: report.stripped_regions,
+    secrets_found,
+    dependency_findings,
+    surviving_commits,
+    warnings,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+
+  fn config(repo: &Path, output: &Path, extra: &str) -> ProjectConfig {
+    toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n{}",
+      repo, output, extra
+    ))
+    .unwrap()
+  }
+
+  #[test]
+  fn surviving_commits_touch_published_files() {
+    let repo = testing::init_repo();
+    testing::commit_files(repo.path(), &[("crates/foo/src/lib.rs", "a\n")], "Publish");
+    testing::commit_files(
+      repo.path(),
+      &[("crates/foo/internal_docs/x.md", "x\n")],
+      "Excluded",
+    );
+    testing::commit_files(repo.path(), &[("crates/bar/lib.rs", "b\n")], "Outside");
+    testing::commit_files(repo.path(), &[("crates/foo/README.md", "r\n")], "Readme");
+    let config = config(
+      repo.path(),
+      Path::new("out"),
+      "exclude = [\"internal_docs/\"]\n",
+    );
+    let filter = PathFilter::from_config(&config).unwrap();
+    assert_eq!(count_surviving_commits(&config, &filter).unwrap(), 2);
+  }
+
+  #[test]
+  fn plan_leaves_the_output_path_alone() {
+    let repo = testing::init_repo();
+    testing::commit_files(
+      repo.path(),
+      &[
+        ("crates/foo/src/lib.rs", "fn a() {}\n"),
+        ("crates/foo/deploy/run.sh", "x\n"),
+      ],
+      "Add",
+    );
+    let output = TempDir::new().unwrap();
+    let config = config(
+      repo.path(),
+      &output.path().join("out"),
+      "exclude = [\"deploy/\"]\n",
+    );
+    let plan = plan_extraction("foo", &config, HistoryMode::CleanSlate).unwrap();
+    assert_eq!(plan.files_to_copy, [PathBuf::from("src/lib.rs")]);
+    assert_eq!(plan.files_excluded, [PathBuf::from("deploy/run.sh")]);
+    assert!(plan.warnings.is_empty() && plan.surviving_commits.is_none());
+    assert!(!config.output_path.exists());
+
+    fs::create_dir_all(config.output_path.join("old")).unwrap();
+    let plan = plan_extraction("foo", &config, HistoryMode::CleanSlate).unwrap();
+    assert_eq!(plan.warnings.len(), 1);
+  }
+
+  #[test]
+  fn clean_slate_extraction_records_the_internal_branch_tip() {
+    let repo = testing::init_repo();
+    let head = testing::commit_files(
+      repo.path(),
+      &[("crates/foo/src/lib.rs", "fn a() {}\n")],
+      "Add",
+    );
+    let output = TempDir::new().unwrap();
+    let config = config(repo.path(), &output.path().join("out"), "");
+    let result = extract_clean_slate("foo", &config).unwrap();
+    assert_eq!(result.synced_commit.as_deref(), Some(head.as_str()));
+    assert_eq!(read_last_synced_commit(&config).unwrap(), Some(head));
+
+    let mut config = config.clone();
+    config.internal_branch = "missing".to_string();
+    config.output_path = output.path().join("out2");
+    let result = extract_clean_slate("foo", &config).unwrap();
+    assert_eq!(result.synced_commit, None);
+    assert!(result
+      .messages
+      .iter()
+      .any(|m| m.contains("'missing' not found")));
+  }
+
+  fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect();
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn staging_dir_replaces_an_empty_output_path_on_finish() {
+    let parent = TempDir::new().unwrap();
+    let output = parent.path().join("out");
+    fs::create_dir(&output).unwrap();
+    let staging = StagingDir::new(&output).unwrap();
+    fs::write(staging.path().join("a.txt"), "a").unwrap();
+    staging.finish().unwrap();
+    assert_eq!(entries(parent.path()), ["out"]);
+    assert_eq!(entries(&output), ["a.txt"]);
+    assert!(matches!(
+      StagingDir::new(&output),
+      Err(PorterError::OutputPathExists(_))
+    ));
+  }
+
+  #[test]
+  fn dropped_staging_dir_leaves_nothing_behind() {
+    let parent = TempDir::new().unwrap();
+    let staging = StagingDir::new(&parent.path().join("out")).unwrap();
+    fs::write(staging.path().join("a.txt"), "a").unwrap();
+    drop(staging);
+    assert!(entries(parent.path()).is_empty());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinks_are_copied_as_links() {
+    let source = TempDir::new().unwrap();
+    let dest = TempDir::new().unwrap();
+    fs::write(source.path().join("a.txt"), "a").unwrap();
+    std::os::unix::fs::symlink("/etc/hostname", source.path().join("link")).unwrap();
+    let files = [PathBuf::from("a.txt"), PathBuf::from("link")];
+    copy_files(source.path(), dest.path(), &files).unwrap();
+    assert_eq!(
+      fs::read_link(dest.path().join("link")).unwrap(),
+      Path::new("/etc/hostname")
+    );
+    assert_eq!(fs::read_to_string(dest.path().join("a.txt")).unwrap(), "a");
+  }
+}
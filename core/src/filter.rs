@@ -0,0 +1,167 @@
+// oss-porter-core/src/filter.rs
+use crate::state::STATE_FILE_NAME;
+use crate::{PorterError, ProjectConfig, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::debug;
+use std::path::Path;
+
+/// Decides which files below `project_subdir` are published, based on the
+/// project's `include` / `exclude` glob lists.
+///
+/// Paths handed to the filter must be relative to `project_subdir`. An empty
+/// `include` list means "everything"; `exclude` always wins over `include`.
+/// The internal sync state file is never published.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+  include: Option<GlobSet>,
+  exclude: Option<GlobSet>,
+  include_patterns: Vec<String>,
+  exclude_patterns: Vec<String>,
+}
+
+impl PathFilter {
+  /// Builds the filter from the `include` / `exclude` lists of a project.
+  pub fn from_config(config: &ProjectConfig) -> Result<Self> {
+    let include_patterns = normalize_patterns(&config.include);
+    let exclude_patterns = normalize_patterns(&config.exclude);
+    Ok(PathFilter {
+      include: build_glob_set(&include_patterns)?,
+      exclude: build_glob_set(&exclude_patterns)?,
+      include_patterns,
+      exclude_patterns,
+    })
+  }
+
+  /// Returns true if the file at `rel_path` (relative to `project_subdir`) should be published.
+  pub fn is_included(&self, rel_path: &Path) -> bool {
+    if rel_path == Path::new(STATE_FILE_NAME) {
+      return false;
+    }
+    if let Some(exclude) = &self.exclude {
+      if exclude.is_match(rel_path) {
+        debug!("Path '{}' excluded by filter.", rel_path.display());
+        return false;
+      }
+    }
+    match &self.include {
+      Some(include) => include.is_match(rel_path),
+      None => true,
+    }
+  }
+
+  /// Normalized include globs (empty if everything is included).
+  pub fn include_patterns(&self) -> &[String] {
+    &self.include_patterns
+  }
+
+  /// Normalized exclude globs.
+  pub fn exclude_patterns(&self) -> &[String] {
+    &self.exclude_patterns
+  }
+}
+
+/// Normalizes user-supplied globs so that directory patterns cover their contents:
+/// `deploy/` and `deploy` both also match `deploy/**`. Leading `/` and `./` are dropped.
+fn normalize_patterns(patterns: &[String]) -> Vec<String> {
+  let mut normalized = Vec::new();
+  for pattern in patterns {
+    let trimmed = pattern.trim();
+    let trimmed = trimmed.trim_start_matches("./").trim_start_matches('/');
+    if trimmed.is_empty() {
+      continue;
+    }
+    if let Some(dir) = trimmed.strip_suffix('/') {
+      normalized.push(format!("{}/**", dir));
+      continue;
+    }
+    normalized.push(trimmed.to_string());
+    if !trimmed.ends_with("**") {
+      normalized.push(format!("{}/**", trimmed));
+    }
+  }
+  normalized
+}
+
+/// Builds a matcher for user-supplied globs relative to `project_subdir`, normalized like the
+/// include/exclude lists. Returns `None` if `patterns` is empty.
+pub(crate) fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+  build_glob_set(&normalize_patterns(patterns))
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+  if patterns.is_empty() {
+    return Ok(None);
+  }
+  let mut builder = GlobSetBuilder::new();
+  for pattern in patterns {
+    let glob = Glob::new(pattern)
+      .map_err(|e| PorterError::Config(format!("Invalid path pattern '{}': {}", pattern, e)))?;
+    builder.add(glob);
+  }
+  let set = builder
+    .build()
+    .map_err(|e| PorterError::Config(format!("Failed to build path filter: {}", e)))?;
+  Ok(Some(set))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+    let mut config: ProjectConfig = toml::from_str(
+      "internal_repo_path = \"internal\"\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n",
+    )
+    .unwrap();
+    config.include = include.iter().map(|p| p.to_string()).collect();
+    config.exclude = exclude.iter().map(|p| p.to_string()).collect();
+    PathFilter::from_config(&config).unwrap()
+  }
+
+  fn patterns(patterns: &[&str]) -> Vec<String> {
+    normalize_patterns(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+  }
+
+  #[test]
+  fn directory_patterns_cover_their_contents() {
+    assert_eq!(patterns(&["deploy/"]), ["deploy/**"]);
+    assert_eq!(patterns(&["deploy"]), ["deploy", "deploy/**"]);
+    assert_eq!(patterns(&["src/**"]), ["src/**"]);
+  }
+
+  #[test]
+  fn leading_slashes_and_blank_patterns_are_dropped() {
+    assert_eq!(
+      patterns(&["./docs/", "/ci/", "  ", ""]),
+      ["docs/**", "ci/**"]
+    );
+  }
+
+  #[test]
+  fn everything_is_included_without_include_patterns() {
+    let filter = filter(&[], &[]);
+    assert!(filter.is_included(Path::new("src/lib.rs")));
+    assert!(filter.is_included(Path::new("Cargo.toml")));
+  }
+
+  #[test]
+  fn exclude_wins_over_include() {
+    let filter = filter(&["src/**", "Cargo.toml"], &["src/internal", "*.env"]);
+    assert!(filter.is_included(Path::new("src/lib.rs")));
+    assert!(filter.is_included(Path::new("Cargo.toml")));
+    assert!(!filter.is_included(Path::new("src/internal/mod.rs")));
+    assert!(!filter.is_included(Path::new("src/.env")));
+    assert!(!filter.is_included(Path::new("README.md")));
+  }
+
+  #[test]
+  fn state_file_is_never_included() {
+    assert!(!filter(&[], &[]).is_included(Path::new(STATE_FILE_NAME)));
+  }
+
+  #[test]
+  fn invalid_patterns_are_config_errors() {
+    let error = build_glob_set(&["src/[".to_string()]).unwrap_err();
+    assert!(matches!(error, PorterError::Config(_)));
+  }
+}
@@ -0,0 +1,311 @@
+// oss-porter-core/src/header.rs
+use crate::utils::current_year;
+use crate::{HeaderMode, PorterError, ProjectConfig, Result};
+use std::path::Path;
+
+/// Line comment prefix by file extension. Files with other extensions never get headers.
+const COMMENT_PREFIXES: &[(&str, &[&str])] = &[
+  (
+    "//",
+    &[
+      "rs", "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "cs", "go", "java", "kt", "kts", "scala",
+      "swift", "dart", "js", "jsx", "mjs", "cjs", "ts", "tsx", "proto", "zig",
+    ],
+  ),
+  (
+    "#",
+    &["py", "sh", "bash", "zsh", "rb", "pl", "r", "nix", "ps1"],
+  ),
+  ("--", &["sql", "lua", "hs"]),
+];
+
+/// Adds, replaces or verifies the license header at the top of source files, according to
+/// a project's `license_header` settings.
+pub struct LicenseHeaders {
+  mode: HeaderMode,
+  lines: Vec<String>, // Expected header, without comment prefixes
+  extensions: Vec<String>,
+}
+
+/// What happened to a file's header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderOutcome {
+  Unchanged, // Expected header present, or the file type has no header
+  Added,
+  Replaced,
+  Missing, // `verify` mode only: the expected header is absent
+}
+
+impl LicenseHeaders {
+  /// Builds the header settings from the project config. Returns `None` if headers are off.
+  pub fn from_config(config: &ProjectConfig) -> Result<Option<Self>> {
+    let settings = &config.license_header;
+    if settings.mode == HeaderMode::Off {
+      return Ok(None);
+    }
+    let license = config.license.as_deref().ok_or_else(|| {
+      PorterError::Config("'license_header' needs the project's 'license' to be set.".to_string())
+    })?;
+    let holder = config.copyright_holder.as_deref();
+    let template = if settings.template.is_empty() {
+      let mut default = Vec::new();
+      if holder.is_some() {
+        default.push("Copyright (c) {holder}".to_string());
+      }
+      default.push("SPDX-License-Identifier: {license}".to_string());
+      default
+    } else {
+      settings.template.clone()
+    };
+    let year = settings
+      .year
+      .clone()
+      .unwrap_or_else(|| current_year().to_string());
+
+    let mut lines = Vec::new();
+    for line in &template {
+      if line.contains("{holder}") && holder.is_none() {
+        return Err(PorterError::Config(
+          "The license header template uses {holder}, but 'copyright_holder' is not set."
+            .to_string(),
+        ));
+      }
+      lines.push(
+        line
+          .replace("{license}", license)
+          .replace("{holder}", holder.unwrap_or_default())
+          .replace("{year}", &year),
+      );
+    }
+
+    for ext in &settings.extensions {
+      if comment_prefix_for(ext).is_none() {
+        return Err(PorterError::Config(format!(
+          "No known comment syntax for license header extension '{}'.",
+          ext
+        )));
+      }
+    }
+    Ok(Some(LicenseHeaders {
+      mode: settings.mode,
+      lines,
+      extensions: settings.extensions.clone(),
+    }))
+  }
+
+  /// Returns the comment prefix used for `path`, or `None` if it doesn't get a header.
+  fn prefix(&self, path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?;
+    if !self.extensions.is_empty() && !self.extensions.iter().any(|e| e == ext) {
+      return None;
+    }
+    comment_prefix_for(ext)
+  }
+
+  /// Renders the expected header for a comment prefix.
+  fn render(&self, prefix: &str) -> Vec<String> {
+    self
+      .lines
+      .iter()
+      .map(|line| {
+        if line.is_empty() {
+          prefix.to_string()
+        } else {
+          format!("{} {}", prefix, line)
+        }
+      })
+      .collect()
+  }
+
+  /// Returns true if `content` starts with the expected header (or `path` gets no header).
+  pub fn has_expected_header(&self, path: &Path, content: &str) -> bool {
+    let Some(prefix) = self.prefix(path) else {
+      return true;
+    };
+    if content.trim().is_empty() {
+      return true; // Empty files are left empty
+    }
+    let lines: Vec<&str> = content.lines().collect();
+    let start = usize::from(lines.first().is_some_and(|l| l.starts_with("#!")));
+    let expected = self.render(prefix);
+    lines.len() >= start + expected.len()
+      && expected
+        .iter()
+        .zip(&lines[start..])
+        .all(|(want, have)| have.trim_end() == want)
+  }
+
+  /// Applies the configured mode to one file. Returns the new content and what was done;
+  /// in `verify` mode the content is never changed.
+  pub fn apply(&self, path: &Path, content: &str) -> (String, HeaderOutcome) {
+    let Some(prefix) = self.prefix(path) else {
+      return (content.to_string(), HeaderOutcome::Unchanged);
+    };
+    if self.has_expected_header(path, content) {
+      return (content.to_string(), HeaderOutcome::Unchanged);
+    }
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let start = usize::from(lines.first().is_some_and(|l| l.starts_with("#!")));
+    let block_end = leading_comment_end(&lines, start, prefix);
+    let block = &lines[start..block_end];
+    let is_license_block = block.iter().any(|line| {
+      let lower = line.to_lowercase();
+      lower.contains("spdx-license-identifier") || lower.contains("copyright")
+    });
+
+    let (skip_to, outcome) = match self.mode {
+      HeaderMode::Off => return (content.to_string(), HeaderOutcome::Unchanged),
+      HeaderMode::Verify => return (content.to_string(), HeaderOutcome::Missing),
+      HeaderMode::Add if is_license_block => {
+        return (content.to_string(), HeaderOutcome::Unchanged);
+      }
+      HeaderMode::Add => (start, HeaderOutcome::Added),
+      HeaderMode::Replace if is_license_block => {
+        // Drop the old header and the blank line separating it from the code.
+        let mut end = block_end;
+        if lines.get(end).is_some_and(|l| l.trim().is_empty()) {
+          end += 1;
+        }
+        (end, HeaderOutcome::Replaced)
+      }
+      HeaderMode::Replace => (start, HeaderOutcome::Added),
+    };
+
+    let newline = if content.contains("\r\n") {
+      "\r\n"
+    } else {
+      "\n"
+    };
+    let mut result = String::with_capacity(content.len() + 128);
+    for line in &lines[..start] {
+      result.push_str(line);
+    }
+    for line in self.render(prefix) {
+      result.push_str(&line);
+      result.push_str(newline);
+    }
+    let rest = &lines[skip_to..];
+    if rest.first().is_some_and(|l| !l.trim().is_empty()) {
+      result.push_str(newline);
+    }
+    for line in rest {
+      result.push_str(line);
+    }
+    (result, outcome)
+  }
+}
+
+fn comment_prefix_for(ext: &str) -> Option<&'static str> {
+  COMMENT_PREFIXES
+    .iter()
+    .find(|(_, exts)| exts.contains(&ext))
+    .map(|(prefix, _)| *prefix)
+}
+
+/// Returns the index just past the run of plain comment lines starting at `start`.
+/// Rust doc comments (`//!`, `///`) are code documentation, not part of a header.
+fn leading_comment_end(lines: &[&str], start: usize, prefix: &str) -> usize {
+  let mut end = start;
+  while let Some(line) = lines.get(end) {
+    let line = line.trim_start();
+    let is_doc = prefix == "//" && (line.starts_with("//!") || line.starts_with("///"));
+    if !line.starts_with(prefix) || is_doc {
+      break;
+    }
+    end += 1;
+  }
+  end
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn headers(mode: HeaderMode) -> LicenseHeaders {
+    LicenseHeaders {
+      mode,
+      lines: vec![
+        "Copyright (c) Example Org".to_string(),
+        "SPDX-License-Identifier: MIT".to_string(),
+      ],
+      extensions: Vec::new(),
+    }
+  }
+
+  const HEADER: &str = "// Copyright (c) Example Org\n// SPDX-License-Identifier: MIT\n";
+
+  #[test]
+  fn add_inserts_the_header_after_a_shebang() {
+    let (text, outcome) =
+      headers(HeaderMode::Add).apply(Path::new("run.py"), "#!/usr/bin/env python\nprint()\n");
+    assert_eq!(outcome, HeaderOutcome::Added);
+    assert_eq!(
+      text,
+      "#!/usr/bin/env python\n# Copyright (c) Example Org\n# SPDX-License-Identifier: MIT\n\nprint()\n"
+    );
+  }
+
+  #[test]
+  fn add_keeps_other_license_blocks_and_doc_comments_are_no_header() {
+    let headers = headers(HeaderMode::Add);
+    let other = "// Copyright 2019 Someone\n\nfn main() {}\n";
+    assert_eq!(
+      headers.apply(Path::new("a.rs"), other),
+      (other.to_string(), HeaderOutcome::Unchanged)
+    );
+    let (text, outcome) = headers.apply(Path::new("lib.rs"), "//! Crate docs\n");
+    assert_eq!(outcome, HeaderOutcome::Added);
+    assert_eq!(text, format!("{}\n//! Crate docs\n", HEADER));
+  }
+
+  #[test]
+  fn replace_swaps_an_outdated_license_block() {
+    let content = "// Copyright 2019 Someone\n// All rights reserved.\n\nfn main() {}\n";
+    let (text, outcome) = headers(HeaderMode::Replace).apply(Path::new("main.rs"), content);
+    assert_eq!(outcome, HeaderOutcome::Replaced);
+    assert_eq!(text, format!("{}\nfn main() {{}}\n", HEADER));
+    assert_eq!(
+      headers(HeaderMode::Replace)
+        .apply(Path::new("main.rs"), &text)
+        .1,
+      HeaderOutcome::Unchanged
+    );
+  }
+
+  #[test]
+  fn verify_never_changes_files() {
+    let content = "fn main() {}\n";
+    let (text, outcome) = headers(HeaderMode::Verify).apply(Path::new("main.rs"), content);
+    assert_eq!((text.as_str(), outcome), (content, HeaderOutcome::Missing));
+  }
+
+  #[test]
+  fn files_without_comment_syntax_or_content_are_skipped() {
+    let headers = headers(HeaderMode::Add);
+    assert_eq!(
+      headers.apply(Path::new("README.md"), "# Title\n").1,
+      HeaderOutcome::Unchanged
+    );
+    assert_eq!(
+      headers.apply(Path::new("empty.rs"), "\n").1,
+      HeaderOutcome::Unchanged
+    );
+    let limited = LicenseHeaders {
+      extensions: vec!["py".to_string()],
+      ..headers
+    };
+    assert_eq!(
+      limited.apply(Path::new("main.rs"), "fn main() {}\n").1,
+      HeaderOutcome::Unchanged
+    );
+  }
+
+  #[test]
+  fn crlf_files_keep_their_line_endings() {
+    let (text, _) = headers(HeaderMode::Add).apply(Path::new("init.sql"), "SELECT 1;\r\n");
+    assert_eq!(
+      text,
+      "-- Copyright (c) Example Org\r\n-- SPDX-License-Identifier: MIT\r\n\r\nSELECT 1;\r\n"
+    );
+  }
+}
@@ -0,0 +1,558 @@
+// oss-porter-core/src/history.rs
+use crate::filter::PathFilter;
+use crate::identity::{Identity, IdentityMap};
+use crate::message::MessageRules;
+use crate::scrub::StrippedRegion;
+use crate::transform::ContentTransforms;
+use crate::utils::{
+  read_objects, run_git_command, run_git_command_with_env, run_git_command_with_input,
+};
+use crate::{PorterError, ProjectConfig, Result};
+use log::{debug, info, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// A commit object as printed by `git cat-file --batch`.
+pub(crate) struct RawCommit {
+  pub hash: String,
+  pub tree: String,
+  pub parents: Vec<String>,
+  pub author: String,    // "Name <email> <timestamp> <tz>"
+  pub committer: String, // Same format
+  pub message: Vec<u8>,  // Raw bytes after the header block
+}
+
+impl RawCommit {
+  fn parse(hash: &str, object: &[u8]) -> Self {
+    let (headers, message) = match object.windows(2).position(|w| w == b"\n\n") {
+      Some(pos) => (&object[..pos], object[pos + 2..].to_vec()),
+      None => (object, Vec::new()),
+    };
+    let mut commit = RawCommit {
+      hash: hash.to_string(),
+      tree: String::new(),
+      parents: Vec::new(),
+      author: String::new(),
+      committer: String::new(),
+      message,
+    };
+    for line in String::from_utf8_lossy(headers).lines() {
+      match line.split_once(' ') {
+        Some(("tree", value)) => commit.tree = value.to_string(),
+        Some(("parent", value)) => commit.parents.push(value.to_string()),
+        Some(("author", value)) => commit.author = value.to_string(),
+        Some(("committer", value)) => commit.committer = value.to_string(),
+        _ => {} // gpgsig, encoding, mergetag, continuation lines
+      }
+    }
+    commit
+  }
+}
+
+/// Reads the objects named in `revs` (one per line) with a single `git cat-file --batch`
+/// and returns the commits among them, in the given order.
+pub(crate) fn read_commits(repo_path: &Path, revs: &[u8]) -> Result<Vec<RawCommit>> {
+  Ok(
+    read_objects(repo_path, revs)?
+      .into_iter()
+      .filter(|(_, kind, _)| kind == "commit")
+      .map(|(hash, _, object)| RawCommit::parse(&hash, &object))
+      .collect(),
+  )
+}
+
+/// Outcome of `rewrite_history`.
+#[derive(Debug, Default)]
+pub struct RewriteReport {
+  pub commits_kept: usize,
+  pub commits_dropped: usize, // Commits that don't change any published file
+  pub identities_changed: usize,
+  pub messages_changed: usize,
+  pub tags_kept: usize, // Tags moved onto rewritten commits (as lightweight tags)
+  pub files_transformed: usize, // Distinct file versions rewritten by the content transforms
+  pub stripped_regions: Vec<StrippedRegion>, // Internal-only regions removed from any file version
+}
+
+/// Splits a raw `Name <email> <timestamp> <tz>` ident into name, email and date.
+fn split_ident(raw: &str) -> Option<(&str, &str, &str)> {
+  let open = raw.find('<')?;
+  let close = open + raw[open..].find('>')?;
+  Some((
+    raw[..open].trim(),
+    &raw[open + 1..close],
+    raw[close + 1..].trim(),
+  ))
+}
+
+fn stdout_line(output: &std::process::Output) -> String {
+  String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Filters trees down to the published files and runs the content transforms on them,
+/// caching results by source tree and source blob.
+struct TreeFilter<'a> {
+  repo_path: &'a Path,
+  filter: &'a PathFilter,
+  transforms: Option<ContentTransforms>, // None if no transform is configured
+  index_file: String,                    // Scratch index used to build filtered trees
+  cache: HashMap<String, Option<String>>,
+  blobs: HashMap<(String, String), String>, // (path, source blob) -> transformed blob
+  files_transformed: usize,
+  stripped_regions: Vec<StrippedRegion>,
+}
+
+impl TreeFilter<'_> {
+  /// Returns the tree with only the files of `tree` accepted by the filter, each transformed,
+  /// or `None` if nothing is left.
+  fn filtered(&mut self, tree: &str) -> Result<Option<String>> {
+    if let Some(cached) = self.cache.get(tree) {
+      return Ok(cached.clone());
+    }
+    let listing = run_git_command(
+      &["ls-tree", "-r", "-z", "--full-tree", tree],
+      self.repo_path,
+    )?;
+    let entries: Vec<&[u8]> = listing
+      .stdout
+      .split(|&b| b == 0)
+      .filter(|entry| !entry.is_empty())
+      .collect();
+    let mut kept: Vec<(String, String, &[u8])> = Vec::new(); // (mode and type, object, raw path)
+    for entry in &entries {
+      let Some(tab) = entry.iter().position(|&b| b == b'\t') else {
+        continue;
+      };
+      let info = String::from_utf8_lossy(&entry[..tab]);
+      let Some((mode_and_type, oid)) = info.rsplit_once(' ') else {
+        continue;
+      };
+      let path = String::from_utf8_lossy(&entry[tab + 1..]);
+      if self.filter.is_included(Path::new(path.as_ref())) {
+        kept.push((
+          mode_and_type.to_string(),
+          oid.to_string(),
+          &entry[tab + 1..],
+        ));
+      }
+    }
+    let mut changed = kept.len() != entries.len();
+    if !kept.is_empty() && self.transforms.is_some() {
+      self.transform_blobs(&kept)?;
+      for (mode_and_type, oid, path) in &mut kept {
+        if mode_and_type.ends_with(" blob") {
+          let key = (String::from_utf8_lossy(path).to_string(), oid.clone());
+          if let Some(new_oid) = self.blobs.get(&key) {
+            changed |= new_oid != oid;
+            *oid = new_oid.clone();
+          }
+        }
+      }
+    }
+
+    let result = if kept.is_empty() {
+      None
+    } else if !changed {
+      Some(tree.to_string())
+    } else {
+      let mut index_info: Vec<u8> = Vec::new();
+      for (mode_and_type, oid, path) in &kept {
+        index_info.extend_from_slice(format!("{} {}\t", mode_and_type, oid).as_bytes());
+        index_info.extend_from_slice(path);
+        index_info.push(0);
+      }
+      let _ = fs::remove_file(&self.index_file);
+      let env = [("GIT_INDEX_FILE", self.index_file.as_str())];
+      run_git_command_with_env(
+        &["update-index", "-z", "--index-info"],
+        self.repo_path,
+        &env,
+        &index_info,
+      )?;
+      let written = run_git_command_with_env(&["write-tree"], self.repo_path, &env, &[])?;
+      Some(stdout_line(&written))
+    };
+    self.cache.insert(tree.to_string(), result.clone());
+    Ok(result)
+  }
+
+  /// Runs the content transforms on the blobs of `entries` not seen before (read with one
+  /// batch) and writes the rewritten ones to the object database. Binary or non-UTF-8 blobs
+  /// are left untouched.
+  fn transform_blobs(&mut self, entries: &[(String, String, &[u8])]) -> Result<()> {
+    let Some(transforms) = &self.transforms else {
+      return Ok(());
+    };
+    let pending: Vec<(String, String)> = entries
+      .iter()
+      .filter(|(mode_and_type, _, _)| mode_and_type.ends_with(" blob"))
+      .map(|(_, oid, path)| (String::from_utf8_lossy(path).to_string(), oid.clone()))
+      .filter(|key| !self.blobs.contains_key(key))
+      .collect();
+    if pending.is_empty() {
+      return Ok(());
+    }
+    let revs: String = pending
+      .iter()
+      .map(|(_, oid)| format!("{}\n", oid))
+      .collect();
+    let contents: HashMap<String, Vec<u8>> = read_objects(self.repo_path, revs.as_bytes())?
+      .into_iter()
+      .map(|(hash, _, content)| (hash, content))
+      .collect();
+    for (path, oid) in pending {
+      let mut new_oid = oid.clone();
+      if let Some(Ok(text)) = contents
+        .get(&oid)
+        .map(|content| std::str::from_utf8(content))
+      {
+        let (transformed, report) = transforms.apply(Path::new(&path), text)?;
+        if transformed != text {
+          new_oid = stdout_line(&run_git_command_with_input(
+            &["hash-object", "-w", "--stdin"],
+            self.repo_path,
+            transformed.as_bytes(),
+          )?);
+          debug!("Transformed {} ({} -> {})", path, oid, new_oid);
+          self.files_transformed += 1;
+          self.stripped_regions.extend(report.stripped_regions);
+        }
+      }
+      self.blobs.insert((path, oid), new_oid);
+    }
+    Ok(())
+  }
+}
+
+/// Rewrites the current branch of the repository at `repo_path` (a throwaway clone of the
+/// internal repo) so it only holds the published project, using plain git plumbing
+/// (`rev-list`, `ls-tree`, `commit-tree`) instead of `git-filter-repo`:
+///
+/// - every tree is re-rooted at `project_subdir` and reduced to the files `filter` accepts;
+/// - commits that don't change any published file are dropped and their children
+///   re-parented, merges whose parents collapse into one are dropped too;
+/// - every published file version goes through the content transforms (scrubbing, license
+///   headers, manifest rewrites), so no commit carries internal-only code;
+/// - authors/committers go through the identity map and messages through the message rules.
+///
+/// Tags pointing into the rewritten history are moved onto the rewritten commits, every
+/// other ref is deleted and unreachable objects are pruned, so no internal history remains.
+pub fn rewrite_history(
+  repo_path: &Path,
+  config: &ProjectConfig,
+  filter: &PathFilter,
+) -> Result<RewriteReport> {
+  let identity_map = IdentityMap::from_config(config)?;
+  let message_rules = MessageRules::from_config(config)?;
+  let transforms = Some(ContentTransforms::from_config(config)?).filter(|t| !t.is_noop());
+  let mut report = RewriteReport::default();
+
+  let branch = stdout_line(&run_git_command(
+    &["symbolic-ref", "-q", "HEAD"],
+    repo_path,
+  )?);
+  let head = stdout_line(&run_git_command(&["rev-parse", "HEAD"], repo_path)?);
+  let rev_list = run_git_command(
+    &["rev-list", "--reverse", "--topo-order", "HEAD"],
+    repo_path,
+  )?;
+  let commits = read_commits(repo_path, &rev_list.stdout)?;
+  info!("Rewriting {} commit(s) on {}", commits.len(), branch);
+
+  // Locate the project directory in every commit with one batch lookup.
+  let subdir = config
+    .project_subdir
+    .to_string_lossy()
+    .replace('\\', "/")
+    .trim_matches('/')
+    .to_string();
+  let subtrees: Vec<Option<String>> = if subdir.is_empty() || subdir == "." {
+    commits.iter().map(|c| Some(c.tree.clone())).collect()
+  } else {
+    let lookups: String = commits
+      .iter()
+      .map(|c| format!("{}:{}\n", c.hash, subdir))
+      .collect();
+    let output = run_git_command_with_input(
+      &["cat-file", "--batch-check"],
+      repo_path,
+      lookups.as_bytes(),
+    )?;
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .map(
+        |line| match line.split(' ').collect::<Vec<_>>().as_slice() {
+          [oid, "tree", _] => Some(oid.to_string()),
+          _ => None, // "missing", or not a directory at this commit
+        },
+      )
+      .collect()
+  };
+  if subtrees.len() != commits.len() {
+    return Err(PorterError::GitOperation(
+      "Unexpected cat-file --batch-check output while locating project_subdir".to_string(),
+    ));
+  }
+
+  let git_dir = stdout_line(&run_git_command(
+    &["rev-parse", "--absolute-git-dir"],
+    repo_path,
+  )?);
+  let mut trees = TreeFilter {
+    repo_path,
+    filter,
+    transforms,
+    index_file: format!("{}/oss-porter-index", git_dir),
+    cache: HashMap::new(),
+    blobs: HashMap::new(),
+    files_transformed: 0,
+    stripped_regions: Vec::new(),
+  };
+  let mut empty_tree: Option<String> = None;
+  let mut mapping: HashMap<String, Option<String>> = HashMap::new(); // Old commit -> new (None: no published ancestor)
+  let mut new_trees: HashMap<String, Option<String>> = HashMap::new();
+  let mut changed_identities: BTreeSet<Identity> = BTreeSet::new();
+  let mut changed_messages: HashSet<Vec<u8>> = HashSet::new();
+
+  for (commit, subtree) in commits.iter().zip(&subtrees) {
+    let tree = match subtree {
+      Some(subtree) => trees.filtered(subtree)?,
+      None => None,
+    };
+    let mut parents: Vec<String> = Vec::new();
+    for parent in &commit.parents {
+      if let Some(Some(new_parent)) = mapping.get(parent) {
+        if !parents.contains(new_parent) {
+          parents.push(new_parent.clone());
+        }
+      }
+    }
+    if parents.len() > 1 {
+      // Drop parents that became ancestors of other parents.
+      let mut args = vec!["merge-base", "--independent"];
+      args.extend(parents.iter().map(String::as_str));
+      let output = run_git_command(&args, repo_path)?;
+      let independent = String::from_utf8_lossy(&output.stdout).to_string();
+      parents.retain(|parent| independent.lines().any(|line| line == parent));
+    }
+    let unchanged = match parents.as_slice() {
+      [] => tree.is_none(),
+      [parent] => new_trees.get(parent) == Some(&tree),
+      _ => false,
+    };
+    if unchanged {
+      debug!("Dropping {} (no published changes)", commit.hash);
+      mapping.insert(commit.hash.clone(), parents.pop());
+      report.commits_dropped += 1;
+      continue;
+    }
+
+    let tree_hash = match &tree {
+      Some(tree) => tree.clone(),
+      None => match &empty_tree {
+        Some(empty) => empty.clone(),
+        None => {
+          let empty = stdout_line(&run_git_command_with_input(&["mktree"], repo_path, &[])?);
+          empty_tree = Some(empty.clone());
+          empty
+        }
+      },
+    };
+
+    let mut env: Vec<(&str, String)> = Vec::new();
+    let idents = [
+      (
+        &commit.author,
+        "GIT_AUTHOR_NAME",
+        "GIT_AUTHOR_EMAIL",
+        "GIT_AUTHOR_DATE",
+      ),
+      (
+        &commit.committer,
+        "GIT_COMMITTER_NAME",
+        "GIT_COMMITTER_EMAIL",
+        "GIT_COMMITTER_DATE",
+      ),
+    ];
+    for (raw, name_var, email_var, date_var) in idents {
+      let Some((name, email, date)) = split_ident(raw) else {
+        warn!("Could not parse identity '{}' of {}", raw, commit.hash);
+        continue;
+      };
+      let mut identity = Identity {
+        name: name.to_string(),
+        email: email.to_string(),
+      };
+      if let Some(map) = &identity_map {
+        let mapped = map.map(name, email);
+        if mapped != identity {
+          changed_identities.insert(identity);
+        }
+        identity = mapped;
+      }
+      env.push((name_var, identity.name));
+      env.push((email_var, identity.email));
+      env.push((date_var, date.to_string()));
+    }
+
+    let mut message = commit.message.clone();
+    if let (Some(rules), Ok(text)) = (&message_rules, std::str::from_utf8(&commit.message)) {
+      let mut rewritten = rules.apply(text);
+      if rewritten != text && text.ends_with('\n') {
+        rewritten.push('\n');
+      }
+      if rewritten != text {
+        changed_messages.insert(commit.message.clone());
+        message = rewritten.into_bytes();
+      }
+    }
+
+    let mut args = vec!["commit-tree", "--no-gpg-sign", tree_hash.as_str()];
+    for parent in &parents {
+      args.extend(["-p", parent.as_str()]);
+    }
+    let env: Vec<(&str, &str)> = env.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let new_commit = stdout_line(&run_git_command_with_env(&args, repo_path, &env, &message)?);
+    debug!("Rewrote {} -> {}", commit.hash, new_commit);
+    new_trees.insert(new_commit.clone(), tree);
+    mapping.insert(commit.hash.clone(), Some(new_commit));
+    report.commits_kept += 1;
+  }
+  let _ = fs::remove_file(&trees.index_file);
+  report.files_transformed = trees.files_transformed;
+  report.stripped_regions = trees.stripped_regions;
+  report.identities_changed = changed_identities.len();
+  report.messages_changed = changed_messages.len();
+
+  // Point the branch at the rewritten history and check it out.
+  match mapping.get(&head).cloned().flatten() {
+    Some(new_head) => {
+      run_git_command(&["update-ref", &branch, &new_head], repo_path)?;
+      run_git_command(&["reset", "--hard", "-q"], repo_path)?;
+    }
+    None => {
+      warn!("No commit touches the published files; the rewritten history is empty.");
+      run_git_command(&["update-ref", "-d", &branch], repo_path)?;
+      run_git_command(&["read-tree", "--empty"], repo_path)?;
+      run_git_command(&["clean", "-fdxq"], repo_path)?;
+    }
+  }
+
+  // Keep tags into the rewritten history, drop every other ref.
+  let refs = run_git_command(
+    &[
+      "for-each-ref",
+      "--format=%(refname) %(objectname) %(*objectname)",
+    ],
+    repo_path,
+  )?;
+  for line in String::from_utf8_lossy(&refs.stdout).lines() {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (refname, target) = match fields.as_slice() {
+      [refname, _, peeled] => (*refname, *peeled),
+      [refname, object] => (*refname, *object),
+      _ => continue,
+    };
+    if refname == branch {
+      continue;
+    }
+    if refname.starts_with("refs/tags/") {
+      if let Some(Some(new_target)) = mapping.get(target) {
+        run_git_command(&["update-ref", refname, new_target], repo_path)?;
+        report.tags_kept += 1;
+        continue;
+      }
+    }
+    debug!("Deleting ref {}", refname);
+    run_git_command(&["update-ref", "--no-deref", "-d", refname], repo_path)?;
+  }
+
+  // Nothing may keep the internal objects alive in the published clone.
+  run_git_command(&["reflog", "expire", "--expire=now", "--all"], repo_path)?;
+  run_git_command(&["gc", "--prune=now", "--quiet"], repo_path)?;
+  info!(
+    "History rewrite done: {} commit(s) kept, {} dropped",
+    report.commits_kept, report.commits_dropped
+  );
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{self, git};
+
+  #[test]
+  fn rewrite_keeps_only_published_changes() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    testing::commit_files(
+      path,
+      &[
+        ("crates/foo/src/lib.rs", "fn a() {}\n"),
+        ("crates/bar/x", "1\n"),
+      ],
+      "Add foo",
+    );
+    testing::commit_files(path, &[("crates/bar/x", "2\n")], "Change bar");
+    testing::commit_files(
+      path,
+      &[("crates/foo/internal_docs/n.md", "n\n")],
+      "Add notes",
+    );
+    let secret = "fn a() {}\nfn b() {}\n// OSS-STRIP-BEGIN\nfn secret() {}\n// OSS-STRIP-END\n";
+    testing::commit_files(
+      path,
+      &[("crates/foo/src/lib.rs", secret)],
+      "Add secret\n\nPROJ-7",
+    );
+    git(path, &["tag", "v1"]);
+    git(path, &["branch", "feature"]);
+
+    let config: ProjectConfig = toml::from_str(
+      "internal_repo_path = \"internal\"\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n\
+       exclude = [\"internal_docs/\"]\n\
+       [scrub]\nenabled = true\n\
+       [identity_map]\nfallback = \"Public <public@example.com>\"\n\
+       [[message_rules]]\npattern = \"PROJ-\\\\d+\"\n",
+    )
+    .unwrap();
+    let filter = PathFilter::from_config(&config).unwrap();
+    let report = rewrite_history(path, &config, &filter).unwrap();
+
+    assert_eq!((report.commits_kept, report.commits_dropped), (2, 2));
+    assert_eq!(
+      (
+        report.tags_kept,
+        report.messages_changed,
+        report.identities_changed
+      ),
+      (1, 1, 1)
+    );
+    assert_eq!(report.stripped_regions.len(), 1);
+    assert_eq!(
+      git(path, &["log", "--format=%s|%an <%ae>", "main"]),
+      "Add secret|Public <public@example.com>\nAdd foo|Public <public@example.com>"
+    );
+    assert_eq!(
+      git(path, &["log", "-1", "--format=%B", "main"]),
+      "Add secret"
+    );
+    assert_eq!(
+      git(path, &["ls-tree", "-r", "--name-only", "main"]),
+      "src/lib.rs"
+    );
+    assert_eq!(
+      git(path, &["show", "main:src/lib.rs"]),
+      "fn a() {}\nfn b() {}"
+    );
+    assert_eq!(
+      git(path, &["rev-parse", "v1"]),
+      git(path, &["rev-parse", "main"])
+    );
+    assert_eq!(
+      git(path, &["for-each-ref", "--format=%(refname)"]),
+      "refs/heads/main\nrefs/tags/v1"
+    );
+  }
+}
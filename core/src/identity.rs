@@ -0,0 +1,358 @@
+// oss-porter-core/src/identity.rs
+use crate::utils::run_git_command;
+use crate::{PorterError, ProjectConfig, Result};
+use log::{debug, warn};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A git identity (author, committer or tagger).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Identity {
+  pub name: String,
+  pub email: String,
+}
+
+impl fmt::Display for Identity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} <{}>", self.name, self.email)
+  }
+}
+
+impl Identity {
+  /// Parses `Name <email>`.
+  pub fn parse(text: &str) -> Result<Self> {
+    let text = text.trim();
+    let (name, rest) = text.split_once('<').ok_or_else(|| {
+      PorterError::Config(format!(
+        "Identity '{}' must look like 'Name <email>'.",
+        text
+      ))
+    })?;
+    let email = rest.strip_suffix('>').filter(|e| !e.contains(['<', '>']));
+    match email {
+      Some(email) if !name.trim().is_empty() => Ok(Identity {
+        name: name.trim().to_string(),
+        email: email.trim().to_string(),
+      }),
+      _ => Err(PorterError::Config(format!(
+        "Identity '{}' must look like 'Name <email>'.",
+        text
+      ))),
+    }
+  }
+}
+
+/// One mailmap line: `[Proper Name] [<proper@email>] [Commit Name] <commit@email>`.
+#[derive(Debug, Clone)]
+struct MailmapEntry {
+  proper_name: Option<String>,
+  proper_email: Option<String>,
+  commit_name: Option<String>,
+  commit_email: String,
+}
+
+impl MailmapEntry {
+  /// Parses a line in git's mailmap format. Returns `None` for blank lines and comments.
+  fn parse(line: &str) -> Result<Option<Self>> {
+    let line = match line.find('#') {
+      Some(pos) => &line[..pos],
+      None => line,
+    };
+    if line.trim().is_empty() {
+      return Ok(None);
+    }
+    // Alternating "name <email>" chunks; names are optional.
+    let mut chunks: Vec<(Option<String>, String)> = Vec::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+      let close = rest[open..].find('>').map(|p| p + open).ok_or_else(|| {
+        PorterError::Config(format!("Unclosed '<' in mailmap line '{}'", line.trim()))
+      })?;
+      let name = rest[..open].trim();
+      let name = (!name.is_empty()).then(|| name.to_string());
+      chunks.push((name, rest[open + 1..close].trim().to_string()));
+      rest = &rest[close + 1..];
+    }
+    let invalid = || PorterError::Config(format!("Invalid mailmap line '{}'", line.trim()));
+    if !rest.trim().is_empty() {
+      return Err(invalid());
+    }
+    match chunks.as_slice() {
+      // Proper Name <commit@email>
+      [(Some(name), email)] => Ok(Some(MailmapEntry {
+        proper_name: Some(name.clone()),
+        proper_email: None,
+        commit_name: None,
+        commit_email: email.clone(),
+      })),
+      // [Proper Name] <proper@email> [Commit Name] <commit@email>
+      [(proper_name, proper_email), (commit_name, commit_email)] => Ok(Some(MailmapEntry {
+        proper_name: proper_name.clone(),
+        proper_email: Some(proper_email.clone()),
+        commit_name: commit_name.clone(),
+        commit_email: commit_email.clone(),
+      })),
+      _ => Err(invalid()),
+    }
+  }
+
+  fn matches(&self, name: &str, email: &str) -> bool {
+    self.commit_email.eq_ignore_ascii_case(email)
+      && self
+        .commit_name
+        .as_deref()
+        .is_none_or(|commit_name| commit_name.eq_ignore_ascii_case(name))
+  }
+}
+
+/// Maps internal identities to public ones: mailmap entries first, then the fallback
+/// identity (if configured) for everyone not listed.
+pub struct IdentityMap {
+  entries: Vec<MailmapEntry>,
+  fallback: Option<Identity>,
+}
+
+impl IdentityMap {
+  /// Builds the map from the project's `identity_map` table. Returns `None` if it's empty.
+  /// A relative `file` is resolved against the internal repository.
+  pub fn from_config(config: &ProjectConfig) -> Result<Option<Self>> {
+    let settings = &config.identity_map;
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(file) = &settings.file {
+      let path = config.internal_repo_path.join(file);
+      let content = fs::read_to_string(&path).map_err(|e| PorterError::Io { source: e, path })?;
+      lines.extend(content.lines().map(str::to_string));
+    }
+    lines.extend(settings.entries.iter().cloned());
+
+    let mut entries = Vec::new();
+    for line in &lines {
+      if let Some(entry) = MailmapEntry::parse(line)? {
+        entries.push(entry);
+      }
+    }
+    let fallback = settings
+      .fallback
+      .as_deref()
+      .map(Identity::parse)
+      .transpose()?;
+    if entries.is_empty() && fallback.is_none() {
+      return Ok(None);
+    }
+    Ok(Some(IdentityMap { entries, fallback }))
+  }
+
+  /// Returns the public identity for `name <email>`. Entries naming the commit name win
+  /// over email-only entries, like in git.
+  pub fn map(&self, name: &str, email: &str) -> Identity {
+    let entry = self
+      .entries
+      .iter()
+      .filter(|entry| entry.matches(name, email))
+      .max_by_key(|entry| entry.commit_name.is_some());
+    match (entry, &self.fallback) {
+      (Some(entry), _) => Identity {
+        name: entry
+          .proper_name
+          .clone()
+          .unwrap_or_else(|| name.to_string()),
+        email: entry
+          .proper_email
+          .clone()
+          .unwrap_or_else(|| email.to_string()),
+      },
+      (None, Some(fallback)) => fallback.clone(),
+      (None, None) => Identity {
+        name: name.to_string(),
+        email: email.to_string(),
+      },
+    }
+  }
+
+  /// Renders a complete mailmap for the given identities (one explicit line per changed
+  /// identity), so tools without fallback support map everyone the same way.
+  pub fn expand_to_mailmap<'a>(
+    &self,
+    identities: impl IntoIterator<Item = &'a Identity>,
+  ) -> String {
+    let mut out = String::new();
+    for identity in identities {
+      let mapped = self.map(&identity.name, &identity.email);
+      if &mapped != identity {
+        out.push_str(&format!("{} {}\n", mapped, identity));
+      }
+    }
+    out
+  }
+}
+
+/// Lists every author and committer identity in the history of the repo at `repo_path`.
+pub fn history_identities(repo_path: &Path) -> Result<BTreeSet<Identity>> {
+  let output = run_git_command(
+    &["log", "--all", "--format=%an%x00%ae%n%cn%x00%ce"],
+    repo_path,
+  )?;
+  Ok(
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| line.split_once('\0'))
+      .map(|(name, email)| Identity {
+        name: name.to_string(),
+        email: email.to_string(),
+      })
+      .collect(),
+  )
+}
+
+/// Returns `-c user.name=.. -c user.email=..` arguments that make git record the mapped
+/// identity of the local user as author/committer in `repo_path`, so the operator's internal
+/// identity doesn't end up in the public history. Empty if no identity map is configured.
+pub fn local_identity_args(config: &ProjectConfig, repo_path: &Path) -> Result<Vec<String>> {
+  let Some(map) = IdentityMap::from_config(config)? else {
+    return Ok(Vec::new());
+  };
+  let identity = match run_git_command(&["var", "GIT_COMMITTER_IDENT"], repo_path) {
+    Ok(output) => {
+      let ident = String::from_utf8_lossy(&output.stdout);
+      // "Name <email> 1700000000 +0000": drop the timestamp.
+      let ident = ident.rsplitn(3, ' ').nth(2).unwrap_or_default();
+      let local = Identity::parse(ident)?;
+      map.map(&local.name, &local.email)
+    }
+    Err(e) => match &map.fallback {
+      Some(fallback) => fallback.clone(),
+      None => {
+        warn!("Could not determine the local git identity: {}", e);
+        return Ok(Vec::new());
+      }
+    },
+  };
+  debug!("Recording local commits as '{}'", identity);
+  Ok(vec![
+    "-c".to_string(),
+    format!("user.name={}", identity.name),
+    "-c".to_string(),
+    format!("user.email={}", identity.email),
+  ])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn identity(name: &str, email: &str) -> Identity {
+    Identity {
+      name: name.to_string(),
+      email: email.to_string(),
+    }
+  }
+
+  fn map(entries: &[&str], fallback: Option<&str>) -> IdentityMap {
+    let entries = entries
+      .iter()
+      .filter_map(|line| MailmapEntry::parse(line).unwrap())
+      .collect();
+    let fallback = fallback.map(|text| Identity::parse(text).unwrap());
+    IdentityMap { entries, fallback }
+  }
+
+  #[test]
+  fn parses_the_four_mailmap_forms() {
+    let map = map(
+      &[
+        "Proper Name <one@corp>",
+        "<proper@example.com> <two@corp>",
+        "Proper Three <three@example.com> <three@corp>",
+        "Proper Four <four@example.com> Commit Four <four@corp> # comment",
+      ],
+      None,
+    );
+    assert_eq!(
+      map.map("Old", "one@corp"),
+      identity("Proper Name", "one@corp")
+    );
+    assert_eq!(
+      map.map("Two", "TWO@corp"),
+      identity("Two", "proper@example.com")
+    );
+    assert_eq!(
+      map.map("Old", "three@corp"),
+      identity("Proper Three", "three@example.com")
+    );
+    assert_eq!(
+      map.map("commit four", "four@corp"),
+      identity("Proper Four", "four@example.com")
+    );
+    assert_eq!(
+      map.map("Someone Else", "four@corp"),
+      identity("Someone Else", "four@corp")
+    );
+  }
+
+  #[test]
+  fn skips_comments_and_blank_lines() {
+    assert!(MailmapEntry::parse("# just a comment").unwrap().is_none());
+    assert!(MailmapEntry::parse("   ").unwrap().is_none());
+  }
+
+  #[test]
+  fn rejects_malformed_lines() {
+    assert!(MailmapEntry::parse("Name <unclosed@corp").is_err());
+    assert!(MailmapEntry::parse("<a@corp> <b@corp> <c@corp>").is_err());
+    assert!(MailmapEntry::parse("Name <a@corp> trailing").is_err());
+    assert!(MailmapEntry::parse("<a@corp>").is_err());
+  }
+
+  #[test]
+  fn entries_with_commit_name_win_over_email_only_ones() {
+    let map = map(
+      &[
+        "Specific <specific@example.com> Dev <dev@corp>",
+        "Generic <generic@example.com> <dev@corp>",
+      ],
+      None,
+    );
+    assert_eq!(
+      map.map("Dev", "dev@corp"),
+      identity("Specific", "specific@example.com")
+    );
+    assert_eq!(
+      map.map("Other", "dev@corp"),
+      identity("Generic", "generic@example.com")
+    );
+  }
+
+  #[test]
+  fn fallback_replaces_unlisted_identities() {
+    let map = map(
+      &["Known <known@example.com> <known@corp>"],
+      Some("Team <team@example.com>"),
+    );
+    assert_eq!(
+      map.map("Anyone", "anyone@corp"),
+      identity("Team", "team@example.com")
+    );
+    let identities = [
+      identity("Known", "known@corp"),
+      identity("Team", "team@example.com"), // Unchanged, so not listed
+      identity("A", "a@corp"),
+    ];
+    assert_eq!(
+      map.expand_to_mailmap(&identities),
+      "Known <known@example.com> Known <known@corp>\nTeam <team@example.com> A <a@corp>\n"
+    );
+  }
+
+  #[test]
+  fn identity_parse_requires_name_and_email() {
+    assert_eq!(
+      Identity::parse(" Jo Dev <jo@example.com> ").unwrap(),
+      identity("Jo Dev", "jo@example.com")
+    );
+    assert!(Identity::parse("<jo@example.com>").is_err());
+    assert!(Identity::parse("Jo Dev jo@example.com").is_err());
+    assert!(Identity::parse("Jo <a> <b>").is_err());
+  }
+}
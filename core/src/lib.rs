@@ -1,8 +1,24 @@
 pub mod check;
 pub mod config;
 pub mod extract;
+pub mod filter;
+pub mod header;
+pub mod history;
+pub mod identity;
+pub mod license;
+pub mod manifest;
+pub mod message;
+pub mod patch;
+pub mod policy;
+pub mod relocation;
 pub mod remote;
+pub mod scrub;
+pub mod selection;
+pub mod session;
 pub mod state;
+#[cfg(test)]
+mod testing;
+pub mod transform;
 pub mod update;
 pub mod utils;
 
@@ -56,6 +72,10 @@ pub enum PorterError {
   },
   #[error("Failed to serialize TOML data: {0}")] // Serialization usually isn't path specific
   TomlSerialize(#[from] toml::ser::Error),
+  #[error("Scrubbing failed: {0}")]
+  Scrub(String), // Unbalanced strip markers or an unparseable cfg-gated item
+  #[error("Invalid patch: {0}")]
+  InvalidPatch(String), // A hand-edited patch that no longer parses
   #[error("Failed to create/access temporary directory: {source}")] // Specific source
   TempDir {
     #[source]
@@ -81,11 +101,140 @@ pub enum HistoryMode {
   Preserve,
 }
 
+/// How `preserve` mode rewrites the internal history.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryRewriter {
+  #[default]
+  Native, // Built-in rewriter using plain git, see `history::rewrite_history`
+  FilterRepo, // The external `git-filter-repo` tool
+}
+
+/// How `update` handles changes that reach `internal_branch` through merge commits.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+  #[default]
+  FirstParent, // Offer mainline commits only; merges and the commits they bring in are skipped
+  Flatten, // Offer the individual commits of merged branches (merges themselves are dropped)
+  Squash,  // Offer each first-parent merge as one commit with the merge's message
+}
+
 // Helper function for default branch name
 fn default_branch() -> String {
   "main".to_string()
 }
 
+pub(crate) fn default_true() -> bool {
+  true
+}
+
+fn default_begin_marker() -> String {
+  "OSS-STRIP-BEGIN".to_string()
+}
+
+fn default_end_marker() -> String {
+  "OSS-STRIP-END".to_string()
+}
+
+fn default_cfg_names() -> Vec<String> {
+  vec!["internal".to_string()]
+}
+
+/// Settings for stripping internal-only code before it is published.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ScrubConfig {
+  #[serde(default)]
+  pub enabled: bool, // Off unless explicitly turned on
+  #[serde(default = "default_begin_marker")]
+  pub begin_marker: String, // Lines from a line containing this...
+  #[serde(default = "default_end_marker")]
+  pub end_marker: String, // ...through a line containing this are removed
+  #[serde(default = "default_cfg_names")]
+  pub cfg_names: Vec<String>, // Rust items under `#[cfg(<name>)]` are removed (.rs files only)
+}
+
+impl Default for ScrubConfig {
+  fn default() -> Self {
+    ScrubConfig {
+      enabled: false,
+      begin_marker: default_begin_marker(),
+      end_marker: default_end_marker(),
+      cfg_names: default_cfg_names(),
+    }
+  }
+}
+
+/// How license headers in published source files are handled.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderMode {
+  #[default]
+  Off,
+  Add,     // Insert the header where no license header exists
+  Replace, // Also replace existing (differing) license headers
+  Verify,  // Never modify files, only report those without the header
+}
+
+/// Settings for the license header at the top of published source files.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LicenseHeaderConfig {
+  #[serde(default)]
+  pub mode: HeaderMode,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub template: Vec<String>, // Header lines without comment markers; {license}, {holder}, {year}
+  pub year: Option<String>, // Value for {year} (defaults to the current year)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub extensions: Vec<String>, // Only these file extensions (empty = all with known comment syntax)
+}
+
+/// Maps internal author/committer identities to public ones, see `identity::IdentityMap`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct IdentityMapConfig {
+  pub file: Option<PathBuf>, // Mailmap file (relative paths resolve against internal_repo_path)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub entries: Vec<String>, // Inline mailmap lines, e.g. "Jane Doe <jane@example.org> <jane@corp.internal>"
+  pub fallback: Option<String>, // "Team <oss@company.com>" for every identity not mapped above
+}
+
+/// A rewrite rule for published commit messages, see `message::MessageRules`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageRule {
+  pub pattern: String, // Regex (Rust `regex` syntax)
+  #[serde(default)]
+  pub replace: String, // Replacement for every match ("" strips it); `$1` for capture groups
+  #[serde(default)]
+  pub drop_line: bool, // Remove each whole line matching `pattern` instead
+}
+
+/// What `update` does with a commit matched by a `SelectionRule`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionAction {
+  Apply, // Apply without asking
+  Skip,  // Skip permanently without asking
+  #[default]
+  Review, // Ask, as for unmatched commits
+}
+
+/// Marks internal commits for (or against) publication, see `selection::SelectionRules`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SelectionRule {
+  pub trailer: Option<String>, // "Key: value" trailer, or "Key" for any value (keys ignore case)
+  pub tag: Option<String>,     // Text anywhere in the message, e.g. "[no-oss]" (ignores case)
+  pub action: SelectionAction,
+}
+
+/// Published source for an internal path dependency, see `ProjectConfig::dependency_rewrites`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DependencyRewrite {
+  pub version: Option<String>, // crates.io version requirement, e.g. "0.3"
+  pub git: Option<String>,     // Or a git repository URL...
+  pub branch: Option<String>,  // ...optionally pinned to a branch, tag or rev
+  pub tag: Option<String>,
+  pub rev: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ProjectConfig {
   pub internal_repo_path: PathBuf,
@@ -94,14 +243,47 @@ pub struct ProjectConfig {
   pub public_repo_url: Option<String>,
   #[serde(default)] // Defaults to CleanSlate if missing
   pub history_mode: HistoryMode,
-  pub license: Option<String>, // Specific license for this project
-                               // Add tags, description etc. later if needed
+  #[serde(default)] // Native unless `filter_repo` is configured
+  pub history_rewriter: HistoryRewriter,
+  pub license: Option<String>, // SPDX expression for this project, e.g. "MIT OR Apache-2.0"
+  pub copyright_holder: Option<String>, // Name used in license texts (defaults to the crate's first author)
+  // Add tags, description etc. later if needed
   // New Branch Configuration
   #[serde(default = "default_branch")] // Use helper for default value "main"
   pub internal_branch: String, // Branch to track in the internal repo for updates
 
+  #[serde(default)] // Follow the first-parent line unless `flatten` or `squash` is configured
+  pub merge_strategy: MergeStrategy,
+
   #[serde(default = "default_branch")] // Use helper for default value "main"
-  pub public_branch: String,   // Branch to push to in the public repo
+  pub public_branch: String, // Branch to push to in the public repo
+
+  // Path filters, globs relative to project_subdir (e.g. "internal_docs/**", "*.env", "deploy/")
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub include: Vec<String>, // Only publish matching files (empty = everything)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub exclude: Vec<String>, // Never publish matching files, even if included
+
+  #[serde(default)] // Scrubbing is off unless `enabled = true` is set
+  pub scrub: ScrubConfig,
+
+  #[serde(default)] // Off unless a mode is set
+  pub license_header: LicenseHeaderConfig,
+
+  #[serde(default)] // Identities are kept verbatim unless mapped
+  pub identity_map: IdentityMapConfig,
+
+  // Regex rewrites for published commit messages (ticket keys, internal links, trailers)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub message_rules: Vec<MessageRule>,
+
+  // Trailer/tag rules that let `update` apply or skip marked commits without asking
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub selection_rules: Vec<SelectionRule>,
+
+  // Opt-in: rewrite `path =` dependencies pointing outside the project, keyed by dependency name
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub dependency_rewrites: HashMap<String, DependencyRewrite>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
@@ -118,13 +300,46 @@ pub struct ExtractionResult {
   pub output_path: PathBuf,
   pub messages: Vec<String>, // Log messages or warnings during extraction
   pub secrets_found: Vec<String>, // List of potential secrets found
+  pub stripped_regions: Vec<scrub::StrippedRegion>, // Internal-only regions removed before committing
+  pub synced_commit: Option<String>, // Internal commit recorded as the last synced one
 }
 
+/// What `extract` would do, computed without touching `output_path` (see `extract::plan_extraction`).
 #[derive(Debug)]
+pub struct ExtractionPlan {
+  pub project_id: String,
+  pub output_path: PathBuf,
+  pub history_mode: HistoryMode,
+  pub files_to_copy: Vec<PathBuf>,  // Relative to project_subdir
+  pub files_excluded: Vec<PathBuf>, // Rejected by the include/exclude filters
+  pub license_actions: Vec<String>, // License files that would be written or kept
+  pub gitignore_action: String,
+  pub transform_notes: Vec<String>, // Header and manifest rewrites
+  pub stripped_regions: Vec<scrub::StrippedRegion>,
+  pub secrets_found: Vec<String>, // Scan of the transformed files
+  pub dependency_findings: Vec<String>, // Internal path deps and leftover workspace inheritance
+  pub surviving_commits: Option<usize>, // Preserve mode: commits left after filtering
+  pub warnings: Vec<String>,      // Problems that would make the real extraction fail
+}
+
+#[derive(Serialize, Debug)]
 pub struct CheckResult {
   pub project_id: String,
   pub secrets_found: Vec<String>,
   pub internal_deps_found: Vec<String>,
+  pub workspace_inherited_found: Vec<String>, // Manifest keys still set to `workspace = true`
+  pub missing_headers: Vec<String>,           // Source files without the expected license header
   pub license_ok: bool,
   // Add other check results
 }
+
+impl CheckResult {
+  /// Returns true if no check reported a problem.
+  pub fn passed(&self) -> bool {
+    self.secrets_found.is_empty()
+      && self.internal_deps_found.is_empty()
+      && self.workspace_inherited_found.is_empty()
+      && self.missing_headers.is_empty()
+      && self.license_ok
+  }
+}
@@ -0,0 +1,206 @@
+// oss-porter-core/src/license.rs
+use crate::manifest::parse_document;
+use crate::utils::current_year;
+use crate::{PorterError, Result};
+use log::{debug, info};
+use std::{fs, path::Path};
+
+/// A license whose text is bundled with oss-porter.
+pub struct BundledLicense {
+  pub id: &'static str,          // Canonical SPDX identifier
+  pub file_suffix: &'static str, // File name used next to other licenses, e.g. LICENSE-APACHE
+  text: &'static str,            // May contain [year] and [fullname] placeholders
+}
+
+impl BundledLicense {
+  /// Returns the license text with the copyright year and holder filled in.
+  pub fn render(&self, year: i64, holder: &str) -> String {
+    self
+      .text
+      .replace("[year]", &year.to_string())
+      .replace("[fullname]", holder)
+  }
+}
+
+macro_rules! bundled {
+  ($id:literal, $suffix:literal) => {
+    BundledLicense {
+      id: $id,
+      file_suffix: $suffix,
+      text: include_str!(concat!("../licenses/", $id, ".txt")),
+    }
+  };
+}
+
+/// Bundled license texts (common OSI-approved licenses plus public-domain dedications).
+pub const BUNDLED_LICENSES: &[BundledLicense] = &[
+  bundled!("MIT", "MIT"),
+  bundled!("Apache-2.0", "APACHE"),
+  bundled!("BSD-2-Clause", "BSD-2-CLAUSE"),
+  bundled!("BSD-3-Clause", "BSD-3-CLAUSE"),
+  bundled!("ISC", "ISC"),
+  bundled!("0BSD", "0BSD"),
+  bundled!("Zlib", "ZLIB"),
+  bundled!("BSL-1.0", "BSL"),
+  bundled!("MPL-2.0", "MPL"),
+  bundled!("GPL-2.0", "GPL-2.0"),
+  bundled!("GPL-3.0", "GPL-3.0"),
+  bundled!("LGPL-2.1", "LGPL-2.1"),
+  bundled!("LGPL-3.0", "LGPL-3.0"),
+  bundled!("Unlicense", "UNLICENSE"),
+  bundled!("CC0-1.0", "CC0"),
+];
+
+/// Looks up a bundled license by SPDX identifier (case-insensitive). The `-only`,
+/// `-or-later` and `+` variants of the GNU licenses share the base text.
+pub fn find_license(id: &str) -> Option<&'static BundledLicense> {
+  let base = id
+    .trim_end_matches('+')
+    .trim_end_matches("-only")
+    .trim_end_matches("-or-later");
+  BUNDLED_LICENSES
+    .iter()
+    .find(|license| license.id.eq_ignore_ascii_case(base))
+}
+
+/// Splits an SPDX expression such as `MIT OR Apache-2.0` or `(MIT AND Zlib) WITH x`
+/// into its license identifiers, in order and without duplicates. Exceptions named after
+/// `WITH` are returned separately. The legacy Cargo `MIT/Apache-2.0` form is accepted.
+pub fn parse_expression(expression: &str) -> Result<(Vec<String>, Vec<String>)> {
+  let mut ids: Vec<String> = Vec::new();
+  let mut exceptions = Vec::new();
+  let spaced = expression.replace(['(', ')', '/'], " ");
+  let mut tokens = spaced.split_whitespace();
+  while let Some(token) = tokens.next() {
+    match token {
+      "OR" | "AND" | "or" | "and" => {}
+      "WITH" | "with" => {
+        if let Some(exception) = tokens.next() {
+          exceptions.push(exception.to_string());
+        }
+      }
+      id => {
+        if !ids.iter().any(|known| known == id) {
+          ids.push(id.to_string());
+        }
+      }
+    }
+  }
+  if ids.is_empty() {
+    return Err(PorterError::Config(format!(
+      "License expression '{}' does not name any license.",
+      expression
+    )));
+  }
+  Ok((ids, exceptions))
+}
+
+/// Picks the copyright holder: the configured one, else the first `authors` entry of the
+/// published Cargo.toml (without its e-mail), else "The <project> Authors".
+fn copyright_holder(project_id: &str, holder: Option<&str>, output_path: &Path) -> String {
+  if let Some(holder) = holder.map(str::trim).filter(|h| !h.is_empty()) {
+    return holder.to_string();
+  }
+  let manifest_path = output_path.join("Cargo.toml");
+  let author = fs::read_to_string(&manifest_path)
+    .ok()
+    .and_then(|content| parse_document(&manifest_path, &content).ok())
+    .and_then(|doc| {
+      let authors = doc.get("package")?.get("authors")?.as_array()?;
+      let first = authors.iter().next()?.as_str()?;
+      let name = first.split('<').next().unwrap_or(first).trim().to_string();
+      (!name.is_empty()).then_some(name)
+    });
+  author.unwrap_or_else(|| format!("The {} Authors", project_id))
+}
+
+/// Writes the license file(s) for `expression` into `output_path`, with the current year
+/// and copyright holder filled in. A single license goes to `LICENSE`; several (e.g.
+/// `MIT OR Apache-2.0`) go to `LICENSE-<NAME>` files. Existing files are never overwritten.
+/// Returns messages describing what was written or skipped.
+pub fn write_license_files(
+  project_id: &str,
+  expression: &str,
+  holder: Option<&str>,
+  output_path: &Path,
+) -> Result<Vec<String>> {
+  let (ids, exceptions) = parse_expression(expression)?;
+  let holder = copyright_holder(project_id, holder, output_path);
+  let year = current_year();
+  let mut messages = Vec::new();
+
+  for id in &ids {
+    let Some(license) = find_license(id) else {
+      messages.push(format!(
+        "Warning: no bundled text for license '{}'; add its license file manually.",
+        id
+      ));
+      continue;
+    };
+    let file_name = if ids.len() == 1 {
+      "LICENSE".to_string()
+    } else {
+      format!("LICENSE-{}", license.file_suffix)
+    };
+    let license_path = output_path.join(&file_name);
+    if license_path.exists() {
+      info!("{} already exists, skipping creation.", file_name);
+      messages.push(format!("Kept existing {}.", file_name));
+      continue;
+    }
+    debug!("Writing {} text for '{}' ({})", license.id, holder, year);
+    fs::write(&license_path, license.render(year, &holder)).map_err(|err| PorterError::Io {
+      source: err,
+      path: license_path.clone(),
+    })?;
+    info!("Created license file: {}", license_path.display());
+    messages.push(format!("Added {} ({} license).", file_name, license.id));
+  }
+  for exception in exceptions {
+    messages.push(format!(
+      "Warning: license exception '{}' is not bundled; add its text manually.",
+      exception
+    ));
+  }
+  Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+  }
+
+  #[test]
+  fn parse_expression_lists_ids_and_exceptions() {
+    let (ids, exceptions) =
+      parse_expression("(MIT AND Zlib) OR Apache-2.0 WITH LLVM-exception OR MIT").unwrap();
+    assert_eq!(ids, strings(&["MIT", "Zlib", "Apache-2.0"]));
+    assert_eq!(exceptions, strings(&["LLVM-exception"]));
+    assert_eq!(
+      parse_expression("MIT/Apache-2.0").unwrap().0,
+      strings(&["MIT", "Apache-2.0"])
+    );
+    assert!(parse_expression(" ( OR ) ").is_err());
+  }
+
+  #[test]
+  fn find_license_accepts_gnu_variants_and_any_case() {
+    assert_eq!(
+      find_license("gpl-3.0-or-later").map(|l| l.id),
+      Some("GPL-3.0")
+    );
+    assert_eq!(find_license("LGPL-2.1+").map(|l| l.id), Some("LGPL-2.1"));
+    assert_eq!(find_license("mit").map(|l| l.file_suffix), Some("MIT"));
+    assert!(find_license("Proprietary").is_none());
+  }
+
+  #[test]
+  fn render_fills_in_year_and_holder() {
+    let text = find_license("MIT").unwrap().render(2024, "Example Org");
+    assert!(text.contains("2024") && text.contains("Example Org"));
+    assert!(!text.contains("[year]") && !text.contains("[fullname]"));
+  }
+}
@@ -0,0 +1,853 @@
+// oss-porter-core/src/manifest.rs
+use crate::utils::run_git_command;
+use crate::{DependencyRewrite, PorterError, ProjectConfig, Result};
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use toml_edit::{value, Array, DocumentMut, InlineTable, Item, TableLike, Value};
+
+const DEPENDENCY_SECTIONS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Keys a rewrite may set; existing values for these are replaced, not merged.
+const SOURCE_KEYS: [&str; 6] = ["path", "version", "git", "branch", "tag", "rev"];
+
+/// Returns true if `file_name` is a Cargo manifest.
+pub fn is_manifest(path: &Path) -> bool {
+  path.file_name().is_some_and(|name| name == "Cargo.toml")
+}
+
+/// Parses a manifest for format-preserving edits.
+pub(crate) fn parse_document(manifest_path: &Path, content: &str) -> Result<DocumentMut> {
+  content.parse::<DocumentMut>().map_err(|e| {
+    PorterError::Config(format!(
+      "Failed to parse {}: {}",
+      manifest_path.display(),
+      e
+    ))
+  })
+}
+
+/// Rewrites `path = ...` dependencies that point outside the published tree into
+/// registry (`version`) or `git` dependencies, using the project's `dependency_rewrites`
+/// table. `manifest_path` is relative to the published root and is used to resolve the
+/// dependency paths. Entries are matched by dependency key, then by `package`.
+/// Other keys (features, optional, ...) and the surrounding formatting are kept.
+/// Returns the new manifest text and a note for every rewritten dependency.
+pub fn rewrite_path_dependencies(
+  manifest_path: &Path,
+  content: &str,
+  rewrites: &HashMap<String, DependencyRewrite>,
+) -> Result<(String, Vec<String>)> {
+  let mut doc = parse_document(manifest_path, content)?;
+  let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+  let mut notes = Vec::new();
+
+  for (section_name, section) in dependency_tables(&mut doc) {
+    for (dep_key, dep_item) in section.iter_mut() {
+      let Some(dep) = dep_item.as_table_like_mut() else {
+        continue; // `foo = "1.0"` has no path
+      };
+      let Some(dep_path) = dep.get("path").and_then(Item::as_str).map(str::to_string) else {
+        continue;
+      };
+      let package = dep
+        .get("package")
+        .and_then(Item::as_str)
+        .unwrap_or(dep_key.get())
+        .to_string();
+      let Some(rewrite) = rewrites
+        .get(dep_key.get())
+        .or_else(|| rewrites.get(&package))
+      else {
+        continue;
+      };
+      if !escapes_root(manifest_dir, Path::new(&dep_path)) {
+        debug!(
+          "Path dependency '{}' ('{}') stays inside the published tree; not rewriting.",
+          dep_key.get(),
+          dep_path
+        );
+        continue;
+      }
+      if rewrite.version.is_none() && rewrite.git.is_none() {
+        return Err(PorterError::Config(format!(
+          "dependency_rewrites entry for '{}' needs a 'version' or 'git' value.",
+          package
+        )));
+      }
+      replace_source(dep, rewrite);
+      notes.push(format!(
+        "{}: rewrote [{}] '{}' (path '{}') to {}",
+        manifest_path.display(),
+        section_name,
+        dep_key.get(),
+        dep_path,
+        describe(rewrite)
+      ));
+    }
+  }
+  Ok((doc.to_string(), notes))
+}
+
+/// Collects every dependency table in the manifest with a display name:
+/// top-level sections, `[workspace.dependencies]` and `[target.'cfg'.*]` sections.
+fn dependency_tables(doc: &mut DocumentMut) -> Vec<(String, &mut dyn TableLike)> {
+  let mut tables: Vec<(String, &mut dyn TableLike)> = Vec::new();
+  let root = doc.as_table_mut();
+  for (key, item) in root.iter_mut() {
+    let key = key.get().to_string();
+    if DEPENDENCY_SECTIONS.contains(&key.as_str()) {
+      if let Some(table) = item.as_table_like_mut() {
+        tables.push((key, table));
+      }
+    } else if key == "workspace" {
+      if let Some(deps) = item
+        .as_table_like_mut()
+        .and_then(|ws| ws.get_mut("dependencies"))
+        .and_then(Item::as_table_like_mut)
+      {
+        tables.push(("workspace.dependencies".to_string(), deps));
+      }
+    } else if key == "target" {
+      let Some(targets) = item.as_table_like_mut() else {
+        continue;
+      };
+      for (target, target_item) in targets.iter_mut() {
+        let target = target.get().to_string();
+        let Some(target_table) = target_item.as_table_like_mut() else {
+          continue;
+        };
+        for (section, section_item) in target_table.iter_mut() {
+          if !DEPENDENCY_SECTIONS.contains(&section.get()) {
+            continue;
+          }
+          let name = format!("target.{}.{}", target, section.get());
+          if let Some(table) = section_item.as_table_like_mut() {
+            tables.push((name, table));
+          }
+        }
+      }
+    }
+  }
+  tables
+}
+
+/// Replaces the source keys of a dependency with the rewrite, keeping every other key
+/// in its original order. The new keys take the position of the old `path` key.
+fn replace_source(dep: &mut dyn TableLike, rewrite: &DependencyRewrite) {
+  let entries: Vec<(String, Item)> = dep
+    .iter()
+    .map(|(key, item)| (key.to_string(), item.clone()))
+    .collect();
+  dep.clear();
+  for (key, item) in entries {
+    if key == "path" {
+      if let Some(git) = &rewrite.git {
+        dep.insert("git", value(git.as_str()));
+      }
+      for (name, val) in [
+        ("branch", &rewrite.branch),
+        ("tag", &rewrite.tag),
+        ("rev", &rewrite.rev),
+        ("version", &rewrite.version),
+      ] {
+        if let Some(val) = val {
+          dep.insert(name, value(val.as_str()));
+        }
+      }
+    } else if !SOURCE_KEYS.contains(&key.as_str())
+      || (key == "version" && rewrite.version.is_none())
+    {
+      dep.insert(&key, item);
+    }
+  }
+  dep.fmt();
+}
+
+fn describe(rewrite: &DependencyRewrite) -> String {
+  let mut parts = Vec::new();
+  if let Some(version) = &rewrite.version {
+    parts.push(format!("version \"{}\"", version));
+  }
+  if let Some(git) = &rewrite.git {
+    parts.push(format!("git \"{}\"", git));
+  }
+  for (name, val) in [
+    ("branch", &rewrite.branch),
+    ("tag", &rewrite.tag),
+    ("rev", &rewrite.rev),
+  ] {
+    if let Some(val) = val {
+      parts.push(format!("{} \"{}\"", name, val));
+    }
+  }
+  parts.join(", ")
+}
+
+/// Lexically resolves `dep_path` against `base` (both relative to the published root)
+/// and returns true if the result leaves the root.
+pub(crate) fn escapes_root(base: &Path, dep_path: &Path) -> bool {
+  dep_path.is_absolute() || normalize(&base.join(dep_path)).is_none()
+}
+
+/// Lexically normalizes a relative path (`a/./b/../c` -> `a/c`).
+/// Returns `None` if the path is absolute or climbs above its starting point.
+fn normalize(path: &Path) -> Option<PathBuf> {
+  let mut resolved = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::ParentDir => {
+        if !resolved.pop() {
+          return None;
+        }
+      }
+      Component::Normal(part) => resolved.push(part),
+      Component::CurDir => {}
+      Component::RootDir | Component::Prefix(_) => return None,
+    }
+  }
+  Some(resolved)
+}
+
+/// Returns the relative path leading from directory `from` to `to` (both normalized).
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+  let from: Vec<Component> = from.components().collect();
+  let to: Vec<Component> = to.components().collect();
+  let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+  let mut result = PathBuf::new();
+  for _ in common..from.len() {
+    result.push("..");
+  }
+  for part in &to[common..] {
+    result.push(part);
+  }
+  if result.as_os_str().is_empty() {
+    result.push(".");
+  }
+  result
+}
+
+/// Sets `package.license` to the project's SPDX expression and drops `license-file`,
+/// which Cargo doesn't expect next to it. Manifests without a `[package]` are unchanged.
+pub fn set_package_license(
+  manifest_path: &Path,
+  content: &str,
+  expression: &str,
+) -> Result<(String, Vec<String>)> {
+  let mut doc = parse_document(manifest_path, content)?;
+  let Some(package) = doc.get_mut("package").and_then(Item::as_table_like_mut) else {
+    return Ok((content.to_string(), Vec::new()));
+  };
+  let current = package
+    .get("license")
+    .and_then(Item::as_str)
+    .map(str::to_string);
+  let mut notes = Vec::new();
+  if current.as_deref() != Some(expression) {
+    let mut new_value = value(expression);
+    if let (Some(new), Some(old)) = (
+      new_value.as_value_mut(),
+      package.get("license").and_then(Item::as_value),
+    ) {
+      *new.decor_mut() = old.decor().clone();
+    }
+    package.insert("license", new_value);
+    notes.push(match current {
+      Some(current) => format!(
+        "{}: set package.license to \"{}\" (was \"{}\")",
+        manifest_path.display(),
+        expression,
+        current
+      ),
+      None => format!(
+        "{}: set package.license to \"{}\"",
+        manifest_path.display(),
+        expression
+      ),
+    });
+  }
+  if package.remove("license-file").is_some() {
+    notes.push(format!(
+      "{}: removed package.license-file in favor of package.license",
+      manifest_path.display()
+    ));
+  }
+  if notes.is_empty() {
+    return Ok((content.to_string(), notes));
+  }
+  Ok((doc.to_string(), notes))
+}
+
+// --- Workspace inheritance ---
+
+/// Package fields whose inherited value is a path relative to the workspace root.
+const INHERITED_PATH_FIELDS: [&str; 2] = ["readme", "license-file"];
+
+/// The root manifest of the internal Cargo workspace that encloses `project_subdir`.
+/// Published crates can't reach it, so whatever they inherit from it has to be inlined.
+pub struct WorkspaceRoot {
+  dir: PathBuf,            // Workspace root, relative to the internal repo root
+  published_root: PathBuf, // Normalized `project_subdir`
+  doc: DocumentMut,
+}
+
+impl WorkspaceRoot {
+  /// Finds the nearest directory above `project_subdir` whose `Cargo.toml` has a
+  /// `[workspace]` table, like Cargo does. The manifests are read from the working tree,
+  /// or from commit `rev` of the internal repo if given.
+  pub fn find(config: &ProjectConfig, rev: Option<&str>) -> Result<Option<Self>> {
+    let published_root = normalize(&config.project_subdir).ok_or_else(|| {
+      PorterError::Config(format!(
+        "project_subdir '{}' must stay inside the internal repository.",
+        config.project_subdir.display()
+      ))
+    })?;
+    for dir in published_root.ancestors().skip(1) {
+      let manifest_path = dir.join("Cargo.toml");
+      let Some(content) = read_internal_file(config, rev, &manifest_path)? else {
+        continue;
+      };
+      let doc = parse_document(&manifest_path, &content)?;
+      if doc.get("workspace").is_some_and(Item::is_table_like) {
+        debug!(
+          "Found enclosing workspace root at '{}'",
+          manifest_path.display()
+        );
+        return Ok(Some(WorkspaceRoot {
+          dir: dir.to_path_buf(),
+          published_root,
+          doc,
+        }));
+      }
+    }
+    Ok(None)
+  }
+
+  fn manifest_display(&self) -> String {
+    self.dir.join("Cargo.toml").display().to_string()
+  }
+
+  /// Looks up `workspace.<table>.<key>` in the root manifest.
+  fn inherited(&self, table: &str, key: &str) -> Option<&Item> {
+    self.doc.get("workspace")?.get(table)?.get(key)
+  }
+
+  /// Rebases a path relative to the workspace root onto `member_dir`
+  /// (relative to the internal repo root). Absolute paths are kept.
+  fn rebase(&self, path: &str, member_dir: &Path) -> String {
+    if Path::new(path).is_absolute() {
+      return path.to_string();
+    }
+    match normalize(&self.dir.join(path)) {
+      Some(target) => relative_path(member_dir, &target)
+        .to_string_lossy()
+        .replace('\\', "/"),
+      None => path.to_string(),
+    }
+  }
+}
+
+/// Reads a file of the internal repo (path relative to its root), from the working tree
+/// or from commit `rev`. Returns `None` if the file doesn't exist.
+fn read_internal_file(
+  config: &ProjectConfig,
+  rev: Option<&str>,
+  rel_path: &Path,
+) -> Result<Option<String>> {
+  let Some(rev) = rev else {
+    let path = config.internal_repo_path.join(rel_path);
+    if !path.is_file() {
+      return Ok(None);
+    }
+    return fs::read_to_string(&path)
+      .map(Some)
+      .map_err(|e| PorterError::Io { source: e, path });
+  };
+  let rel_path = rel_path.to_string_lossy();
+  let listed = run_git_command(
+    &["ls-tree", "--name-only", rev, "--", &rel_path],
+    &config.internal_repo_path,
+  )?;
+  if listed.stdout.is_empty() {
+    return Ok(None);
+  }
+  let spec = format!("{}:{}", rev, rel_path);
+  let output = run_git_command(&["show", &spec], &config.internal_repo_path)?;
+  Ok(Some(String::from_utf8_lossy(&output.stdout).to_string()))
+}
+
+fn is_inherited(item: &Item) -> bool {
+  item
+    .as_table_like()
+    .and_then(|t| t.get("workspace"))
+    .and_then(Item::as_bool)
+    .unwrap_or(false)
+}
+
+/// Replaces every `workspace = true` package field, dependency and `[lints]` table in the
+/// manifest at `manifest_path` (relative to the published root) with the value from the
+/// enclosing workspace root. Dependency `features` are merged and other member keys
+/// (e.g. `optional`) kept; paths are rebased onto the manifest's directory.
+/// Manifests that are workspace roots themselves are left alone.
+/// Returns the new manifest text and notes describing the changes.
+pub fn inline_workspace_inheritance(
+  manifest_path: &Path,
+  content: &str,
+  workspace: &WorkspaceRoot,
+) -> Result<(String, Vec<String>)> {
+  let mut doc = parse_document(manifest_path, content)?;
+  if doc.get("workspace").is_some() {
+    return Ok((content.to_string(), Vec::new()));
+  }
+  let member_dir = workspace
+    .published_root
+    .join(manifest_path.parent().unwrap_or(Path::new("")));
+  let missing = |what: &str, key: &str| {
+    PorterError::Config(format!(
+      "{} inherits {} '{}', but {} does not define it.",
+      manifest_path.display(),
+      what,
+      key,
+      workspace.manifest_display()
+    ))
+  };
+  let mut notes = Vec::new();
+  let mut fields = 0;
+  let mut dependencies = 0;
+
+  // [package] fields, e.g. `version.workspace = true`
+  if let Some(package) = doc.get_mut("package").and_then(Item::as_table_like_mut) {
+    if package.remove("workspace").is_some() {
+      notes.push(format!(
+        "{}: removed 'package.workspace' (the workspace root is not published)",
+        manifest_path.display()
+      ));
+    }
+    for (key, item) in package.iter_mut() {
+      if !is_inherited(item) {
+        continue;
+      }
+      let mut inherited = workspace
+        .inherited("package", key.get())
+        .ok_or_else(|| missing("package field", key.get()))?
+        .clone();
+      if INHERITED_PATH_FIELDS.contains(&key.get()) {
+        if let Some(path) = inherited.as_str() {
+          let rebased = workspace.rebase(path, &member_dir);
+          if escapes_root(
+            manifest_path.parent().unwrap_or(Path::new("")),
+            Path::new(&rebased),
+          ) {
+            notes.push(format!(
+              "{}: inherited '{}' points outside the published tree ('{}'); copy the file in or override it",
+              manifest_path.display(),
+              key.get(),
+              rebased
+            ));
+          }
+          inherited = value(rebased);
+        }
+      }
+      if let Some(new) = inherited.as_value_mut() {
+        match item.as_value() {
+          Some(old) => *new.decor_mut() = old.decor().clone(),
+          None => new.decor_mut().clear(),
+        }
+      }
+      *item = inherited;
+      fields += 1;
+    }
+  }
+
+  // Dependencies, e.g. `serde = { workspace = true, features = ["derive"] }`
+  for (_, section) in dependency_tables(&mut doc) {
+    for (dep_key, dep_item) in section.iter_mut() {
+      if !is_inherited(dep_item) {
+        continue;
+      }
+      let inherited = workspace
+        .inherited("dependencies", dep_key.get())
+        .ok_or_else(|| missing("dependency", dep_key.get()))?;
+      let merged = merge_dependency(inherited, dep_item, workspace, &member_dir);
+      let decor = dep_item.as_value().map(|v| v.decor().clone());
+      if let Some(table) = dep_item.as_table_mut().filter(|t| !t.is_dotted()) {
+        // `[dependencies.foo]` header style: keep the table, replace its keys.
+        table.clear();
+        for (key, val) in merged.iter() {
+          table.insert(key, value(val.clone()));
+        }
+      } else {
+        let mut new_value = simplify_dependency(merged);
+        if let Some(decor) = decor {
+          *new_value.decor_mut() = decor;
+        }
+        *dep_item = Item::Value(new_value);
+      }
+      dependencies += 1;
+    }
+  }
+
+  // [lints] workspace = true
+  if doc.get("lints").is_some_and(is_inherited) {
+    let lints = workspace
+      .doc
+      .get("workspace")
+      .and_then(|ws| ws.get("lints"))
+      .ok_or_else(|| missing("table", "lints"))?
+      .clone();
+    doc["lints"] = lints;
+    fields += 1;
+  }
+
+  if fields + dependencies > 0 {
+    notes.insert(
+      0,
+      format!(
+        "{}: inlined {} package field(s) and {} dependency(ies) inherited from workspace {}",
+        manifest_path.display(),
+        fields,
+        dependencies,
+        workspace.manifest_display()
+      ),
+    );
+  }
+  if notes.is_empty() {
+    return Ok((content.to_string(), notes));
+  }
+  Ok((doc.to_string(), notes))
+}
+
+/// Combines a `[workspace.dependencies]` entry with the member's `{ workspace = true, .. }`
+/// entry: member `features` are added to the inherited ones, other member keys win.
+fn merge_dependency(
+  inherited: &Item,
+  member: &Item,
+  workspace: &WorkspaceRoot,
+  member_dir: &Path,
+) -> InlineTable {
+  let mut merged = InlineTable::new();
+  if let Some(version) = inherited.as_str() {
+    merged.insert("version", version.into());
+  } else if let Some(table) = inherited.as_table_like() {
+    for (key, item) in table.iter() {
+      let Some(val) = item.as_value() else {
+        continue;
+      };
+      let val = match (key, val.as_str()) {
+        ("path", Some(path)) => workspace.rebase(path, member_dir).into(),
+        _ => val.clone(),
+      };
+      merged.insert(key, val);
+    }
+  }
+  if let Some(table) = member.as_table_like() {
+    for (key, item) in table.iter() {
+      let Some(val) = item.as_value() else {
+        continue;
+      };
+      match key {
+        "workspace" => {}
+        "features" => {
+          let mut features: Array = merged
+            .get("features")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+          for feature in val.as_array().into_iter().flatten() {
+            if !features.iter().any(|f| f.as_str() == feature.as_str()) {
+              features.push(feature.clone());
+            }
+          }
+          merged.insert("features", Value::Array(features));
+        }
+        _ => {
+          merged.insert(key, val.clone());
+        }
+      }
+    }
+  }
+  merged.fmt();
+  merged
+}
+
+/// `{ version = "1" }` becomes `"1"`; anything else stays an inline table.
+fn simplify_dependency(dep: InlineTable) -> Value {
+  if dep.len() == 1 {
+    if let Some(version) = dep.get("version").and_then(Value::as_str) {
+      return version.into();
+    }
+  }
+  Value::InlineTable(dep)
+}
+
+/// Lists every key in the manifest still set to `workspace = true`, e.g. `package.version`
+/// or `dependencies.serde`. Such manifests only build inside the workspace they came from.
+pub fn find_inherited_keys(manifest_path: &Path, content: &str) -> Result<Vec<String>> {
+  let doc = parse_document(manifest_path, content)?;
+  let mut found = Vec::new();
+  collect_inherited(doc.as_item(), "", &mut found);
+  Ok(found)
+}
+
+fn collect_inherited(item: &Item, prefix: &str, found: &mut Vec<String>) {
+  let Some(table) = item.as_table_like() else {
+    return;
+  };
+  if is_inherited(item) {
+    found.push(prefix.to_string());
+    return;
+  }
+  for (key, child) in table.iter() {
+    if prefix.is_empty() && key == "workspace" {
+      continue; // The workspace root's own [workspace] table
+    }
+    let path = if prefix.is_empty() {
+      key.to_string()
+    } else {
+      format!("{}.{}", prefix, key)
+    };
+    collect_inherited(child, &path, found);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rewrites(entries: &[(&str, DependencyRewrite)]) -> HashMap<String, DependencyRewrite> {
+    entries
+      .iter()
+      .map(|(name, rewrite)| (name.to_string(), rewrite.clone()))
+      .collect()
+  }
+
+  fn version(version: &str) -> DependencyRewrite {
+    DependencyRewrite {
+      version: Some(version.to_string()),
+      ..Default::default()
+    }
+  }
+
+  const MEMBER: &str = r#"[package]
+name = "foo"
+
+[dependencies]
+util = { path = "../util", features = ["x"] } # keep me
+inner = { path = "inner" }
+my-log = { path = "../log", package = "log" }
+serde = "1"
+
+[target.'cfg(unix)'.dev-dependencies]
+helper = { path = "../../helper", version = "0.1" }
+"#;
+
+  #[test]
+  fn path_dependencies_leaving_the_tree_are_rewritten() {
+    let git = DependencyRewrite {
+      git: Some("https://example.com/helper.git".to_string()),
+      tag: Some("v1".to_string()),
+      ..Default::default()
+    };
+    let rewrites = rewrites(&[
+      ("util", version("0.3")),
+      ("inner", version("9")),
+      ("log", version("0.4")),
+      ("helper", git),
+    ]);
+    let (text, notes) =
+      rewrite_path_dependencies(Path::new("Cargo.toml"), MEMBER, &rewrites).unwrap();
+    assert!(text.contains(r#"util = { version = "0.3", features = ["x"] } # keep me"#));
+    assert!(text.contains(r#"inner = { path = "inner" }"#));
+    assert!(text.contains(r#"my-log = { version = "0.4", package = "log" }"#));
+    assert!(text.contains(
+      r#"helper = { git = "https://example.com/helper.git", tag = "v1", version = "0.1" }"#
+    ));
+    assert_eq!(notes.len(), 3);
+    assert_eq!(
+      notes[0],
+      r#"Cargo.toml: rewrote [dependencies] 'util' (path '../util') to version "0.3""#
+    );
+    assert!(notes[2].contains("[target.cfg(unix).dev-dependencies] 'helper'"));
+  }
+
+  #[test]
+  fn rewrite_without_a_source_is_an_error() {
+    let rewrites = rewrites(&[("util", DependencyRewrite::default())]);
+    let error = rewrite_path_dependencies(Path::new("Cargo.toml"), MEMBER, &rewrites).unwrap_err();
+    assert!(matches!(error, PorterError::Config(_)));
+  }
+
+  #[test]
+  fn escapes_root_resolves_paths_lexically() {
+    assert!(escapes_root(Path::new(""), Path::new("../util")));
+    assert!(!escapes_root(Path::new("crates/a"), Path::new("../b")));
+    assert!(escapes_root(Path::new("crates/a"), Path::new("../../../b")));
+    assert!(escapes_root(Path::new(""), Path::new("/abs/util")));
+    assert_eq!(
+      normalize(Path::new("a/./b/../c")),
+      Some(PathBuf::from("a/c"))
+    );
+  }
+
+  const WORKSPACE_ROOT: &str = r#"[workspace]
+members = ["crates/*"]
+
+[workspace.package]
+version = "1.2.0"
+edition = "2021"
+readme = "README.md"
+license-file = "crates/foo/LICENSE"
+
+[workspace.dependencies]
+serde = { version = "1", features = ["derive"] }
+util = { path = "crates/util" }
+anyhow = "1"
+
+[workspace.lints.rust]
+unsafe_code = "forbid"
+"#;
+
+  const INHERITING_MEMBER: &str = r#"[package]
+name = "foo"
+version.workspace = true
+edition = { workspace = true }
+readme.workspace = true
+license-file.workspace = true
+
+[dependencies]
+serde = { workspace = true, features = ["rc"], optional = true }
+util.workspace = true
+anyhow = { workspace = true }
+
+[lints]
+workspace = true
+"#;
+
+  fn workspace() -> WorkspaceRoot {
+    WorkspaceRoot {
+      dir: PathBuf::new(),
+      published_root: PathBuf::from("crates/foo"),
+      doc: WORKSPACE_ROOT.parse().unwrap(),
+    }
+  }
+
+  #[test]
+  fn inherited_fields_and_dependencies_are_inlined() {
+    let (text, notes) =
+      inline_workspace_inheritance(Path::new("Cargo.toml"), INHERITING_MEMBER, &workspace())
+        .unwrap();
+    let doc: DocumentMut = text.parse().unwrap();
+    let package = &doc["package"];
+    assert_eq!(package["version"].as_str(), Some("1.2.0"));
+    assert_eq!(package["edition"].as_str(), Some("2021"));
+    assert_eq!(package["readme"].as_str(), Some("../../README.md"));
+    assert_eq!(package["license-file"].as_str(), Some("LICENSE"));
+
+    let serde = &doc["dependencies"]["serde"];
+    assert_eq!(serde["version"].as_str(), Some("1"));
+    let features: Vec<&str> = serde["features"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .filter_map(Value::as_str)
+      .collect();
+    assert_eq!(features, ["derive", "rc"]);
+    assert_eq!(serde["optional"].as_bool(), Some(true));
+    assert_eq!(
+      doc["dependencies"]["util"]["path"].as_str(),
+      Some("../util")
+    );
+    assert_eq!(doc["dependencies"]["anyhow"].as_str(), Some("1"));
+    assert_eq!(doc["lints"]["rust"]["unsafe_code"].as_str(), Some("forbid"));
+
+    assert!(notes[0].contains("inlined 5 package field(s) and 3 dependency(ies)"));
+    assert!(notes
+      .iter()
+      .any(|note| note.contains("inherited 'readme' points outside")));
+    assert!(find_inherited_keys(Path::new("Cargo.toml"), &text)
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn find_inherited_keys_lists_workspace_references() {
+    let keys = find_inherited_keys(Path::new("Cargo.toml"), INHERITING_MEMBER).unwrap();
+    assert_eq!(
+      keys,
+      [
+        "package.version",
+        "package.edition",
+        "package.readme",
+        "package.license-file",
+        "dependencies.serde",
+        "dependencies.util",
+        "dependencies.anyhow",
+        "lints",
+      ]
+    );
+    assert!(find_inherited_keys(Path::new("Cargo.toml"), WORKSPACE_ROOT)
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn missing_workspace_values_are_errors() {
+    let member = "[package]\nname = \"foo\"\nauthors.workspace = true\n";
+    let error =
+      inline_workspace_inheritance(Path::new("Cargo.toml"), member, &workspace()).unwrap_err();
+    assert!(error
+      .to_string()
+      .contains("inherits package field 'authors'"));
+  }
+
+  #[test]
+  fn workspace_roots_are_left_alone() {
+    let (text, notes) =
+      inline_workspace_inheritance(Path::new("Cargo.toml"), WORKSPACE_ROOT, &workspace()).unwrap();
+    assert_eq!(text, WORKSPACE_ROOT);
+    assert!(notes.is_empty());
+  }
+
+  #[test]
+  fn relative_path_climbs_to_the_common_ancestor() {
+    assert_eq!(
+      relative_path(Path::new("crates/foo"), Path::new("crates/util")),
+      PathBuf::from("../util")
+    );
+    assert_eq!(
+      relative_path(Path::new("crates/foo"), Path::new("crates/foo")),
+      PathBuf::from(".")
+    );
+    assert_eq!(
+      relative_path(Path::new(""), Path::new("a/b")),
+      PathBuf::from("a/b")
+    );
+  }
+
+  #[test]
+  fn package_license_replaces_license_file() {
+    let manifest = "[package]\nname = \"foo\"\nlicense = \"Proprietary\" # internal\nlicense-file = \"LICENSE.txt\"\n";
+    let (text, notes) =
+      set_package_license(Path::new("Cargo.toml"), manifest, "MIT OR Apache-2.0").unwrap();
+    assert_eq!(
+      text,
+      "[package]\nname = \"foo\"\nlicense = \"MIT OR Apache-2.0\" # internal\n"
+    );
+    assert_eq!(notes.len(), 2);
+    assert!(notes[0].contains("(was \"Proprietary\")"));
+
+    let (unchanged, notes) =
+      set_package_license(Path::new("Cargo.toml"), &text, "MIT OR Apache-2.0").unwrap();
+    assert_eq!(unchanged, text);
+    assert!(notes.is_empty());
+  }
+
+  #[test]
+  fn manifests_without_a_package_keep_their_license() {
+    let (text, notes) =
+      set_package_license(Path::new("Cargo.toml"), WORKSPACE_ROOT, "MIT").unwrap();
+    assert_eq!(text, WORKSPACE_ROOT);
+    assert!(notes.is_empty());
+  }
+}
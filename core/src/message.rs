@@ -0,0 +1,142 @@
+// oss-porter-core/src/message.rs
+use crate::{PorterError, ProjectConfig, Result};
+use regex::Regex;
+
+/// Message used when the rules remove everything from a commit message.
+const EMPTY_MESSAGE_REPLACEMENT: &str = "Update from internal repository";
+
+struct CompiledRule {
+  regex: Regex,
+  replace: String,
+  drop_line: bool,
+}
+
+/// Rewrites commit messages with a project's `message_rules`, in the configured order.
+pub struct MessageRules {
+  rules: Vec<CompiledRule>,
+}
+
+impl MessageRules {
+  /// Compiles the project's message rules. Returns `None` if none are configured.
+  pub fn from_config(config: &ProjectConfig) -> Result<Option<Self>> {
+    if config.message_rules.is_empty() {
+      return Ok(None);
+    }
+    let mut rules = Vec::new();
+    for rule in &config.message_rules {
+      let regex = Regex::new(&rule.pattern).map_err(|e| {
+        PorterError::Config(format!(
+          "Invalid message rule pattern '{}': {}",
+          rule.pattern, e
+        ))
+      })?;
+      rules.push(CompiledRule {
+        regex,
+        replace: rule.replace.clone(),
+        drop_line: rule.drop_line,
+      });
+    }
+    Ok(Some(MessageRules { rules }))
+  }
+
+  /// Applies every rule to `message`: `drop_line` rules remove each line they match,
+  /// the others replace all matches (`$1` refers to capture groups). If any rule matched,
+  /// trailing whitespace and runs of blank lines left behind are cleaned up afterwards;
+  /// otherwise `message` is returned unchanged.
+  pub fn apply(&self, message: &str) -> String {
+    let mut text = message.to_string();
+    let mut matched = false;
+    for rule in &self.rules {
+      if rule.drop_line {
+        let kept: String = text
+          .split_inclusive('\n')
+          .filter(|line| !rule.regex.is_match(line.trim_end_matches('\n')))
+          .collect();
+        matched |= kept.len() != text.len();
+        text = kept;
+      } else if rule.regex.is_match(&text) {
+        matched = true;
+        text = rule
+          .regex
+          .replace_all(&text, rule.replace.as_str())
+          .into_owned();
+      }
+    }
+    if !matched {
+      return text;
+    }
+    tidy(&text)
+  }
+}
+
+/// Trims trailing whitespace, collapses consecutive blank lines and strips blank lines at
+/// both ends. An empty result is replaced by a neutral message.
+fn tidy(text: &str) -> String {
+  let mut lines: Vec<&str> = Vec::new();
+  for line in text.lines().map(str::trim_end) {
+    if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+      continue;
+    }
+    lines.push(line);
+  }
+  while lines.last().is_some_and(|line| line.is_empty()) {
+    lines.pop();
+  }
+  if lines.is_empty() {
+    return EMPTY_MESSAGE_REPLACEMENT.to_string();
+  }
+  lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rules(rules: &[(&str, &str, bool)]) -> MessageRules {
+    MessageRules {
+      rules: rules
+        .iter()
+        .map(|(pattern, replace, drop_line)| CompiledRule {
+          regex: Regex::new(pattern).unwrap(),
+          replace: replace.to_string(),
+          drop_line: *drop_line,
+        })
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn rules_apply_in_order_with_capture_groups() {
+    let rules = rules(&[
+      (r"PROJ-(\d+)", "#$1", false),
+      (r"#(\d+)", "issue $1", false),
+    ]);
+    assert_eq!(rules.apply("Fix PROJ-12 crash"), "Fix issue 12 crash");
+  }
+
+  #[test]
+  fn dropped_lines_leave_no_blank_runs() {
+    let rules = rules(&[
+      (r"^Reviewed-by:", "", true),
+      (r"https://wiki\.corp/\S*", "", false),
+    ]);
+    let message = "Fix parser\n\nSee https://wiki.corp/page   \n\n\nReviewed-by: Jo <jo@corp>\n";
+    assert_eq!(rules.apply(message), "Fix parser\n\nSee");
+  }
+
+  #[test]
+  fn unmatched_message_is_left_untouched() {
+    let rules = rules(&[(r"PROJ-(\d+)", "#$1", false), (r"^Reviewed-by:", "", true)]);
+    let message = "Fix parser  \n\n\n\nDetails\n";
+    assert_eq!(rules.apply(message), message);
+  }
+
+  #[test]
+  fn empty_result_gets_a_neutral_message() {
+    let rules = rules(&[(".*", "", true)]);
+    assert_eq!(
+      rules.apply("INTERNAL-ONLY\n\ndetails\n"),
+      EMPTY_MESSAGE_REPLACEMENT
+    );
+  }
+}
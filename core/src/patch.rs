@@ -0,0 +1,763 @@
+// oss-porter-core/src/patch.rs
+use crate::identity::Identity;
+use crate::scrub::StrippedRegion;
+use crate::{PorterError, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write as _;
+
+/// One hunk of a unified diff. `lines` keep their leading ' ', '+', '-' or '\' marker
+/// but not the trailing newline.
+#[derive(Debug, Clone)]
+pub struct Hunk {
+  pub old_start: usize,
+  pub old_lines: usize,
+  pub new_start: usize,
+  pub new_lines: usize,
+  pub section: String, // Text after the closing "@@" (usually the enclosing function)
+  pub lines: Vec<String>,
+}
+
+impl Hunk {
+  /// Renders the `@@ -a,b +c,d @@` header line.
+  pub fn header(&self) -> String {
+    format!(
+      "@@ -{},{} +{},{} @@{}",
+      self.old_start, self.old_lines, self.new_start, self.new_lines, self.section
+    )
+  }
+}
+
+/// The diff of a single file inside a patch.
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+  pub old_path: Option<String>, // None for added files
+  pub new_path: Option<String>, // None for deleted files
+  pub header: Vec<String>,      // "diff --git", mode, index and ---/+++ lines
+  pub hunks: Vec<Hunk>,
+  pub binary: Vec<String>, // Raw "GIT binary patch" payload, if any
+}
+
+impl FileDiff {
+  /// Path of the file after the change, or before it for deletions.
+  pub fn path(&self) -> &str {
+    self
+      .new_path
+      .as_deref()
+      .or(self.old_path.as_deref())
+      .unwrap_or_default()
+  }
+
+  pub fn is_binary(&self) -> bool {
+    !self.binary.is_empty() || self.header.iter().any(|l| l.starts_with("Binary files "))
+  }
+
+  /// Replaces the blob hashes on the `index` header line, keeping the mode suffix.
+  pub fn set_index_hashes(&mut self, old_hash: &str, new_hash: &str) {
+    for line in self.header.iter_mut() {
+      if let Some(rest) = line.strip_prefix("index ") {
+        let mode = rest.split_once(' ').map(|(_, mode)| mode);
+        *line = match mode {
+          Some(mode) => format!("index {}..{} {}", old_hash, new_hash, mode),
+          None => format!("index {}..{}", old_hash, new_hash),
+        };
+      }
+    }
+  }
+
+  /// Keeps only the hunks whose index is in `keep`, shifting the new-side start of the
+  /// remaining hunks by the line count changes of the dropped ones before them.
+  fn retain_hunks(&mut self, keep: &BTreeSet<usize>) {
+    let mut shift: isize = 0;
+    let mut kept = Vec::new();
+    for (index, mut hunk) in std::mem::take(&mut self.hunks).into_iter().enumerate() {
+      if keep.contains(&index) {
+        hunk.new_start = hunk.new_start.saturating_add_signed(-shift);
+        kept.push(hunk);
+      } else {
+        shift += hunk.new_lines as isize - hunk.old_lines as isize;
+      }
+    }
+    self.hunks = kept;
+  }
+
+  fn render(&self, out: &mut String) {
+    for line in &self.header {
+      out.push_str(line);
+      out.push('\n');
+    }
+    for line in &self.binary {
+      out.push_str(line);
+      out.push('\n');
+    }
+    for hunk in &self.hunks {
+      out.push_str(&hunk.header());
+      out.push('\n');
+      for line in &hunk.lines {
+        out.push_str(line);
+        out.push('\n');
+      }
+    }
+  }
+}
+
+/// The files and hunks of a [`CommitPatch`] to publish, by index into `files` and `hunks`.
+/// Files not selected at all are left out.
+#[derive(Debug, Clone, Default)]
+pub struct PatchSelection {
+  files: BTreeMap<usize, Option<BTreeSet<usize>>>, // None keeps the whole file
+}
+
+impl PatchSelection {
+  /// Selects the whole file at `file`.
+  pub fn keep_file(&mut self, file: usize) {
+    self.files.insert(file, None);
+  }
+
+  /// Selects hunk `hunk` of the file at `file` (unless the whole file is already selected).
+  pub fn keep_hunk(&mut self, file: usize, hunk: usize) {
+    if let Some(hunks) = self
+      .files
+      .entry(file)
+      .or_insert_with(|| Some(BTreeSet::new()))
+    {
+      hunks.insert(hunk);
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.files.is_empty()
+  }
+}
+
+/// A file an internal commit renamed or copied across the `project_subdir` boundary.
+/// Inside the patch it is a plain addition (with full content) or deletion.
+#[derive(Debug, Clone)]
+pub struct BoundaryMove {
+  pub from: String,       // Repo-relative path before the move
+  pub to: String,         // Repo-relative path after it
+  pub copied: bool,       // Copied rather than renamed
+  pub into_project: bool, // Moved in from elsewhere in the repo (else moved out)
+}
+
+impl fmt::Display for BoundaryMove {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let verb = if self.copied { "copied" } else { "moved" };
+    if self.into_project {
+      write!(
+        f,
+        "'{}' {} into the project from '{}'",
+        self.to, verb, self.from
+      )
+    } else {
+      write!(
+        f,
+        "'{}' {} out of the project to '{}'",
+        self.from, verb, self.to
+      )
+    }
+  }
+}
+
+/// Signature line ending the mailboxes rendered by [`CommitPatch::to_mbox`].
+const MBOX_SIGNATURE: &str = "oss-porter";
+
+/// A single internal commit turned into a patch for the output repository.
+/// Rendered with [`CommitPatch::to_mbox`] in the same mailbox format `git format-patch`
+/// produces, so it can be applied with `git am`.
+#[derive(Debug, Clone)]
+pub struct CommitPatch {
+  pub commit_hash: String,
+  pub parent: Option<String>, // None for root commits
+  pub author_name: String,
+  pub author_email: String,
+  pub author_date: String,              // RFC 2822, as used in mail headers
+  pub message: String,                  // Full commit message as published, subject first
+  pub original_message: Option<String>, // Internal message, if `message_rules` changed it
+  pub files: Vec<FileDiff>,
+  pub stripped_regions: Vec<StrippedRegion>, // Internal-only regions scrubbed from the diff
+  pub transform_notes: Vec<String>, // Other content rewrites (e.g. Cargo dependency rewrites)
+  pub boundary_moves: Vec<BoundaryMove>, // Files renamed/copied into or out of the project
+}
+
+impl CommitPatch {
+  /// First paragraph of the message, joined into one line (like `git log --format=%s`).
+  pub fn subject(&self) -> String {
+    let (subject, _) = split_message(&self.message);
+    subject
+  }
+
+  /// Returns true if nothing publishable is left in this commit.
+  pub fn is_empty(&self) -> bool {
+    self.files.is_empty()
+  }
+
+  /// Returns the patch reduced to `selection`, with the hunk headers of partially kept files
+  /// recomputed so it still applies, and a description of every left-out file or hunk.
+  /// A new file keeps all of its content or is left out entirely.
+  pub fn filtered(&self, selection: &PatchSelection) -> (CommitPatch, Vec<String>) {
+    let mut filtered = self.clone();
+    filtered.files.clear();
+    let mut omitted = Vec::new();
+    for (index, file) in self.files.iter().enumerate() {
+      let keep = match selection.files.get(&index) {
+        Some(None) => {
+          filtered.files.push(file.clone());
+          continue;
+        }
+        Some(Some(hunks)) if file.old_path.is_some() || hunks.len() == file.hunks.len() => hunks,
+        _ => {
+          omitted.push(file.path().to_string());
+          continue;
+        }
+      };
+      for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+        if !keep.contains(&hunk_index) {
+          omitted.push(format!("{} {}", file.path(), hunk.header()));
+        }
+      }
+      if keep.is_empty() || file.hunks.is_empty() {
+        continue;
+      }
+      let mut partial = file.clone();
+      partial.retain_hunks(keep);
+      filtered.files.push(partial);
+    }
+    (filtered, omitted)
+  }
+
+  /// Renders only the diff part of the patch.
+  pub fn diff_text(&self) -> String {
+    let mut out = String::new();
+    for file in &self.files {
+      file.render(&mut out);
+    }
+    out
+  }
+
+  /// Renders the patch as a `git format-patch` style mailbox message.
+  pub fn to_mbox(&self) -> String {
+    let (subject, body) = split_message(&self.message);
+    let mut out = String::new();
+    let _ = writeln!(out, "From {} Mon Sep 17 00:00:00 2001", self.commit_hash);
+    let _ = writeln!(out, "From: {} <{}>", self.author_name, self.author_email);
+    let _ = writeln!(out, "Date: {}", self.author_date);
+    let _ = writeln!(out, "Subject: [PATCH] {}", subject);
+    out.push('\n');
+    if !body.is_empty() {
+      out.push_str(&body);
+      out.push('\n');
+    }
+    out.push_str("---\n\n");
+    out.push_str(&self.diff_text());
+    let _ = writeln!(out, "-- \n{}\n", MBOX_SIGNATURE);
+    out
+  }
+}
+
+/// Reads back a patch rendered by [`CommitPatch::to_mbox`] and edited by hand: author,
+/// date, message and diff come from `text`, everything else from `original`. Hunk line
+/// counts are recomputed from the hunk bodies, so lines can be added or removed without
+/// fixing the `@@` headers. Fails if the result is not a usable patch.
+pub fn parse_edited_mbox(original: &CommitPatch, text: &str) -> Result<CommitPatch> {
+  let invalid = |message: &str| PorterError::InvalidPatch(message.to_string());
+  let (headers, rest) = text
+    .split_once("\n\n")
+    .ok_or_else(|| invalid("missing the blank line after the mail headers"))?;
+  let mut patch = original.clone();
+  let mut subject = String::new();
+  for line in headers.lines() {
+    if let Some(author) = line.strip_prefix("From: ") {
+      let author = Identity::parse(author)?;
+      patch.author_name = author.name;
+      patch.author_email = author.email;
+    } else if let Some(date) = line.strip_prefix("Date: ") {
+      patch.author_date = date.trim().to_string();
+    } else if let Some(text) = line.strip_prefix("Subject: ") {
+      subject = text.trim().trim_start_matches("[PATCH]").trim().to_string();
+    }
+  }
+  if subject.is_empty() {
+    return Err(invalid("the Subject header is missing or empty"));
+  }
+
+  // The message body ends at the first "---" line; the diff ends at the signature
+  let rest = format!("\n{}", rest);
+  let (body, diff) = rest
+    .split_once("\n---\n")
+    .ok_or_else(|| invalid("missing the '---' line between the message and the diff"))?;
+  let diff = strip_signature(diff);
+  patch.message = match body.trim() {
+    "" => subject,
+    body => format!("{}\n\n{}", subject, body),
+  };
+  patch.files = parse_diff(&recount_hunks(diff)?);
+  // A text change whose hunks were all deleted is gone; file additions, deletions, mode
+  // and binary changes carry no hunks of their own
+  patch.files.retain(|file| {
+    !file.hunks.is_empty()
+      || file.is_binary()
+      || file.header.iter().any(|line| {
+        line.starts_with("new file mode")
+          || line.starts_with("deleted file mode")
+          || line.starts_with("old mode")
+      })
+  });
+  if patch.files.is_empty() {
+    return Err(invalid("no file changes left"));
+  }
+  Ok(patch)
+}
+
+/// Cuts the `-- \noss-porter` signature written by [`CommitPatch::to_mbox`] off the end of
+/// `diff`, also when an editor stripped the trailing space of the `-- ` line.
+fn strip_signature(diff: &str) -> &str {
+  let body = diff.trim_end();
+  let Some(rest) = body.strip_suffix(MBOX_SIGNATURE) else {
+    return diff;
+  };
+  let rest = rest.strip_suffix('\n').unwrap_or(rest);
+  let rest = rest.strip_suffix(' ').unwrap_or(rest);
+  match rest.strip_suffix("\n--") {
+    Some(diff) => diff,
+    None if rest == "--" => "",
+    None => diff,
+  }
+}
+
+/// Rewrites the `@@` header of every hunk in `diff` to match the lines that follow it (up to
+/// the next hunk or file), shifting the new-side start of later hunks of the same file.
+/// Blank lines inside a hunk are taken as empty context lines (editors strip the space).
+fn recount_hunks(diff: &str) -> Result<String> {
+  let lines: Vec<&str> = diff.trim_end_matches('\n').split('\n').collect();
+  let mut out = String::new();
+  let mut shift: isize = 0;
+  let mut i = 0;
+  while i < lines.len() {
+    let line = lines[i];
+    i += 1;
+    if line.starts_with("diff --git ") {
+      shift = 0;
+    }
+    if !line.starts_with("@@ ") {
+      out.push_str(line);
+      out.push('\n');
+      continue;
+    }
+    let mut hunk = parse_hunk_header(line)
+      .ok_or_else(|| PorterError::InvalidPatch(format!("malformed hunk header '{}'", line)))?;
+    let old_net = hunk.new_lines as isize - hunk.old_lines as isize;
+    while i < lines.len() && !lines[i].starts_with("@@ ") && !lines[i].starts_with("diff --git ") {
+      let hunk_line = match lines[i] {
+        "" => " ",
+        other => other,
+      };
+      if !hunk_line.starts_with([' ', '+', '-', '\\']) {
+        return Err(PorterError::InvalidPatch(format!(
+          "'{}' in hunk '{}' is not a context, '+' or '-' line",
+          hunk_line, line
+        )));
+      }
+      hunk.lines.push(hunk_line.to_string());
+      i += 1;
+    }
+    if !hunk.lines.iter().any(|l| l.starts_with(['+', '-'])) {
+      shift -= old_net; // Nothing left to change; drop the hunk
+      continue;
+    }
+    let count = |marker: char| {
+      hunk
+        .lines
+        .iter()
+        .filter(|l| l.starts_with([' ', marker]))
+        .count()
+    };
+    hunk.old_lines = count('-');
+    hunk.new_lines = count('+');
+    if hunk.new_lines > 0 {
+      hunk.new_start = hunk.new_start.max(1).saturating_add_signed(shift);
+    }
+    shift += hunk.new_lines as isize - hunk.old_lines as isize - old_net;
+    out.push_str(&hunk.header());
+    out.push('\n');
+    for hunk_line in &hunk.lines {
+      out.push_str(hunk_line);
+      out.push('\n');
+    }
+  }
+  Ok(out)
+}
+
+/// Splits a commit message into its one-line subject and the remaining body.
+fn split_message(message: &str) -> (String, String) {
+  let message = message.trim();
+  let (first, rest) = match message.split_once("\n\n") {
+    Some((first, rest)) => (first, rest.trim()),
+    None => (message, ""),
+  };
+  let subject = first.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+  (subject, rest.to_string())
+}
+
+/// Parses `git diff` output (as produced with `--full-index --binary`) into per-file diffs.
+pub fn parse_diff(text: &str) -> Vec<FileDiff> {
+  let mut files: Vec<FileDiff> = Vec::new();
+  // Split on '\n' only, so carriage returns in CRLF files survive the round trip.
+  let mut lines: Vec<&str> = text.split('\n').collect();
+  if lines.last() == Some(&"") {
+    lines.pop();
+  }
+  let mut i = 0;
+
+  while i < lines.len() {
+    let line = lines[i];
+    if !line.starts_with("diff --git ") {
+      i += 1;
+      continue;
+    }
+    let mut file = FileDiff {
+      old_path: None,
+      new_path: None,
+      header: vec![line.to_string()],
+      hunks: Vec::new(),
+      binary: Vec::new(),
+    };
+    let (git_old, git_new) = parse_git_header_paths(line);
+    let mut saw_old_marker = false;
+    let mut saw_new_marker = false;
+    let mut is_new = false;
+    let mut is_deleted = false;
+    i += 1;
+
+    // Extended header lines up to the first hunk (or the next file).
+    while i < lines.len() && !lines[i].starts_with("@@ ") && !lines[i].starts_with("diff --git ") {
+      let header_line = lines[i];
+      if header_line == "GIT binary patch" {
+        while i < lines.len() && !lines[i].starts_with("diff --git ") {
+          file.binary.push(lines[i].to_string());
+          i += 1;
+        }
+        break;
+      }
+      if header_line.starts_with("new file mode") {
+        is_new = true;
+      } else if header_line.starts_with("deleted file mode") {
+        is_deleted = true;
+      } else if let Some(path) = header_line.strip_prefix("--- ") {
+        saw_old_marker = true;
+        file.old_path = strip_side_prefix(path, "a/");
+      } else if let Some(path) = header_line.strip_prefix("+++ ") {
+        saw_new_marker = true;
+        file.new_path = strip_side_prefix(path, "b/");
+      }
+      file.header.push(header_line.to_string());
+      i += 1;
+    }
+    if !saw_old_marker && !is_new {
+      file.old_path = git_old;
+    }
+    if !saw_new_marker && !is_deleted {
+      file.new_path = git_new;
+    }
+
+    // Hunks, read by line counts so content lines are never mistaken for headers.
+    while i < lines.len() && lines[i].starts_with("@@ ") {
+      let Some(mut hunk) = parse_hunk_header(lines[i]) else {
+        break;
+      };
+      i += 1;
+      let mut old_left = hunk.old_lines;
+      let mut new_left = hunk.new_lines;
+      while i < lines.len() && (old_left > 0 || new_left > 0 || lines[i].starts_with('\\')) {
+        let hunk_line = lines[i];
+        match hunk_line.chars().next() {
+          Some('-') => old_left = old_left.saturating_sub(1),
+          Some('+') => new_left = new_left.saturating_sub(1),
+          Some('\\') => {}
+          _ => {
+            old_left = old_left.saturating_sub(1);
+            new_left = new_left.saturating_sub(1);
+          }
+        }
+        hunk.lines.push(hunk_line.to_string());
+        i += 1;
+      }
+      file.hunks.push(hunk);
+    }
+    files.push(file);
+  }
+  files
+}
+
+/// Parses a `@@ -a,b +c,d @@ section` line. Omitted counts default to 1.
+pub fn parse_hunk_header(line: &str) -> Option<Hunk> {
+  let rest = line.strip_prefix("@@ -")?;
+  let (ranges, section) = rest.split_once(" @@")?;
+  let (old, new) = ranges.split_once(" +")?;
+  let parse_range = |range: &str| -> Option<(usize, usize)> {
+    match range.split_once(',') {
+      Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+      None => Some((range.parse().ok()?, 1)),
+    }
+  };
+  let (old_start, old_lines) = parse_range(old)?;
+  let (new_start, new_lines) = parse_range(new)?;
+  Some(Hunk {
+    old_start,
+    old_lines,
+    new_start,
+    new_lines,
+    section: section.to_string(),
+    lines: Vec::new(),
+  })
+}
+
+fn strip_side_prefix(path: &str, prefix: &str) -> Option<String> {
+  let path = path.trim_end_matches('\t');
+  if path == "/dev/null" {
+    return None;
+  }
+  Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+/// Extracts the two paths from a `diff --git a/<old> b/<new>` line (unquoted paths only).
+fn parse_git_header_paths(line: &str) -> (Option<String>, Option<String>) {
+  let rest = line.trim_start_matches("diff --git ");
+  match rest.strip_prefix("a/").and_then(|r| r.split_once(" b/")) {
+    Some((old, new)) => (Some(old.to_string()), Some(new.to_string())),
+    None => (None, None),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111111111111111111111111111111111111..2222222222222222222222222222222222222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@ mod a;
+ fn a() {}
++fn b() {}
+ fn c() {}
+ 
+@@ -10,3 +11,3 @@ fn d() {
+ let x = 1;
+-let y = 2;
++let y = 3;
+ let z = 4;
+";
+
+  fn patch(diff: &str) -> CommitPatch {
+    CommitPatch {
+      commit_hash: "abc123".to_string(),
+      parent: None,
+      author_name: "Dev".to_string(),
+      author_email: "dev@example.com".to_string(),
+      author_date: "Mon, 1 Jan 2024 00:00:00 +0000".to_string(),
+      message: "Fix things\n\nLonger explanation.".to_string(),
+      original_message: None,
+      files: parse_diff(diff),
+      stripped_regions: Vec::new(),
+      transform_notes: Vec::new(),
+      boundary_moves: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn unedited_mbox_round_trips() {
+    let original = patch(DIFF);
+    let parsed = parse_edited_mbox(&original, &original.to_mbox()).unwrap();
+    assert_eq!(parsed.message, original.message);
+    assert_eq!(parsed.diff_text(), DIFF);
+    assert_eq!(parsed.to_mbox(), original.to_mbox());
+  }
+
+  #[test]
+  fn edited_mbox_with_stripped_trailing_whitespace_parses() {
+    let original = patch(DIFF);
+    // Editors configured to strip trailing whitespace turn "-- " into "--" and " " into ""
+    let edited: String = original
+      .to_mbox()
+      .replace("Subject: [PATCH] Fix things", "Subject: [PATCH] Public fix")
+      .replace("+fn b() {}\n", "+fn b() {}\n+fn b2() {}\n")
+      .lines()
+      .map(|line| format!("{}\n", line.trim_end()))
+      .collect();
+    assert!(edited.contains("\n--\noss-porter\n"));
+
+    let parsed = parse_edited_mbox(&original, &edited).unwrap();
+    assert_eq!(parsed.message, "Public fix\n\nLonger explanation.");
+    let hunks = &parsed.files[0].hunks;
+    assert_eq!(hunks.len(), 2);
+    assert_eq!(hunks[0].header(), "@@ -1,3 +1,5 @@ mod a;");
+    assert_eq!(hunks[0].lines.last().map(String::as_str), Some(" "));
+    assert_eq!(hunks[1].header(), "@@ -10,3 +12,3 @@ fn d() {");
+    assert!(!parsed.diff_text().contains("oss-porter"));
+  }
+
+  #[test]
+  fn signature_without_trailing_newline_is_stripped() {
+    assert_eq!(strip_signature("+a\n-- \noss-porter"), "+a");
+    assert_eq!(strip_signature("+a\n--\noss-porter\n\n"), "+a");
+    // A removed line "-" is not a signature
+    assert_eq!(strip_signature("+a\n--\n"), "+a\n--\n");
+  }
+
+  #[test]
+  fn edited_mbox_rejects_garbage_in_hunks() {
+    let original = patch(DIFF);
+    let edited = original.to_mbox().replace("+let y = 3;", "let y = 3;");
+    assert!(matches!(
+      parse_edited_mbox(&original, &edited),
+      Err(PorterError::InvalidPatch(_))
+    ));
+  }
+
+  #[test]
+  fn edited_mbox_requires_a_subject() {
+    let original = patch(DIFF);
+    let edited = original
+      .to_mbox()
+      .replace("Subject: [PATCH] Fix things", "Subject: [PATCH] ");
+    assert!(parse_edited_mbox(&original, &edited).is_err());
+  }
+
+  #[test]
+  fn hunk_with_all_changes_removed_is_dropped() {
+    let original = patch(DIFF);
+    let edited = original.to_mbox().replace("+fn b() {}\n", "");
+    let parsed = parse_edited_mbox(&original, &edited).unwrap();
+    let hunks = &parsed.files[0].hunks;
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].header(), "@@ -10,3 +10,3 @@ fn d() {");
+  }
+
+  const OTHER_FILES: &str = "\
+diff --git a/NEW.md b/NEW.md
+new file mode 100644
+index 0000000000000000000000000000000000000000..3333333333333333333333333333333333333333
+--- /dev/null
++++ b/NEW.md
+@@ -0,0 +1,2 @@
++# New
++-- not a removal
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+index 4444444444444444444444444444444444444444..0000000000000000000000000000000000000000
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+\\ No newline at end of file
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/logo.png b/logo.png
+index 5555555555555555555555555555555555555555..6666666666666666666666666666666666666666 100644
+GIT binary patch
+literal 3
+Kc${NkU|;|M00aO5
+
+literal 0
+HcmV?d00001
+
+";
+
+  #[test]
+  fn parse_diff_reads_files_hunks_and_paths() {
+    let text = format!("{}{}", DIFF, OTHER_FILES);
+    let files = parse_diff(&text);
+    let paths: Vec<&str> = files.iter().map(FileDiff::path).collect();
+    assert_eq!(
+      paths,
+      ["src/lib.rs", "NEW.md", "old.txt", "run.sh", "logo.png"]
+    );
+
+    assert_eq!(files[0].hunks.len(), 2);
+    assert_eq!(files[0].hunks[1].section, " fn d() {");
+    assert_eq!(files[0].hunks[1].lines.len(), 4);
+    assert_eq!(
+      (files[1].old_path.as_deref(), files[1].hunks[0].lines.len()),
+      (None, 2)
+    );
+    assert_eq!(files[2].new_path, None);
+    assert_eq!(
+      files[2].hunks[0].lines.last().map(String::as_str),
+      Some("\\ No newline at end of file")
+    );
+    assert_eq!(files[3].old_path.as_deref(), Some("run.sh"));
+    assert!(files[3].hunks.is_empty());
+    assert!(files[4].is_binary() && !files[0].is_binary());
+
+    let mut rendered = String::new();
+    for file in &files {
+      file.render(&mut rendered);
+    }
+    // Hunk headers are rendered with explicit counts
+    assert_eq!(rendered, text.replace("@@ -1 +0,0 @@", "@@ -1,1 +0,0 @@"));
+  }
+
+  #[test]
+  fn parse_hunk_header_defaults_omitted_counts() {
+    let hunk = parse_hunk_header("@@ -3 +3,2 @@").unwrap();
+    assert_eq!(
+      (
+        hunk.old_start,
+        hunk.old_lines,
+        hunk.new_start,
+        hunk.new_lines
+      ),
+      (3, 1, 3, 2)
+    );
+    assert_eq!(hunk.section, "");
+    assert!(parse_hunk_header("@@ -x,1 +1 @@").is_none());
+    assert!(parse_hunk_header("@@@ -1,2 -1,2 +1,3 @@@").is_none());
+  }
+
+  #[test]
+  fn filtered_shifts_hunks_after_dropped_ones() {
+    let mut selection = PatchSelection::default();
+    selection.keep_hunk(0, 1);
+    let (filtered, omitted) = patch(DIFF).filtered(&selection);
+    let hunks = &filtered.files[0].hunks;
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].header(), "@@ -10,3 +10,3 @@ fn d() {");
+    assert_eq!(omitted, ["src/lib.rs @@ -1,3 +1,4 @@ mod a;"]);
+  }
+
+  #[test]
+  fn filtered_leaves_out_unselected_files() {
+    let mut selection = PatchSelection::default();
+    selection.keep_hunk(0, 0);
+    selection.keep_file(0); // A whole file wins over single hunks
+    selection.keep_file(2);
+    let (filtered, omitted) = patch(&format!("{}{}", DIFF, OTHER_FILES)).filtered(&selection);
+    let paths: Vec<&str> = filtered.files.iter().map(FileDiff::path).collect();
+    assert_eq!(paths, ["src/lib.rs", "old.txt"]);
+    assert_eq!(filtered.files[0].hunks.len(), 2);
+    assert_eq!(omitted, ["NEW.md", "run.sh", "logo.png"]);
+    assert!(PatchSelection::default().is_empty());
+  }
+
+  #[test]
+  fn recount_hunks_fixes_counts_and_keeps_deletions_at_zero() {
+    let edited = "\
+diff --git a/a.txt b/a.txt
+@@ -1,1 +1,1 @@
+ a
++b
++c
+diff --git a/old.txt b/old.txt
+@@ -1 +0,0 @@
+-gone
+";
+    let recounted = recount_hunks(edited).unwrap();
+    assert!(recounted.contains("@@ -1,1 +1,3 @@\n a\n"));
+    assert!(recounted.contains("@@ -1,1 +0,0 @@\n-gone\n"));
+    assert!(recount_hunks("@@ -1 +1 @@ broken\n").is_ok());
+    assert!(recount_hunks("@@ -1,1 @@\n a\n").is_err());
+  }
+}
@@ -0,0 +1,441 @@
+// oss-porter-core/src/policy.rs
+use crate::check::check_project;
+use crate::filter::glob_set;
+use crate::relocation::find_subdir_relocation;
+use crate::selection::{has_trailer, SelectionRules};
+use crate::session::load_update_session;
+use crate::state::{
+  read_last_synced_commit, read_sync_history, record_sync_decision, skip_commit,
+  write_last_synced_commit, write_pending_commits, SyncDecision, SyncRecord,
+};
+use crate::update::{
+  abort_apply_session, advance_sync_cursor, apply_in_progress, apply_patch_to_output,
+  build_commit_patch, get_internal_commits_since, ApplyResult, CommitInfo, PublicPatchIds,
+};
+use crate::utils::run_git_command;
+use crate::{CheckResult, PorterError, ProjectConfig, Result, SelectionAction};
+use globset::GlobSet;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Which commits a non-interactive update applies.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplySelection {
+  #[default]
+  All, // Every new commit
+  Matching, // Only commits matching `trailers` or `paths`
+}
+
+/// Policy for `update --policy`, read from a TOML file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdatePolicy {
+  #[serde(default)]
+  pub apply: ApplySelection,
+  #[serde(default)]
+  pub trailers: Vec<String>, // "Key: value" (or just "Key") trailers selecting a commit
+  #[serde(default)]
+  pub paths: Vec<String>, // Globs relative to project_subdir; touching one selects a commit
+  #[serde(default = "crate::default_true")]
+  pub stop_on_conflict: bool, // Otherwise the conflicting commit is left pending
+  #[serde(default)]
+  pub require_checks: bool, // Run `check` afterwards; failures fail the run
+  pub branch: Option<String>, // Output branch for the synced commits, created from HEAD if missing
+}
+
+impl UpdatePolicy {
+  /// Reads a policy file.
+  pub fn load(path: &Path) -> Result<Self> {
+    let content = fs::read_to_string(path).map_err(|e| PorterError::Io {
+      source: e,
+      path: path.to_path_buf(),
+    })?;
+    let policy: UpdatePolicy = toml::from_str(&content).map_err(|e| PorterError::TomlParse {
+      source: e,
+      path: path.to_path_buf(),
+    })?;
+    if policy.apply == ApplySelection::Matching
+      && policy.trailers.is_empty()
+      && policy.paths.is_empty()
+    {
+      return Err(PorterError::Config(format!(
+        "Policy '{}' applies matching commits but lists no `trailers` or `paths`.",
+        path.display()
+      )));
+    }
+    Ok(policy)
+  }
+}
+
+/// Overall result of a non-interactive update.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+  Ok,
+  Conflict,     // A commit conflicted (see `commits`)
+  Failed,       // A patch could not be built or applied
+  ChecksFailed, // Commits were applied but `check` reported problems
+}
+
+/// What happened to one commit in a non-interactive update.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitOutcome {
+  Applied,
+  AlreadyPresent, // Its change is already at the output HEAD (as `public_commit`); left pending
+  Skipped,        // Skipped permanently by a selection rule
+  NotSelected,    // Did not match the policy; left pending
+  Conflict,       // The patch was aborted; left pending
+  Failed,
+  NotReached, // The run stopped before this commit
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommitReport {
+  pub internal_commit: String,
+  pub subject: String,
+  pub outcome: CommitOutcome,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub public_commit: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub message: Option<String>, // Error details
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub warnings: Vec<String>, // Files moved across the project boundary
+}
+
+/// Machine-readable summary of a non-interactive update.
+#[derive(Serialize, Debug)]
+pub struct UpdateSummary {
+  pub project_id: String,
+  pub status: UpdateStatus,
+  pub since_commit: Option<String>,  // Sync cursor before the run
+  pub synced_commit: Option<String>, // Sync cursor after the run
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub branch: Option<String>,
+  pub commits: Vec<CommitReport>,
+  pub pending: Vec<String>, // Undecided commits after the cursor
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub checks: Option<CheckResult>,
+}
+
+/// Decides which commits a policy selects.
+struct CommitSelector {
+  trailers: Vec<String>,
+  paths: Option<GlobSet>,
+}
+
+impl CommitSelector {
+  fn new(policy: &UpdatePolicy) -> Result<Option<Self>> {
+    if policy.apply == ApplySelection::All {
+      return Ok(None);
+    }
+    Ok(Some(CommitSelector {
+      trailers: policy.trailers.clone(),
+      paths: glob_set(&policy.paths)?,
+    }))
+  }
+
+  /// A commit is selected if it carries one of the trailers (keys compare case-insensitively)
+  /// or its published patch touches one of the paths.
+  fn selects(&self, commit: &CommitInfo, files: &[&str]) -> bool {
+    if self
+      .trailers
+      .iter()
+      .any(|trailer| has_trailer(commit, trailer))
+    {
+      return true;
+    }
+    match &self.paths {
+      Some(paths) => files.iter().any(|file| paths.is_match(file)),
+      None => false,
+    }
+  }
+}
+
+/// Switches the output repo to `branch`. A missing branch is created from HEAD, and one that
+/// HEAD moved ahead of is fast-forwarded; a branch that diverged from HEAD is never reset.
+fn prepare_output_branch(config: &ProjectConfig, branch: &str) -> Result<()> {
+  let output_path = &config.output_path;
+  let branch_ref = format!("refs/heads/{}", branch);
+  if run_git_command(&["rev-parse", "--verify", "-q", &branch_ref], output_path).is_err() {
+    info!("Creating output branch '{}' from HEAD", branch);
+    run_git_command(&["checkout", "-q", "-b", branch], output_path)?;
+    return Ok(());
+  }
+  let head = run_git_command(&["rev-parse", "HEAD"], output_path)?;
+  let head = String::from_utf8_lossy(&head.stdout).trim().to_string();
+  let is_ancestor = |ancestor: &str, descendant: &str| {
+    run_git_command(
+      &["merge-base", "--is-ancestor", ancestor, descendant],
+      output_path,
+    )
+    .is_ok()
+  };
+  if is_ancestor(&head, &branch_ref) {
+    info!("Switching to output branch '{}'", branch);
+    run_git_command(&["checkout", "-q", branch], output_path)?;
+  } else if is_ancestor(&branch_ref, &head) {
+    info!(
+      "Switching to output branch '{}' and fast-forwarding it to {}",
+      branch, head
+    );
+    run_git_command(&["checkout", "-q", branch], output_path)?;
+    run_git_command(&["merge", "-q", "--ff-only", &head], output_path)?;
+  } else {
+    return Err(PorterError::GitOperation(format!(
+      "Output branch '{}' has diverged from HEAD in {}; merge or delete it before running the policy.",
+      branch,
+      output_path.display()
+    )));
+  }
+  Ok(())
+}
+
+/// Runs an update without prompts: applies the new commits selected by `policy` to the output
+/// repo (on `policy.branch` if set), records the decisions, the sync cursor and the pending
+/// commits in the state file (not committed), and optionally runs the checks. The project's
+/// selection rules come first: their `apply`/`skip` override the policy, `review` defers to it.
+pub fn run_policy_update(
+  project_id: &str,
+  config: &ProjectConfig,
+  policy: &UpdatePolicy,
+) -> Result<UpdateSummary> {
+  if load_update_session(config)?.is_some() || apply_in_progress(config)? {
+    return Err(PorterError::GitOperation(format!(
+      "An update is already in progress in {}; finish it with 'update --continue' or '--abort'.",
+      config.output_path.display()
+    )));
+  }
+  // Following a move of the project subdir changes the configuration; that needs a person
+  if let Some(relocation) = find_subdir_relocation(config)? {
+    return Err(PorterError::Config(format!(
+      "'{}' was moved to '{}' by commit {}; run 'oss-porter update {}' interactively to follow the move.",
+      relocation.from.display(),
+      relocation.to.display(),
+      relocation.commit,
+      project_id
+    )));
+  }
+  let since_commit = read_last_synced_commit(config)?;
+  if since_commit.is_none() && read_sync_history(config)?.is_empty() {
+    return Err(PorterError::GitOperation(format!(
+      "No sync state recorded for project '{}'.",
+      project_id
+    )));
+  }
+  if let Some(branch) = &policy.branch {
+    prepare_output_branch(config, branch)?;
+  }
+
+  let selector = CommitSelector::new(policy)?;
+  let selection_rules = SelectionRules::from_config(config)?;
+  let public_patch_ids = PublicPatchIds::load(config)?;
+  let mut commits = get_internal_commits_since(config, since_commit.as_deref())?;
+  let mut reports = Vec::new();
+  let mut status = UpdateStatus::Ok;
+
+  while let Some(commit) = commits.pop_front() {
+    let hash = commit.hash.clone();
+    let mut report = CommitReport {
+      internal_commit: hash.clone(),
+      subject: commit.subject.clone(),
+      outcome: CommitOutcome::Applied,
+      public_commit: None,
+      message: None,
+      warnings: Vec::new(),
+    };
+    let rule = selection_rules
+      .as_ref()
+      .and_then(|rules| rules.matching_rule(&commit));
+    let action = rule.map_or(SelectionAction::Review, |rule| rule.action);
+    if let (Some(rule), SelectionAction::Skip) = (rule, action) {
+      info!("Commit {} skipped by selection rule {}.", hash, rule);
+      let reason = format!("selection rule: {}", rule);
+      skip_commit(config, &hash, Some(&reason))?;
+      record_sync_decision(config, SyncRecord::now(&hash, None, SyncDecision::Skipped))?;
+      report.outcome = CommitOutcome::Skipped;
+      report.message = Some(reason);
+      reports.push(report);
+      continue;
+    }
+    let patch = match build_commit_patch(config, &hash) {
+      Ok(patch) => patch,
+      Err(e) => {
+        report.outcome = CommitOutcome::Failed;
+        report.message = Some(format!("Failed to prepare patch: {}", e));
+        reports.push(report);
+        status = UpdateStatus::Failed;
+        break;
+      }
+    };
+    report.warnings = patch.boundary_moves.iter().map(|m| m.to_string()).collect();
+    // Not decided without a reviewer: a re-introduced change can look like an applied one
+    if let Some(public_commit) = public_patch_ids.find(config, &patch)? {
+      info!(
+        "Commit {} looks already applied as {}; leaving it pending for review.",
+        hash, public_commit
+      );
+      report.outcome = CommitOutcome::AlreadyPresent;
+      report.public_commit = Some(public_commit.to_string());
+      reports.push(report);
+      continue;
+    }
+    if let (Some(selector), SelectionAction::Review) = (&selector, action) {
+      let files: Vec<&str> = patch.files.iter().map(|file| file.path()).collect();
+      if !selector.selects(&commit, &files) {
+        info!(
+          "Commit {} does not match the policy; leaving it pending.",
+          hash
+        );
+        report.outcome = CommitOutcome::NotSelected;
+        reports.push(report);
+        continue;
+      }
+    }
+    match apply_patch_to_output(config, &patch)? {
+      ApplyResult::Success(public_commit) => {
+        record_sync_decision(
+          config,
+          SyncRecord::now(&hash, public_commit.as_deref(), SyncDecision::Applied),
+        )?;
+        report.public_commit = public_commit;
+        reports.push(report);
+      }
+      ApplyResult::Conflict => {
+        warn!("Commit {} conflicts; aborting its patch.", hash);
+        abort_apply_session(config)?;
+        report.outcome = CommitOutcome::Conflict;
+        reports.push(report);
+        status = UpdateStatus::Conflict;
+        if policy.stop_on_conflict {
+          break;
+        }
+      }
+      ApplyResult::Failure(stderr) => {
+        report.outcome = CommitOutcome::Failed;
+        report.message = Some(stderr.trim().to_string());
+        reports.push(report);
+        status = UpdateStatus::Failed;
+        break;
+      }
+    }
+  }
+  reports.extend(commits.into_iter().map(|commit| CommitReport {
+    internal_commit: commit.hash,
+    subject: commit.subject,
+    outcome: CommitOutcome::NotReached,
+    public_commit: None,
+    message: None,
+    warnings: Vec::new(),
+  }));
+
+  let cursor = advance_sync_cursor(config, since_commit.as_deref())?;
+  write_last_synced_commit(config, cursor.cursor.as_deref())?;
+  write_pending_commits(config, &cursor.pending)?;
+
+  let checks = if policy.require_checks {
+    let result = check_project(project_id, config)?;
+    if !result.passed() && status == UpdateStatus::Ok {
+      status = UpdateStatus::ChecksFailed;
+    }
+    Some(result)
+  } else {
+    None
+  };
+
+  Ok(UpdateSummary {
+    project_id: project_id.to_string(),
+    status,
+    since_commit,
+    synced_commit: cursor.cursor,
+    branch: policy.branch.clone(),
+    commits: reports,
+    pending: cursor.pending,
+    checks,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{self, git};
+
+  fn config(internal: &Path, output: &Path) -> ProjectConfig {
+    toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n",
+      internal, output
+    ))
+    .unwrap()
+  }
+
+  fn policy(content: &str) -> UpdatePolicy {
+    toml::from_str(content).unwrap()
+  }
+
+  fn commit(message: &str, trailers: &[(&str, &str)]) -> CommitInfo {
+    CommitInfo {
+      hash: "abc123".to_string(),
+      subject: message.lines().next().unwrap_or_default().to_string(),
+      message: message.to_string(),
+      trailers: trailers
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn policy_selects_all_commits_by_default() {
+    let all = policy("");
+    assert!(all.stop_on_conflict);
+    assert!(CommitSelector::new(&all).unwrap().is_none());
+  }
+
+  #[test]
+  fn selector_matches_trailers_or_paths() {
+    let policy = policy(
+      "apply = \"matching\"\ntrailers = [\"Public: yes\", \" Release-Note\"]\npaths = [\"docs/**\"]\n",
+    );
+    let selector = CommitSelector::new(&policy).unwrap().unwrap();
+    let public = commit("Fix", &[("public", "yes")]);
+    let private = commit("Fix", &[("Public", "no")]);
+    let noted = commit("Fix", &[("Release-Note", "faster")]);
+    assert!(selector.selects(&public, &["src/lib.rs"]));
+    assert!(!selector.selects(&private, &["src/lib.rs"]));
+    assert!(selector.selects(&private, &["docs/guide.md"]));
+    assert!(selector.selects(&noted, &[]));
+  }
+
+  #[test]
+  fn output_branch_is_reused_but_never_reset() {
+    let output = testing::init_repo();
+    let path = output.path();
+    let base = testing::commit_files(path, &[("a", "1\n")], "Base");
+    let config = config(Path::new("internal"), path);
+
+    prepare_output_branch(&config, "oss-sync").unwrap();
+    assert_eq!(git(path, &["branch", "--show-current"]), "oss-sync");
+    let synced = testing::commit_files(path, &[("a", "2\n")], "Synced");
+
+    // HEAD behind the branch: switch without losing the synced commit.
+    git(path, &["checkout", "-q", "main"]);
+    prepare_output_branch(&config, "oss-sync").unwrap();
+    assert_eq!(git(path, &["rev-parse", "HEAD"]), synced);
+
+    // HEAD ahead of the branch: fast-forward.
+    git(path, &["checkout", "-q", "main"]);
+    git(path, &["merge", "-q", "--ff-only", "oss-sync"]);
+    let ahead = testing::commit_files(path, &[("b", "1\n")], "Ahead");
+    prepare_output_branch(&config, "oss-sync").unwrap();
+    assert_eq!(git(path, &["rev-parse", "HEAD"]), ahead);
+
+    // Diverged: refused, nothing moves.
+    git(path, &["checkout", "-q", "-b", "other", &base]);
+    testing::commit_files(path, &[("c", "1\n")], "Diverged");
+    assert!(prepare_output_branch(&config, "oss-sync").is_err());
+    assert_eq!(git(path, &["branch", "--show-current"]), "other");
+    assert_eq!(git(path, &["rev-parse", "oss-sync"]), ahead);
+  }
+}
@@ -0,0 +1,309 @@
+// oss-porter-core/src/relocation.rs
+use crate::filter::PathFilter;
+use crate::state::{
+  get_internal_state_file_path, read_subdir_moves, record_subdir_move, record_sync_decision,
+  SubdirMove, SyncDecision, SyncRecord, STATE_FILE_NAME,
+};
+use crate::utils::run_git_command;
+use crate::{PorterError, ProjectConfig, Result};
+use log::{debug, info};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A commit of the internal repo that moved the whole `project_subdir` somewhere else.
+#[derive(Debug, Clone)]
+pub struct SubdirRelocation {
+  pub commit: String,
+  pub subject: String,
+  pub from: PathBuf,
+  pub to: PathBuf,
+  pub changed: Vec<String>, // Published files (subdir-relative) also edited, added or removed by the move
+}
+
+/// Checks whether `project_subdir` is gone from `origin/<internal_branch>` because it was
+/// moved: the last commit touching it must have renamed its files below one new directory.
+/// Returns `None` if the subdir still exists or its files went elsewhere.
+pub fn find_subdir_relocation(config: &ProjectConfig) -> Result<Option<SubdirRelocation>> {
+  if config.project_subdir == Path::new(".") {
+    return Ok(None);
+  }
+  let internal_repo = &config.internal_repo_path;
+  let branch = format!("origin/{}", config.internal_branch);
+  let subdir = config.project_subdir.to_string_lossy();
+  let listing = run_git_command(
+    &["ls-tree", "-d", "--name-only", &branch, "--", &subdir],
+    internal_repo,
+  )?;
+  if !listing.stdout.is_empty() {
+    return Ok(None);
+  }
+  let log_output = run_git_command(
+    &["log", "-1", "--format=%H%x00%s", &branch, "--", &subdir],
+    internal_repo,
+  )?;
+  let log = String::from_utf8_lossy(&log_output.stdout);
+  let Some((commit, subject)) = log.trim_end().split_once('\x00') else {
+    return Ok(None); // The subdir never existed on the branch
+  };
+  debug!(
+    "'{}' is gone from {}; checking whether commit {} moved it.",
+    subdir, branch, commit
+  );
+  let relocation = detect_relocation(config, commit)?.map(|(to, changed)| SubdirRelocation {
+    commit: commit.to_string(),
+    subject: subject.to_string(),
+    from: config.project_subdir.clone(),
+    to,
+    changed,
+  });
+  if let Some(relocation) = &relocation {
+    info!(
+      "Commit {} moved '{}' to '{}'.",
+      relocation.commit,
+      relocation.from.display(),
+      relocation.to.display()
+    );
+  }
+  Ok(relocation)
+}
+
+/// Uses rename detection on `commit` to find where the files of `project_subdir` went.
+/// Returns the new directory and the published files the commit changed besides moving them.
+fn detect_relocation(
+  config: &ProjectConfig,
+  commit: &str,
+) -> Result<Option<(PathBuf, Vec<String>)>> {
+  let output = run_git_command(
+    &[
+      "diff-tree",
+      "--no-commit-id",
+      "--root",
+      "-r",
+      "-z",
+      "--name-status",
+      "-M",
+      "--diff-merges=first-parent",
+      commit,
+    ],
+    &config.internal_repo_path,
+  )?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let subdir = &config.project_subdir;
+
+  let mut targets = HashSet::new();
+  let mut changed = Vec::new();
+  let mut added = Vec::new();
+  let mut fields = stdout.split('\x00').filter(|field| !field.is_empty());
+  while let Some(status) = fields.next() {
+    let Some(path) = fields.next() else {
+      break;
+    };
+    if !status.starts_with('R') {
+      match Path::new(path).strip_prefix(subdir) {
+        Ok(rel_path) => changed.push(rel_path.to_string_lossy().to_string()),
+        Err(_) if status == "A" => added.push(path),
+        Err(_) => {}
+      }
+      continue;
+    }
+    let Some(new_path) = fields.next() else {
+      break;
+    };
+    let Ok(rel_path) = Path::new(path).strip_prefix(subdir) else {
+      continue;
+    };
+    let rel_path = rel_path.to_string_lossy().to_string();
+    // The new directory is what precedes the unchanged subdir-relative path
+    let target = new_path
+      .strip_suffix(rel_path.as_str())
+      .and_then(|prefix| prefix.strip_suffix('/'));
+    match target {
+      Some(target) => {
+        targets.insert(target.to_string());
+        if status != "R100" {
+          changed.push(rel_path); // Edited while moved
+        }
+      }
+      None => changed.push(rel_path), // Moved elsewhere, i.e. removed from the project
+    }
+  }
+  if targets.len() != 1 {
+    debug!(
+      "Commit {} does not move '{}' to a single directory ({} candidates).",
+      commit,
+      subdir.display(),
+      targets.len()
+    );
+    return Ok(None);
+  }
+  let to = PathBuf::from(targets.into_iter().next().unwrap_or_default());
+  changed.extend(added.into_iter().filter_map(|path| {
+    Path::new(path)
+      .strip_prefix(&to)
+      .ok()
+      .map(|rel_path| rel_path.to_string_lossy().to_string())
+  }));
+
+  let filter = PathFilter::from_config(config)?;
+  changed.retain(|rel_path| rel_path != STATE_FILE_NAME && filter.is_included(Path::new(rel_path)));
+  changed.sort();
+  changed.dedup();
+  Ok(Some((to, changed)))
+}
+
+/// Follows `relocation`: returns the configuration with `project_subdir` at its new place
+/// and records the move in the state file, with the move commit as `relocated` (it publishes
+/// nothing). The state file is read from the new place, so the internal checkout must
+/// include the move. Saving the returned configuration is up to the caller.
+pub fn follow_subdir_relocation(
+  config: &ProjectConfig,
+  relocation: &SubdirRelocation,
+) -> Result<ProjectConfig> {
+  let mut relocated = config.clone();
+  relocated.project_subdir = relocation.to.clone();
+  let state_path = get_internal_state_file_path(&relocated);
+  if !state_path.exists() {
+    return Err(PorterError::Config(format!(
+      "No sync state at '{}'. Update the internal checkout at {} to include commit {} first.",
+      state_path.display(),
+      config.internal_repo_path.display(),
+      relocation.commit
+    )));
+  }
+  record_subdir_move(
+    &relocated,
+    SubdirMove {
+      commit: relocation.commit.clone(),
+      from: relocation.from.clone(),
+      to: relocation.to.clone(),
+    },
+  )?;
+  record_sync_decision(
+    &relocated,
+    SyncRecord::now(&relocation.commit, None, SyncDecision::Relocated),
+  )?;
+  Ok(relocated)
+}
+
+/// Returns the configuration in effect for `commit`: if it predates recorded moves of the
+/// project subdir, `project_subdir` is where the project lived at that commit.
+pub fn config_at<'a>(config: &'a ProjectConfig, commit: &str) -> Result<Cow<'a, ProjectConfig>> {
+  let mut effective = Cow::Borrowed(config);
+  for subdir_move in read_subdir_moves(config)?.into_iter().rev() {
+    if includes_commit(config, commit, &subdir_move.commit)? {
+      break;
+    }
+    effective.to_mut().project_subdir = subdir_move.from;
+  }
+  Ok(effective)
+}
+
+/// True if `commit` has `ancestor` in its history (or is it).
+fn includes_commit(config: &ProjectConfig, commit: &str, ancestor: &str) -> Result<bool> {
+  let base = run_git_command(
+    &["merge-base", ancestor, commit],
+    &config.internal_repo_path,
+  )?;
+  Ok(String::from_utf8_lossy(&base.stdout).trim() == ancestor)
+}
+
+/// Returns the earlier locations of the project subdir, oldest first.
+pub fn former_subdirs(config: &ProjectConfig) -> Result<Vec<PathBuf>> {
+  Ok(
+    read_subdir_moves(config)?
+      .into_iter()
+      .map(|subdir_move| subdir_move.from)
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state::write_last_synced_commit;
+  use crate::testing::{self, git};
+
+  fn config(repo: &Path, subdir: &str) -> ProjectConfig {
+    toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = {:?}\noutput_path = \"out\"\n",
+      repo, subdir
+    ))
+    .unwrap()
+  }
+
+  #[test]
+  fn moved_subdir_is_detected_and_followed() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let lib = "pub fn lib() {}\n".repeat(10);
+    let base = testing::commit_files(
+      path,
+      &[
+        ("crates/foo/src/lib.rs", &lib),
+        ("crates/foo/b.rs", "fn b() {}\n"),
+      ],
+      "Base",
+    );
+    let config = config(path, "crates/foo");
+    write_last_synced_commit(&config, Some(&base)).unwrap();
+    testing::commit(path, "Record sync state");
+    assert!(find_subdir_relocation(&config).is_err()); // No origin/main yet
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    assert!(find_subdir_relocation(&config).unwrap().is_none());
+
+    std::fs::create_dir(path.join("libs")).unwrap();
+    git(path, &["mv", "crates/foo", "libs/foo"]);
+    testing::write_files(
+      path,
+      &[
+        ("libs/foo/b.rs", "fn b() { todo!() }\n"),
+        ("libs/foo/new.rs", "\n"),
+      ],
+    );
+    let moved = testing::commit(path, "Move foo to libs");
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+
+    let relocation = find_subdir_relocation(&config).unwrap().unwrap();
+    assert_eq!(relocation.commit, moved);
+    assert_eq!(relocation.to, Path::new("libs/foo"));
+    assert_eq!(relocation.changed, ["b.rs", "new.rs"]);
+
+    let relocated = follow_subdir_relocation(&config, &relocation).unwrap();
+    assert_eq!(relocated.project_subdir, Path::new("libs/foo"));
+    assert_eq!(
+      former_subdirs(&relocated).unwrap(),
+      [PathBuf::from("crates/foo")]
+    );
+    assert_eq!(
+      config_at(&relocated, &base).unwrap().project_subdir,
+      Path::new("crates/foo")
+    );
+    assert_eq!(
+      config_at(&relocated, &moved).unwrap().project_subdir,
+      Path::new("libs/foo")
+    );
+  }
+
+  #[test]
+  fn files_scattered_elsewhere_are_not_a_move() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let (a, b) = ("fn a() {}\n".repeat(10), "fn b() {}\n".repeat(10));
+    testing::commit_files(
+      path,
+      &[("crates/foo/a.rs", &a), ("crates/foo/b.rs", &b)],
+      "Base",
+    );
+    for (from, to, content) in [
+      ("crates/foo/a.rs", "x/a.rs", &a),
+      ("crates/foo/b.rs", "y/b.rs", &b),
+    ] {
+      git(path, &["rm", "-q", from]);
+      testing::write_files(path, &[(to, content.as_str())]);
+    }
+    let split = testing::commit(path, "Split foo");
+    let config = config(path, "crates/foo");
+    assert!(detect_relocation(&config, &split).unwrap().is_none());
+  }
+}
@@ -0,0 +1,499 @@
+// oss-porter-core/src/scrub.rs
+use crate::utils::{read_objects, run_git_command};
+use crate::{PorterError, ProjectConfig, Result};
+use regex::Regex;
+use std::{
+  collections::HashMap,
+  fmt,
+  path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripKind {
+  Markers, // Region between begin/end marker comments
+  CfgItem, // Rust item guarded by `#[cfg(<internal name>)]`
+}
+
+/// A region removed from a file before it was published. Line numbers are 1-based
+/// and refer to the original (internal) content.
+#[derive(Debug, Clone)]
+pub struct StrippedRegion {
+  pub path: PathBuf,
+  pub start_line: usize,
+  pub end_line: usize,
+  pub kind: StripKind,
+}
+
+impl fmt::Display for StrippedRegion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = match self.kind {
+      StripKind::Markers => "marker block",
+      StripKind::CfgItem => "cfg-gated item",
+    };
+    write!(
+      f,
+      "{}:{}-{} ({})",
+      self.path.display(),
+      self.start_line,
+      self.end_line,
+      kind
+    )
+  }
+}
+
+/// Removes internal-only regions from source text according to a project's `scrub` settings.
+pub struct Scrubber {
+  begin_marker: String,
+  end_marker: String,
+  cfg_attr: Option<Regex>,
+}
+
+impl Scrubber {
+  /// Builds a scrubber from the project's `scrub` table. Returns `None` if scrubbing is disabled.
+  pub fn from_config(config: &ProjectConfig) -> Result<Option<Self>> {
+    let scrub = &config.scrub;
+    if !scrub.enabled {
+      return Ok(None);
+    }
+    if scrub.begin_marker.trim().is_empty() || scrub.end_marker.trim().is_empty() {
+      return Err(PorterError::Config(
+        "Scrub markers 'begin_marker' and 'end_marker' must not be empty.".to_string(),
+      ));
+    }
+    let cfg_attr = if scrub.cfg_names.is_empty() {
+      None
+    } else {
+      let names: Vec<String> = scrub.cfg_names.iter().map(|n| regex::escape(n)).collect();
+      let pattern = format!(r"^\s*#\[cfg\(\s*(?:{})\s*\)\]", names.join("|"));
+      Some(
+        Regex::new(&pattern)
+          .map_err(|e| PorterError::Config(format!("Invalid scrub cfg name list: {}", e)))?,
+      )
+    };
+    Ok(Some(Scrubber {
+      begin_marker: scrub.begin_marker.clone(),
+      end_marker: scrub.end_marker.clone(),
+      cfg_attr,
+    }))
+  }
+
+  /// Strips marked regions from `content`. `path` is only used for reporting and to decide
+  /// whether Rust `#[cfg(..)]` items apply (`.rs` files only).
+  /// Returns the scrubbed text and the regions removed.
+  pub fn scrub_text(&self, path: &Path, content: &str) -> Result<(String, Vec<StrippedRegion>)> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let is_rust = path.extension().is_some_and(|ext| ext == "rs");
+    let mut kept = String::with_capacity(content.len());
+    let mut regions = Vec::new();
+
+    let mut i = 0;
+    for (idx, line) in lines.iter().enumerate() {
+      self.check_marker_placement(path, idx, line)?;
+    }
+    while i < lines.len() {
+      let line = lines[i];
+      if line.contains(&self.end_marker) && !line.contains(&self.begin_marker) {
+        return Err(PorterError::Scrub(format!(
+          "{}:{}: '{}' without a matching '{}'",
+          path.display(),
+          i + 1,
+          self.end_marker,
+          self.begin_marker
+        )));
+      }
+      if line.contains(&self.begin_marker) && line.contains(&self.end_marker) {
+        regions.push(self.region(path, i, i, StripKind::Markers));
+        i += 1;
+        continue;
+      }
+      if line.contains(&self.begin_marker) {
+        let end = (i + 1..lines.len())
+          .find(|&j| lines[j].contains(&self.end_marker) || lines[j].contains(&self.begin_marker))
+          .filter(|&j| !lines[j].contains(&self.begin_marker))
+          .ok_or_else(|| {
+            PorterError::Scrub(format!(
+              "{}:{}: '{}' is not closed by '{}' (nested blocks are not supported)",
+              path.display(),
+              i + 1,
+              self.begin_marker,
+              self.end_marker
+            ))
+          })?;
+        regions.push(self.region(path, i, end, StripKind::Markers));
+        i = end + 1;
+        continue;
+      }
+      if is_rust {
+        if let Some(cfg_attr) = &self.cfg_attr {
+          if let Some(m) = cfg_attr.find(line) {
+            let end = rust_item_end(&lines, i, m.end()).ok_or_else(|| {
+              PorterError::Scrub(format!(
+                "{}:{}: could not find the end of the item guarded by '{}'",
+                path.display(),
+                i + 1,
+                m.as_str().trim()
+              ))
+            })?;
+            regions.push(self.region(path, i, end, StripKind::CfgItem));
+            i = end + 1;
+            continue;
+          }
+        }
+      }
+      kept.push_str(line);
+      i += 1;
+    }
+    Ok((kept, regions))
+  }
+
+  /// Rejects a marker that follows code on its line: removing the whole line would silently
+  /// drop that code too.
+  fn check_marker_placement(&self, path: &Path, idx: usize, line: &str) -> Result<()> {
+    let first_marker = [&self.begin_marker, &self.end_marker]
+      .into_iter()
+      .filter_map(|marker| line.find(marker.as_str()).map(|pos| (pos, marker)))
+      .min();
+    match first_marker {
+      Some((pos, marker)) if !MARKER_LINE_PREFIXES.contains(&line[..pos].trim()) => {
+        Err(PorterError::Scrub(format!(
+          "{}:{}: '{}' follows code on the same line; put strip markers on their own comment line",
+          path.display(),
+          idx + 1,
+          marker
+        )))
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Scrubs every file version reachable from any ref of the repository at `repo_path`
+  /// and returns the internal-only regions found, without changing anything. Used to check
+  /// a history rewritten by `git-filter-repo`, which cannot scrub file contents itself.
+  pub fn scan_history(&self, repo_path: &Path) -> Result<Vec<StrippedRegion>> {
+    let listing = run_git_command(&["rev-list", "--objects", "--all"], repo_path)?;
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    let paths: HashMap<&str, &str> = listing
+      .lines()
+      .filter_map(|line| line.split_once(' '))
+      .collect();
+    let revs: String = paths.keys().map(|oid| format!("{}\n", oid)).collect();
+    let mut regions = Vec::new();
+    for (oid, kind, content) in read_objects(repo_path, revs.as_bytes())? {
+      let text = std::str::from_utf8(&content);
+      let (Some(path), "blob", Ok(text)) = (paths.get(oid.as_str()), kind.as_str(), text) else {
+        continue; // Trees, and binary or non-UTF-8 files
+      };
+      regions.extend(self.scrub_text(Path::new(path), text)?.1);
+    }
+    Ok(regions)
+  }
+
+  fn region(&self, path: &Path, start: usize, end: usize, kind: StripKind) -> StrippedRegion {
+    StrippedRegion {
+      path: path.to_path_buf(),
+      start_line: start + 1,
+      end_line: end + 1,
+      kind,
+    }
+  }
+}
+
+/// What may precede a strip marker on its line: nothing or a comment opener.
+const MARKER_LINE_PREFIXES: &[&str] = &["", "//", "#", "/*", "*", "--", "<!--", ";", "%"];
+
+/// Keywords that start a Rust item or statement (rather than a struct field, enum variant or
+/// match arm); such items never end at a `,`.
+const ITEM_KEYWORDS: &[&str] = &[
+  "async",
+  "const",
+  "enum",
+  "extern",
+  "fn",
+  "impl",
+  "let",
+  "macro_rules",
+  "mod",
+  "static",
+  "struct",
+  "trait",
+  "type",
+  "union",
+  "unsafe",
+  "use",
+];
+
+/// Finds the (0-based) line on which the Rust item following a `#[cfg(..)]` attribute ends.
+/// Scanning starts on line `start` at byte `offset`, just past the attribute. Further
+/// attributes and comments are skipped; the item ends at a top-level `;`, when the first
+/// brace block closes, or, for fields, variants and match arms, at a top-level `,` outside
+/// generic arguments (`<..>`, ignoring the `>` of `->` and `=>`). String, char and comment
+/// contents are ignored.
+fn rust_item_end(lines: &[&str], start: usize, offset: usize) -> Option<usize> {
+  let mut depth: i32 = 0; // (), [] and {} nesting
+  let mut angle_depth: i32 = 0; // <> nesting outside of the other brackets
+  let mut seen_brace = false;
+  let mut in_item = false;
+  let mut leading = true; // Still reading the item's leading words (`pub`, `fn`, ...)
+  let mut keyword_item = false;
+  let mut in_block_comment = false;
+  let mut in_string = false;
+
+  for (line_idx, line) in lines.iter().enumerate().skip(start) {
+    let chars: Vec<char> = if line_idx == start {
+      line[offset..].chars().collect()
+    } else {
+      line.chars().collect()
+    };
+    let mut j = 0;
+    while j < chars.len() {
+      let c = chars[j];
+      let prev = j.checked_sub(1).map(|p| chars[p]);
+      let next = chars.get(j + 1).copied();
+      if in_block_comment {
+        if c == '*' && next == Some('/') {
+          in_block_comment = false;
+          j += 1;
+        }
+      } else if in_string {
+        if c == '\\' {
+          j += 1;
+        } else if c == '"' {
+          in_string = false;
+        }
+      } else if c == '/' && next == Some('/') {
+        break; // Rest of the line is a comment
+      } else if c == '/' && next == Some('*') {
+        in_block_comment = true;
+        j += 1;
+      } else if c == '"' {
+        in_string = true;
+        in_item |= depth == 0;
+        leading &= depth != 0;
+      } else if c == '\'' {
+        // Char literal ('x' or '\n'); anything else is a lifetime.
+        if next == Some('\\') {
+          j += chars
+            .get(j + 2..)
+            .and_then(|rest| rest.iter().position(|&ch| ch == '\''))
+            .map_or(0, |p| p + 2);
+        } else if chars.get(j + 2) == Some(&'\'') {
+          j += 2;
+        }
+        in_item |= depth == 0;
+        leading &= depth != 0;
+      } else if c == '#' && !in_item && depth == 0 {
+        // A further attribute on the same item; its brackets are tracked as nesting.
+      } else if (c.is_alphanumeric() || c == '_') && depth == 0 {
+        let word_end = (j..chars.len())
+          .find(|&k| !(chars[k].is_alphanumeric() || chars[k] == '_'))
+          .unwrap_or(chars.len());
+        let word: String = chars[j..word_end].iter().collect();
+        keyword_item |= leading && ITEM_KEYWORDS.contains(&word.as_str());
+        in_item = true;
+        j = word_end;
+        continue;
+      } else if c == '(' || c == '[' || c == '{' {
+        if depth == 0 && c != '[' {
+          in_item = true;
+          seen_brace |= c == '{';
+        }
+        leading &= !(depth == 0 && in_item);
+        depth += 1;
+      } else if c == ')' || c == ']' || c == '}' {
+        depth -= 1;
+        if depth == 0 && c == '}' && seen_brace {
+          return Some(line_idx);
+        }
+        if depth < 0 {
+          // Closed the enclosing block (e.g. last struct field without a trailing comma),
+          // so the item ended on the previous line.
+          return (line_idx > start).then(|| line_idx - 1);
+        }
+      } else if depth == 0 && !c.is_whitespace() {
+        in_item = true;
+        leading = false;
+        match c {
+          '<' => angle_depth += 1,
+          '>' if prev != Some('-') && prev != Some('=') => angle_depth = (angle_depth - 1).max(0),
+          ';' => return Some(line_idx),
+          ',' if angle_depth == 0 && !keyword_item => return Some(line_idx),
+          _ => {}
+        }
+      }
+      j += 1;
+    }
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+
+  fn scrubber() -> Scrubber {
+    Scrubber {
+      begin_marker: "OSS-STRIP-BEGIN".to_string(),
+      end_marker: "OSS-STRIP-END".to_string(),
+      cfg_attr: Some(Regex::new(r"^\s*#\[cfg\(\s*(?:internal)\s*\)\]").unwrap()),
+    }
+  }
+
+  fn scrub(content: &str) -> (String, Vec<StrippedRegion>) {
+    scrubber()
+      .scrub_text(Path::new("src/lib.rs"), content)
+      .unwrap()
+  }
+
+  #[test]
+  fn fn_returning_generic_with_comma_is_stripped_whole() {
+    let (text, regions) =
+      scrub("#[cfg(internal)]\nfn f() -> Result<(), E> {\n  Ok(())\n}\nfn g() {}\n");
+    assert_eq!(text, "fn g() {}\n");
+    assert_eq!((regions[0].start_line, regions[0].end_line), (1, 4));
+  }
+
+  #[test]
+  fn generic_impl_is_stripped_whole() {
+    let (text, _) =
+      scrub("#[cfg(internal)]\nimpl<A, B> Trait for X<A, B> {\n  fn t(&self) {}\n}\nstruct Y;\n");
+    assert_eq!(text, "struct Y;\n");
+  }
+
+  #[test]
+  fn generic_struct_is_stripped_whole() {
+    let (text, _) =
+      scrub("#[cfg(internal)]\nstruct S<K, V> {\n  map: HashMap<K, V>,\n}\nstruct T;\n");
+    assert_eq!(text, "struct T;\n");
+  }
+
+  #[test]
+  fn gated_fields_and_variants_end_at_their_comma() {
+    let (text, regions) = scrub(
+      "struct S {\n  #[cfg(internal)]\n  map: HashMap<K, V>,\n  b: u8,\n}\nenum E {\n  A,\n  #[cfg(internal)] B { x: u8 },\n  C,\n}\n",
+    );
+    assert_eq!(text, "struct S {\n  b: u8,\n}\nenum E {\n  A,\n  C,\n}\n");
+    assert_eq!(regions.len(), 2);
+  }
+
+  #[test]
+  fn last_field_without_comma_ends_before_the_closing_brace() {
+    let (text, _) = scrub("struct S {\n  a: u8,\n  #[cfg(internal)]\n  b: Vec<u8>\n}\n");
+    assert_eq!(text, "struct S {\n  a: u8,\n}\n");
+  }
+
+  #[test]
+  fn statements_end_at_semicolon() {
+    let (text, _) = scrub(
+      "fn f() {\n  #[cfg(internal)]\n  let g = |a, b| a + b;\n  #[cfg(internal)]\n  use crate::internal::{A, B};\n  h();\n}\n",
+    );
+    assert_eq!(text, "fn f() {\n  h();\n}\n");
+  }
+
+  #[test]
+  fn match_arm_with_fat_arrow_ends_at_its_comma() {
+    let (text, _) = scrub("match x {\n  #[cfg(internal)]\n  X::<A, B> => g(a, b),\n  _ => {}\n}\n");
+    assert_eq!(text, "match x {\n  _ => {}\n}\n");
+  }
+
+  #[test]
+  fn strings_comments_and_further_attributes_are_skipped() {
+    let (text, _) = scrub(
+      "#[cfg(internal)]\n#[derive(Debug)] // }\nfn f() { let s = \"}\"; let c = '}'; }\nfn g<'a>() {}\n",
+    );
+    assert_eq!(text, "fn g<'a>() {}\n");
+  }
+
+  #[test]
+  fn marker_blocks_are_stripped() {
+    let (text, regions) = scrub(
+      "a\n// OSS-STRIP-BEGIN\nsecret\n// OSS-STRIP-END\nb\n  # OSS-STRIP-BEGIN x OSS-STRIP-END\nc\n",
+    );
+    assert_eq!(text, "a\nb\nc\n");
+    assert!(regions
+      .iter()
+      .all(|region| region.kind == StripKind::Markers));
+    assert_eq!((regions[0].start_line, regions[0].end_line), (2, 4));
+  }
+
+  #[test]
+  fn markers_following_code_are_errors() {
+    let scrubber = scrubber();
+    assert!(scrubber
+      .scrub_text(
+        Path::new("a.rs"),
+        "let a = 1; // OSS-STRIP-BEGIN\n// OSS-STRIP-END\n"
+      )
+      .is_err());
+    assert!(scrubber
+      .scrub_text(
+        Path::new("a.rs"),
+        "// OSS-STRIP-BEGIN\nx(); // OSS-STRIP-END\n"
+      )
+      .is_err());
+    let (text, _) = scrubber
+      .scrub_text(
+        Path::new("a.html"),
+        "<!-- OSS-STRIP-BEGIN -->\nx\n<!-- OSS-STRIP-END -->\ny\n",
+      )
+      .unwrap();
+    assert_eq!(text, "y\n");
+  }
+
+  #[test]
+  fn scrubbing_is_off_unless_enabled() {
+    let base =
+      "internal_repo_path = \"internal\"\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n";
+    let config: ProjectConfig =
+      toml::from_str(&format!("{}[scrub]\ncfg_names = [\"x\"]\n", base)).unwrap();
+    assert!(Scrubber::from_config(&config).unwrap().is_none());
+    let config: ProjectConfig =
+      toml::from_str(&format!("{}[scrub]\nenabled = true\n", base)).unwrap();
+    assert!(Scrubber::from_config(&config).unwrap().is_some());
+  }
+
+  #[test]
+  fn scan_history_finds_regions_in_earlier_commits() {
+    let repo = testing::init_repo();
+    testing::commit_files(
+      repo.path(),
+      &[("a.rs", "// OSS-STRIP-BEGIN\nx\n// OSS-STRIP-END\n")],
+      "Add",
+    );
+    testing::commit_files(
+      repo.path(),
+      &[("a.rs", "y\n"), ("logo.png", "\u{0}\u{ff}")],
+      "Clean",
+    );
+    let regions = scrubber().scan_history(repo.path()).unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].path, Path::new("a.rs"));
+  }
+
+  #[test]
+  fn cfg_items_are_kept_outside_rust_files() {
+    let content = "#[cfg(internal)]\nfn f() {}\n";
+    let (text, regions) = scrubber()
+      .scrub_text(Path::new("README.md"), content)
+      .unwrap();
+    assert_eq!(text, content);
+    assert!(regions.is_empty());
+  }
+
+  #[test]
+  fn unbalanced_markers_are_errors() {
+    let scrubber = scrubber();
+    assert!(scrubber
+      .scrub_text(Path::new("a.rs"), "// OSS-STRIP-BEGIN\nx\n")
+      .is_err());
+    assert!(scrubber
+      .scrub_text(Path::new("a.rs"), "x\n// OSS-STRIP-END\n")
+      .is_err());
+    assert!(scrubber
+      .scrub_text(
+        Path::new("a.rs"),
+        "// OSS-STRIP-BEGIN\n// OSS-STRIP-BEGIN\n// OSS-STRIP-END\n"
+      )
+      .is_err());
+  }
+}
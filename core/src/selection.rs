@@ -0,0 +1,150 @@
+// oss-porter-core/src/selection.rs
+use crate::update::CommitInfo;
+use crate::{PorterError, ProjectConfig, Result, SelectionRule};
+use std::fmt;
+
+impl fmt::Display for SelectionRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match (&self.trailer, &self.tag) {
+      (Some(trailer), Some(tag)) => write!(f, "trailer '{}' with tag '{}'", trailer, tag),
+      (Some(trailer), None) => write!(f, "trailer '{}'", trailer),
+      (None, Some(tag)) => write!(f, "tag '{}'", tag),
+      (None, None) => write!(f, "empty rule"),
+    }
+  }
+}
+
+/// Decides what `update` does with a commit from the trailers and tags engineers put in
+/// internal commit messages (`OSS: yes`, `[no-oss]`, ...), using a project's `selection_rules`.
+pub struct SelectionRules<'a> {
+  rules: &'a [SelectionRule],
+}
+
+impl<'a> SelectionRules<'a> {
+  /// Validates the project's selection rules. Returns `None` if none are configured.
+  pub fn from_config(config: &'a ProjectConfig) -> Result<Option<Self>> {
+    if config.selection_rules.is_empty() {
+      return Ok(None);
+    }
+    if let Some(rule) = config
+      .selection_rules
+      .iter()
+      .find(|rule| rule.trailer.is_none() && rule.tag.is_none())
+    {
+      return Err(PorterError::Config(format!(
+        "Selection rule with action '{:?}' needs a `trailer` or a `tag`.",
+        rule.action
+      )));
+    }
+    Ok(Some(SelectionRules {
+      rules: &config.selection_rules,
+    }))
+  }
+
+  /// Returns the first rule matching `commit`. A rule with both a trailer and a tag needs both.
+  pub fn matching_rule(&self, commit: &CommitInfo) -> Option<&'a SelectionRule> {
+    self.rules.iter().find(|rule| {
+      rule
+        .trailer
+        .as_deref()
+        .is_none_or(|trailer| has_trailer(commit, trailer))
+        && rule.tag.as_deref().is_none_or(|tag| has_tag(commit, tag))
+    })
+  }
+}
+
+/// True if the commit has the trailer `Key: value` (or any `Key` trailer if no value is given).
+/// Keys compare case-insensitively, values exactly.
+pub fn has_trailer(commit: &CommitInfo, trailer: &str) -> bool {
+  let (wanted_key, wanted_value) = match trailer.split_once(':') {
+    Some((key, value)) => (key.trim(), Some(value.trim())),
+    None => (trailer.trim(), None),
+  };
+  commit.trailers.iter().any(|(key, value)| {
+    key.eq_ignore_ascii_case(wanted_key) && wanted_value.is_none_or(|wanted| wanted == value)
+  })
+}
+
+fn has_tag(commit: &CommitInfo, tag: &str) -> bool {
+  commit.message.to_lowercase().contains(&tag.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::SelectionAction;
+
+  fn config(rules: &str) -> ProjectConfig {
+    toml::from_str(&format!(
+      "internal_repo_path = \"internal\"\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n{}",
+      rules
+    ))
+    .unwrap()
+  }
+
+  fn commit(message: &str, trailers: &[(&str, &str)]) -> CommitInfo {
+    CommitInfo {
+      hash: "abc123".to_string(),
+      subject: message.lines().next().unwrap_or_default().to_string(),
+      message: message.to_string(),
+      trailers: trailers
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn trailer_keys_ignore_case_but_values_do_not() {
+    let commit = commit("Fix\n\noss: yes", &[("oss", "yes")]);
+    assert!(has_trailer(&commit, "OSS: yes"));
+    assert!(has_trailer(&commit, " Oss "));
+    assert!(!has_trailer(&commit, "OSS: Yes"));
+    assert!(!has_trailer(&commit, "Public"));
+  }
+
+  fn action(rules: &SelectionRules, commit: &CommitInfo) -> SelectionAction {
+    rules
+      .matching_rule(commit)
+      .map_or(SelectionAction::Review, |rule| rule.action)
+  }
+
+  #[test]
+  fn first_matching_rule_wins() {
+    let config = config(
+      r#"
+[[selection_rules]]
+trailer = "OSS: no"
+tag = "[internal]"
+action = "review"
+
+[[selection_rules]]
+tag = "[NO-OSS]"
+action = "skip"
+
+[[selection_rules]]
+trailer = "OSS"
+action = "apply"
+"#,
+    );
+    let rules = SelectionRules::from_config(&config).unwrap().unwrap();
+    let both = commit("[internal] Fix\n\nOSS: no", &[("OSS", "no")]);
+    let tagged = commit("Fix [no-oss]\n\nOSS: yes", &[("OSS", "yes")]);
+    let trailer_only = commit("Fix\n\nOSS: no", &[("OSS", "no")]);
+    assert_eq!(action(&rules, &both), SelectionAction::Review);
+    assert_eq!(action(&rules, &tagged), SelectionAction::Skip);
+    assert_eq!(action(&rules, &trailer_only), SelectionAction::Apply);
+    assert_eq!(action(&rules, &commit("Fix", &[])), SelectionAction::Review);
+    assert_eq!(
+      rules.matching_rule(&tagged).unwrap().to_string(),
+      "tag '[NO-OSS]'"
+    );
+  }
+
+  #[test]
+  fn rules_need_a_trailer_or_tag() {
+    assert!(SelectionRules::from_config(&config("")).unwrap().is_none());
+    let config = config("[[selection_rules]]\naction = \"skip\"\n");
+    assert!(SelectionRules::from_config(&config).is_err());
+  }
+}
@@ -0,0 +1,274 @@
+// oss-porter-core/src/session.rs
+use crate::state::{get_internal_state_file_path, SyncDecision, SyncRecord};
+use crate::update::{abort_apply_session, output_git_dir, output_head, CommitInfo};
+use crate::utils::run_git_command;
+use crate::{PorterError, ProjectConfig, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+
+/// Name of the session file, kept in the output repo's git directory so it is never committed.
+pub const SESSION_FILE_NAME: &str = "oss_porter_update_session.toml";
+
+/// A commit whose `git am` stopped on a conflict in the output repo.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConflictedCommit {
+  pub commit: CommitInfo,
+  pub output_head: Option<String>, // Output HEAD while the patch was being applied
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub decision: Option<SyncDecision>, // Recorded once applied; `applied` if unset
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub omitted: Vec<String>, // Files/hunks the reviewer left out of the patch
+}
+
+impl ConflictedCommit {
+  /// The history record for the commit once it is applied as `public_commit`.
+  pub fn record(&self, public_commit: Option<&str>) -> SyncRecord {
+    let decision = self.decision.unwrap_or(SyncDecision::Applied);
+    SyncRecord::now(&self.commit.hash, public_commit, decision).with_omitted(self.omitted.clone())
+  }
+}
+
+/// An interactive `update` run, saved after every decision so it can be resumed with
+/// `update --continue` after a conflict or rolled back with `update --abort`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpdateSession {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub since_commit: Option<String>, // Sync cursor when the session started
+  pub output_head: Option<String>, // Output HEAD when the session started
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub state_backup: Option<String>, // State file content when the session started
+  #[serde(default)]
+  pub apply_all: bool, // "Apply ALL remaining" was chosen
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub current: Option<ConflictedCommit>,
+  #[serde(default)]
+  pub queue: VecDeque<CommitInfo>, // Commits still to review, in order
+  #[serde(default)]
+  pub decisions: Vec<SyncRecord>, // Decisions taken so far in this session
+}
+
+impl UpdateSession {
+  /// Starts a session reviewing `queue` from `since_commit`, remembering the output HEAD and
+  /// the state file so `--abort` can restore both. Nothing is written until [`save`](Self::save).
+  pub fn start(
+    config: &ProjectConfig,
+    since_commit: Option<&str>,
+    queue: VecDeque<CommitInfo>,
+  ) -> Result<Self> {
+    let state_path = get_internal_state_file_path(config);
+    let state_backup = if state_path.exists() {
+      Some(
+        fs::read_to_string(&state_path).map_err(|e| PorterError::Io {
+          source: e,
+          path: state_path,
+        })?,
+      )
+    } else {
+      None
+    };
+    Ok(UpdateSession {
+      since_commit: since_commit.map(str::to_string),
+      output_head: output_head(config)?,
+      state_backup,
+      apply_all: false,
+      current: None,
+      queue,
+      decisions: Vec::new(),
+    })
+  }
+
+  /// Marks `commit` as stopped on a conflict; `update --continue` picks it up from here and
+  /// records `record`'s decision and omitted parts once it is applied.
+  pub fn mark_conflict(
+    &mut self,
+    config: &ProjectConfig,
+    commit: CommitInfo,
+    record: &SyncRecord,
+  ) -> Result<()> {
+    self.current = Some(ConflictedCommit {
+      commit,
+      output_head: output_head(config)?,
+      decision: Some(record.decision),
+      omitted: record.omitted.clone(),
+    });
+    Ok(())
+  }
+
+  /// Writes the session file.
+  pub fn save(&self, config: &ProjectConfig) -> Result<()> {
+    let path = session_file_path(config)?;
+    let content = toml::to_string_pretty(self).map_err(PorterError::TomlSerialize)?;
+    debug!("Saving update session to {}", path.display());
+    fs::write(&path, content).map_err(|e| PorterError::Io { source: e, path })
+  }
+}
+
+/// Returns the path of the session file inside the output repo's git directory.
+pub fn session_file_path(config: &ProjectConfig) -> Result<PathBuf> {
+  Ok(output_git_dir(config)?.join(SESSION_FILE_NAME))
+}
+
+/// Loads the update session in progress for the project, if any.
+pub fn load_update_session(config: &ProjectConfig) -> Result<Option<UpdateSession>> {
+  let path = session_file_path(config)?;
+  if !path.exists() {
+    return Ok(None);
+  }
+  let content = fs::read_to_string(&path).map_err(|e| PorterError::Io {
+    source: e,
+    path: path.clone(),
+  })?;
+  let session = toml::from_str(&content).map_err(|e| PorterError::TomlParse { source: e, path })?;
+  Ok(Some(session))
+}
+
+/// Removes the session file (the session is finished).
+pub fn clear_update_session(config: &ProjectConfig) -> Result<()> {
+  let path = session_file_path(config)?;
+  if path.exists() {
+    fs::remove_file(&path).map_err(|e| PorterError::Io { source: e, path })?;
+  }
+  Ok(())
+}
+
+/// Rolls a session back: aborts the pending `git am`, resets the output repo to where the
+/// session started (dropping the commits applied in it) and restores the state file.
+pub fn abort_update_session(config: &ProjectConfig, session: &UpdateSession) -> Result<()> {
+  abort_apply_session(config)?;
+  match &session.output_head {
+    Some(head) => {
+      info!("Resetting {} to {}", config.output_path.display(), head);
+      run_git_command(&["reset", "--hard", "-q", head], &config.output_path)?;
+    }
+    None => debug!("Output repo had no commits when the session started; leaving it alone."),
+  }
+  let state_path = get_internal_state_file_path(config);
+  match &session.state_backup {
+    Some(content) => fs::write(&state_path, content),
+    None if state_path.exists() => fs::remove_file(&state_path),
+    None => Ok(()),
+  }
+  .map_err(|e| PorterError::Io {
+    source: e,
+    path: state_path,
+  })?;
+  info!("Restored the sync state from before the update session.");
+  clear_update_session(config)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state::{record_sync_decision, write_last_synced_commit, SyncDecision};
+  use crate::testing::{self, git};
+  use crate::update::{
+    apply_commit_to_output, apply_in_progress, continue_apply_session, ApplyResult,
+  };
+  use tempfile::TempDir;
+
+  struct Repos {
+    internal: TempDir,
+    output: TempDir,
+    config: ProjectConfig,
+  }
+
+  fn repos() -> Repos {
+    let internal = testing::init_repo();
+    let output = testing::init_repo();
+    git(output.path(), &["config", "user.name", "Test"]);
+    git(output.path(), &["config", "user.email", "test@example.com"]);
+    let config = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n",
+      internal.path(),
+      output.path()
+    ))
+    .unwrap();
+    Repos {
+      internal,
+      output,
+      config,
+    }
+  }
+
+  #[test]
+  fn abort_restores_the_output_head_and_state() {
+    let Repos {
+      internal,
+      output,
+      config,
+    } = repos();
+    testing::commit_files(internal.path(), &[("crates/foo/a", "1\n")], "Add");
+    let start = testing::commit_files(output.path(), &[("a", "1\n")], "Initial");
+    write_last_synced_commit(&config, Some("aaa111")).unwrap();
+    let state_before = fs::read_to_string(get_internal_state_file_path(&config)).unwrap();
+
+    let session = UpdateSession::start(&config, Some("aaa111"), VecDeque::new()).unwrap();
+    session.save(&config).unwrap();
+    testing::commit_files(output.path(), &[("a", "2\n")], "Applied in session");
+    record_sync_decision(
+      &config,
+      SyncRecord::now("bbb222", None, SyncDecision::Skipped),
+    )
+    .unwrap();
+
+    let loaded = load_update_session(&config).unwrap().unwrap();
+    assert_eq!(loaded.since_commit.as_deref(), Some("aaa111"));
+    abort_update_session(&config, &loaded).unwrap();
+    assert_eq!(git(output.path(), &["rev-parse", "HEAD"]), start);
+    let state_after = fs::read_to_string(get_internal_state_file_path(&config)).unwrap();
+    assert_eq!(state_after, state_before);
+    assert!(load_update_session(&config).unwrap().is_none());
+  }
+
+  #[test]
+  fn conflicted_commit_is_resumed_after_resolution() {
+    let Repos {
+      internal,
+      output,
+      config,
+    } = repos();
+    testing::commit_files(internal.path(), &[("crates/foo/a", "1\n")], "Add");
+    let change = testing::commit_files(internal.path(), &[("crates/foo/a", "2\n")], "Change");
+    testing::commit_files(output.path(), &[("a", "other\n")], "Diverged");
+
+    let mut session = UpdateSession::start(&config, None, VecDeque::new()).unwrap();
+    let result = apply_commit_to_output(&config, &change).unwrap();
+    assert!(matches!(result, ApplyResult::Conflict));
+    let info = CommitInfo {
+      hash: change.clone(),
+      subject: "Change".to_string(),
+      message: "Change\n".to_string(),
+      trailers: Vec::new(),
+    };
+    let record =
+      SyncRecord::now(&change, None, SyncDecision::Partial).with_omitted(vec!["b".to_string()]);
+    session.mark_conflict(&config, info, &record).unwrap();
+    session.save(&config).unwrap();
+
+    // Unresolved conflicts keep the session stopped.
+    assert!(matches!(
+      continue_apply_session(&config).unwrap(),
+      ApplyResult::Conflict
+    ));
+    testing::write_files(output.path(), &[("a", "2\n")]);
+    git(output.path(), &["add", "a"]);
+    let ApplyResult::Success(Some(head)) = continue_apply_session(&config).unwrap() else {
+      panic!("git am --continue did not finish");
+    };
+    assert!(!apply_in_progress(&config).unwrap());
+    assert_eq!(
+      git(output.path(), &["log", "-1", "--format=%s", &head]),
+      "Change"
+    );
+    let loaded = load_update_session(&config).unwrap().unwrap();
+    assert_eq!(loaded.since_commit, None);
+    // The partial decision and its left-out parts survive the stop.
+    let record = loaded.current.unwrap().record(Some(&head));
+    assert_eq!(record.internal_commit, change);
+    assert_eq!(record.decision, SyncDecision::Partial);
+    assert_eq!(record.omitted, ["b"]);
+  }
+}
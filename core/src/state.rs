@@ -1,17 +1,87 @@
 // oss-porter-core/src/state.rs
-use crate::utils::run_git_command; // Use from utils
+use crate::utils::{now_rfc3339, run_git_command}; // Use from utils
 use crate::{PorterError, ProjectConfig, Result};
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
+  collections::HashSet,
   fs,
   io::Write,
-  path::PathBuf,
+  path::{Path, PathBuf},
 };
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 struct StateFileContent {
   last_synced_internal_commit: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pending: Vec<String>, // Undecided commits after the cursor, as of the last update run
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  skipped: Vec<SkippedCommit>, // Never offered by `update` again
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  history: Vec<SyncRecord>, // Every reviewed commit, oldest first
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  relocations: Vec<SubdirMove>, // Moves of the project subdir followed by `update`, oldest first
+}
+
+/// An internal commit the reviewer chose never to publish.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedCommit {
+  pub commit: String,
+  pub reason: Option<String>,
+  pub timestamp: String, // RFC 3339, UTC
+}
+
+/// What the reviewer decided for an internal commit during `update`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDecision {
+  Applied,          // Published as is
+  Skipped,          // Never to be published
+  Edited,           // Published after the reviewer edited the patch
+  PreviouslySynced, // Passed by the sync cursor before per-commit records were kept
+  AlreadyApplied,   // Its change was already in the public repo (matched by patch-id)
+  Relocated,        // Moved the project subdir; nothing published
+  Squashed,         // Published together with other commits as one public commit
+  Partial,          // Published with only some of its files/hunks (see `omitted`)
+}
+
+/// One entry of the sync history: an internal commit and what became of it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncRecord {
+  pub internal_commit: String,
+  pub public_commit: Option<String>, // None if skipped or nothing was publishable
+  pub timestamp: String,             // RFC 3339, UTC
+  pub decision: SyncDecision,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub omitted: Vec<String>, // Files/hunks left out of a partial publication
+}
+
+impl SyncRecord {
+  /// Creates a record for `internal_commit` stamped with the current time.
+  pub fn now(internal_commit: &str, public_commit: Option<&str>, decision: SyncDecision) -> Self {
+    SyncRecord {
+      internal_commit: internal_commit.to_string(),
+      public_commit: public_commit.map(str::to_string),
+      timestamp: now_rfc3339(),
+      decision,
+      omitted: Vec::new(),
+    }
+  }
+
+  /// Sets the files/hunks that were left out of the published patch.
+  pub fn with_omitted(mut self, omitted: Vec<String>) -> Self {
+    self.omitted = omitted;
+    self
+  }
+}
+
+/// A move of the project subdir inside the internal repo. Commits before `commit` are
+/// still read from `from`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubdirMove {
+  pub commit: String, // Internal commit that moved the subdir
+  pub from: PathBuf,
+  pub to: PathBuf,
 }
 
 pub const STATE_FILE_NAME: &str = ".oss_porter_state.toml";
@@ -24,9 +94,8 @@ pub fn get_internal_state_file_path(config: &ProjectConfig) -> PathBuf {
     .join(STATE_FILE_NAME)
 }
 
-/// Reads the last synced commit hash from the state file in the internal repo.
-/// Returns Ok(None) if the file doesn't exist or the commit hash is not set.
-pub fn read_last_synced_commit(config: &ProjectConfig) -> Result<Option<String>> {
+/// Reads the whole state file. A missing or empty file is an empty state.
+fn read_state(config: &ProjectConfig) -> Result<StateFileContent> {
   let state_file_path = get_internal_state_file_path(config);
   debug!("Reading sync state from: {}", state_file_path.display());
 
@@ -35,7 +104,7 @@ pub fn read_last_synced_commit(config: &ProjectConfig) -> Result<Option<String>>
       "Sync state file not found at {}. Assuming no prior sync.",
       state_file_path.display()
     );
-    return Ok(None); // No state file means no previous sync state recorded
+    return Ok(StateFileContent::default()); // No state file means no previous sync state recorded
   }
 
   let content = fs::read_to_string(&state_file_path).map_err(|e| PorterError::Io {
@@ -48,40 +117,19 @@ pub fn read_last_synced_commit(config: &ProjectConfig) -> Result<Option<String>>
       "Sync state file {} is empty. Assuming no prior sync.",
       state_file_path.display()
     );
-    return Ok(None); // Empty file
+    return Ok(StateFileContent::default()); // Empty file
   }
 
-  let state: StateFileContent = toml::from_str(&content).map_err(|e| PorterError::TomlParse {
+  toml::from_str(&content).map_err(|e| PorterError::TomlParse {
     source: e,
     path: state_file_path,
-  })?;
-
-  // Normalize empty string to None
-  match state.last_synced_internal_commit {
-    Some(s) if s.trim().is_empty() => {
-      warn!("Sync state file contains empty commit hash. Assuming no prior sync.");
-      Ok(None)
-    }
-    other => Ok(other),
-  }
+  })
 }
 
-/// Writes the last synced commit hash to the state file in the internal repo.
-/// Overwrites existing file. Does NOT commit the change.
-pub fn write_last_synced_commit(config: &ProjectConfig, commit_hash: Option<&str>) -> Result<()> {
+/// Overwrites the state file with `state`. Does NOT commit the change.
+fn write_state(config: &ProjectConfig, state: &StateFileContent) -> Result<()> {
   let state_file_path = get_internal_state_file_path(config);
-  let hash_to_write = commit_hash.map(|s| s.to_string()); // Convert Option<&str> to Option<String>
-  debug!(
-    "Writing sync state {:?} to: {}",
-    &hash_to_write,
-    state_file_path.display()
-  );
-
-  let state = StateFileContent {
-    last_synced_internal_commit: hash_to_write,
-  };
-
-  let toml_string = toml::to_string_pretty(&state).map_err(|e| PorterError::TomlSerialize(e))?;
+  let toml_string = toml::to_string_pretty(state).map_err(PorterError::TomlSerialize)?;
 
   // Ensure parent directory exists (should normally be the project subdir)
   if let Some(parent) = state_file_path.parent() {
@@ -105,6 +153,239 @@ pub fn write_last_synced_commit(config: &ProjectConfig, commit_hash: Option<&str
   Ok(())
 }
 
+/// Reads the last synced commit hash from the state file in the internal repo.
+/// Returns Ok(None) if the file doesn't exist or the commit hash is not set.
+pub fn read_last_synced_commit(config: &ProjectConfig) -> Result<Option<String>> {
+  let state = read_state(config)?;
+
+  // Normalize empty string to None
+  match state.last_synced_internal_commit {
+    Some(s) if s.trim().is_empty() => {
+      warn!("Sync state file contains empty commit hash. Assuming no prior sync.");
+      Ok(None)
+    }
+    other => Ok(other),
+  }
+}
+
+/// Writes the last synced commit hash to the state file in the internal repo, keeping the
+/// recorded history. Does NOT commit the change.
+pub fn write_last_synced_commit(config: &ProjectConfig, commit_hash: Option<&str>) -> Result<()> {
+  let mut state = read_state(config)?;
+  state.last_synced_internal_commit = commit_hash.map(|s| s.to_string()); // Convert Option<&str> to Option<String>
+  debug!(
+    "Writing sync state {:?} to: {}",
+    &state.last_synced_internal_commit,
+    get_internal_state_file_path(config).display()
+  );
+  write_state(config, &state)
+}
+
+/// Returns the commits left undecided by the last `update` run, oldest first.
+pub fn read_pending_commits(config: &ProjectConfig) -> Result<Vec<String>> {
+  Ok(read_state(config)?.pending)
+}
+
+/// Stores the commits left undecided after the cursor. Does NOT commit the change.
+pub fn write_pending_commits(config: &ProjectConfig, pending: &[String]) -> Result<()> {
+  let mut state = read_state(config)?;
+  state.pending = pending.to_vec();
+  write_state(config, &state)
+}
+
+/// Returns every internal commit that needs no further review: applied or edited ones
+/// from the history, plus the permanently skipped ones.
+pub fn decided_commits(config: &ProjectConfig) -> Result<HashSet<String>> {
+  Ok(decided_in(&read_state(config)?))
+}
+
+fn decided_in(state: &StateFileContent) -> HashSet<String> {
+  let mut decided: HashSet<String> = state
+    .history
+    .iter()
+    .filter(|record| record.decision != SyncDecision::Skipped) // Skips can be undone
+    .map(|record| record.internal_commit.clone())
+    .collect();
+  decided.extend(state.skipped.iter().map(|skipped| skipped.commit.clone()));
+  decided
+}
+
+/// Returns the recorded moves of the project subdir, oldest first.
+pub fn read_subdir_moves(config: &ProjectConfig) -> Result<Vec<SubdirMove>> {
+  Ok(read_state(config)?.relocations)
+}
+
+/// Appends `subdir_move` to the state file. Does NOT commit the change.
+pub fn record_subdir_move(config: &ProjectConfig, subdir_move: SubdirMove) -> Result<()> {
+  let mut state = read_state(config)?;
+  debug!(
+    "Recording move of '{}' to '{}' by commit {}",
+    subdir_move.from.display(),
+    subdir_move.to.display(),
+    subdir_move.commit
+  );
+  state.relocations.push(subdir_move);
+  write_state(config, &state)
+}
+
+/// Returns the recorded sync history, oldest first.
+pub fn read_sync_history(config: &ProjectConfig) -> Result<Vec<SyncRecord>> {
+  Ok(read_state(config)?.history)
+}
+
+/// Appends `record` to the sync history in the state file. Does NOT commit the change.
+pub fn record_sync_decision(config: &ProjectConfig, record: SyncRecord) -> Result<()> {
+  let mut state = read_state(config)?;
+  debug!(
+    "Recording {:?} for internal commit {}",
+    record.decision, record.internal_commit
+  );
+  state.history.push(record);
+  write_state(config, &state)
+}
+
+/// Returns the commits marked as permanently skipped.
+pub fn read_skipped_commits(config: &ProjectConfig) -> Result<Vec<SkippedCommit>> {
+  Ok(read_state(config)?.skipped)
+}
+
+/// Marks `commit` (a full hash) as permanently skipped, so `update` no longer offers it.
+/// Skipping an already skipped commit only updates its reason. Does NOT commit the change.
+pub fn skip_commit(config: &ProjectConfig, commit: &str, reason: Option<&str>) -> Result<()> {
+  let mut state = read_state(config)?;
+  let reason = reason
+    .map(str::trim)
+    .filter(|r| !r.is_empty())
+    .map(str::to_string);
+  match state
+    .skipped
+    .iter_mut()
+    .find(|skipped| skipped.commit == commit)
+  {
+    Some(existing) => existing.reason = reason,
+    None => state.skipped.push(SkippedCommit {
+      commit: commit.to_string(),
+      reason,
+      timestamp: now_rfc3339(),
+    }),
+  }
+  info!("Marked internal commit {} as skipped.", commit);
+  write_state(config, &state)
+}
+
+/// Removes the skipped commit matching `commit` (full hash or unique prefix) so the next
+/// `update` offers it again. If the sync cursor already moved past it, the cursor is
+/// moved back to its parent (or cleared for a root commit); the other commits in between
+/// were already decided and are recorded as such, so they are not offered again.
+/// Returns the removed entry, or `None` if nothing matched. Does NOT commit the change.
+pub fn unskip_commit(config: &ProjectConfig, commit: &str) -> Result<Option<SkippedCommit>> {
+  let mut state = read_state(config)?;
+  let commit = commit.trim().to_lowercase();
+  let matches: Vec<usize> = state
+    .skipped
+    .iter()
+    .enumerate()
+    .filter(|(_, skipped)| skipped.commit.starts_with(&commit))
+    .map(|(i, _)| i)
+    .collect();
+  match matches.as_slice() {
+    [] => Ok(None),
+    [index] => {
+      let removed = state.skipped.remove(*index);
+      info!(
+        "Removed internal commit {} from the skipped commits.",
+        removed.commit
+      );
+      if let Some(cursor) = &state.last_synced_internal_commit {
+        let repo = &config.internal_repo_path;
+        let behind_cursor = run_git_command(
+          &["merge-base", "--is-ancestor", &removed.commit, cursor],
+          repo,
+        )
+        .is_ok();
+        if behind_cursor {
+          // Commits the cursor passed before history records existed are only decided
+          // by their position, so record them before the cursor moves behind them.
+          // Every commit `update` may have listed, whatever the merge strategy, including
+          // those from where the project subdir was before a move
+          let excluded = format!("^{}", removed.commit);
+          let mut subdirs: Vec<&Path> =
+            state.relocations.iter().map(|m| m.from.as_path()).collect();
+          subdirs.push(&config.project_subdir);
+          let pathspecs: Vec<String> = subdirs
+            .iter()
+            .flat_map(|subdir| {
+              [
+                subdir.to_string_lossy().to_string(),
+                format!(":(exclude){}", subdir.join(STATE_FILE_NAME).display()),
+              ]
+            })
+            .collect();
+          let mut args = vec!["rev-list", "--full-history", cursor, &excluded, "--"];
+          args.extend(pathspecs.iter().map(String::as_str));
+          let passed = run_git_command(&args, repo)?;
+          let decided = decided_in(&state);
+          for passed_commit in String::from_utf8_lossy(&passed.stdout).lines().rev() {
+            if !decided.contains(passed_commit) {
+              debug!("Recording {} as previously synced", passed_commit);
+              state.history.push(SyncRecord::now(
+                passed_commit,
+                None,
+                SyncDecision::PreviouslySynced,
+              ));
+            }
+          }
+
+          let parent = run_git_command(
+            &[
+              "rev-parse",
+              "--verify",
+              "-q",
+              &format!("{}^", removed.commit),
+            ],
+            repo,
+          )
+          .ok()
+          .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+          info!(
+            "Moving the sync cursor back from {} to {}.",
+            cursor,
+            parent.as_deref().unwrap_or("before the first commit")
+          );
+          state.last_synced_internal_commit = parent;
+        }
+      }
+      write_state(config, &state)?;
+      Ok(Some(removed))
+    }
+    _ => Err(PorterError::GitOperation(format!(
+      "'{}' matches {} skipped commits; use a longer prefix.",
+      commit,
+      matches.len()
+    ))),
+  }
+}
+
+/// Returns the history entries whose internal or public commit starts with `commit`
+/// (full hash or abbreviation), latest first.
+pub fn find_sync_records(config: &ProjectConfig, commit: &str) -> Result<Vec<SyncRecord>> {
+  let commit = commit.trim().to_lowercase();
+  Ok(
+    read_state(config)?
+      .history
+      .into_iter()
+      .rev()
+      .filter(|record| {
+        record.internal_commit.starts_with(&commit)
+          || record
+            .public_commit
+            .as_deref()
+            .is_some_and(|public| public.starts_with(&commit))
+      })
+      .collect(),
+  )
+}
+
 /// Commits the state file change in the internal repository.
 pub fn commit_state_file_change(config: &ProjectConfig, commit_hash: Option<&str>) -> Result<()> {
   // Use PathBuf::from for consistent path separator handling
@@ -154,3 +435,138 @@ pub fn commit_state_file_change(config: &ProjectConfig, commit_hash: Option<&str
   info!("Successfully committed state file update in internal repository.");
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+  use tempfile::TempDir;
+
+  fn config(repo: &TempDir) -> ProjectConfig {
+    toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n",
+      repo.path()
+    ))
+    .unwrap()
+  }
+
+  #[test]
+  fn sync_history_survives_cursor_updates() {
+    let repo = TempDir::new().unwrap();
+    let config = config(&repo);
+    assert!(read_sync_history(&config).unwrap().is_empty());
+    write_last_synced_commit(&config, Some("aaa111")).unwrap();
+    record_sync_decision(
+      &config,
+      SyncRecord::now("bbb222", Some("ccc333"), SyncDecision::Applied),
+    )
+    .unwrap();
+    record_sync_decision(
+      &config,
+      SyncRecord::now("ddd444", None, SyncDecision::Skipped),
+    )
+    .unwrap();
+    write_last_synced_commit(&config, Some("ddd444")).unwrap();
+
+    let history = read_sync_history(&config).unwrap();
+    let commits: Vec<&str> = history.iter().map(|r| r.internal_commit.as_str()).collect();
+    assert_eq!(commits, ["bbb222", "ddd444"]);
+    assert_eq!(history[1].decision, SyncDecision::Skipped);
+    assert_eq!(
+      read_last_synced_commit(&config).unwrap().as_deref(),
+      Some("ddd444")
+    );
+  }
+
+  #[test]
+  fn sync_records_are_found_by_internal_or_public_prefix() {
+    let repo = TempDir::new().unwrap();
+    let config = config(&repo);
+    record_sync_decision(
+      &config,
+      SyncRecord::now("abc123", Some("fed987"), SyncDecision::Applied),
+    )
+    .unwrap();
+    record_sync_decision(
+      &config,
+      SyncRecord::now("abc999", None, SyncDecision::Skipped),
+    )
+    .unwrap();
+    let found = find_sync_records(&config, " ABC ").unwrap();
+    let commits: Vec<&str> = found.iter().map(|r| r.internal_commit.as_str()).collect();
+    assert_eq!(commits, ["abc999", "abc123"]);
+    assert_eq!(
+      find_sync_records(&config, "fed9").unwrap()[0].internal_commit,
+      "abc123"
+    );
+    assert!(find_sync_records(&config, "123").unwrap().is_empty());
+  }
+
+  #[test]
+  fn skipping_twice_only_updates_the_reason() {
+    let repo = TempDir::new().unwrap();
+    let config = config(&repo);
+    skip_commit(&config, "abc123", Some("internal tooling")).unwrap();
+    skip_commit(&config, "abc123", Some("  ")).unwrap();
+    let skipped = read_skipped_commits(&config).unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].reason, None);
+  }
+
+  #[test]
+  fn unskip_needs_a_unique_prefix() {
+    let repo = TempDir::new().unwrap();
+    let config = config(&repo);
+    skip_commit(&config, "abc123", None).unwrap();
+    skip_commit(&config, "abd456", None).unwrap();
+    assert!(unskip_commit(&config, "ab").is_err());
+    assert!(unskip_commit(&config, "fff").unwrap().is_none());
+    assert_eq!(
+      unskip_commit(&config, "ABD").unwrap().unwrap().commit,
+      "abd456"
+    );
+    let skipped = read_skipped_commits(&config).unwrap();
+    assert_eq!(
+      skipped
+        .iter()
+        .map(|s| s.commit.as_str())
+        .collect::<Vec<_>>(),
+      ["abc123"]
+    );
+  }
+
+  #[test]
+  fn unskipping_a_root_commit_clears_the_cursor() {
+    let repo = testing::init_repo();
+    let root = testing::commit_files(repo.path(), &[("crates/foo/a", "1\n")], "Root");
+    let config = config(&repo);
+    skip_commit(&config, &root, None).unwrap();
+    write_last_synced_commit(&config, Some(&root)).unwrap();
+    unskip_commit(&config, &root).unwrap().unwrap();
+    assert_eq!(read_last_synced_commit(&config).unwrap(), None);
+  }
+
+  #[test]
+  fn unskipping_keeps_commits_synced_before_history_decided() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let base = testing::commit_files(path, &[("crates/foo/a", "1\n")], "Base");
+    let skipped = testing::commit_files(path, &[("crates/foo/a", "2\n")], "Skipped");
+    let synced = testing::commit_files(path, &[("crates/foo/b", "1\n")], "Synced");
+    testing::commit_files(path, &[("crates/bar/c", "1\n")], "Elsewhere");
+    let head = testing::commit_files(path, &[("crates/foo/b", "2\n")], "Also synced");
+    let config = config(&repo);
+    skip_commit(&config, &skipped, None).unwrap();
+    write_last_synced_commit(&config, Some(&head)).unwrap();
+
+    unskip_commit(&config, &skipped[..8]).unwrap().unwrap();
+    assert_eq!(read_last_synced_commit(&config).unwrap(), Some(base));
+    let history = read_sync_history(&config).unwrap();
+    let commits: Vec<&str> = history.iter().map(|r| r.internal_commit.as_str()).collect();
+    assert_eq!(commits, [synced.as_str(), head.as_str()]);
+    assert!(history
+      .iter()
+      .all(|r| r.decision == SyncDecision::PreviouslySynced));
+    assert!(!decided_commits(&config).unwrap().contains(&skipped));
+  }
+}
@@ -0,0 +1,56 @@
+// oss-porter-core/src/testing.rs
+//! Helpers for unit tests that need a real git repository.
+use std::{fs, path::Path, process::Command};
+use tempfile::TempDir;
+
+/// Runs git in `repo` with a fixed identity and returns its trimmed stdout. Panics on failure.
+pub fn git(repo: &Path, args: &[&str]) -> String {
+  let output = Command::new("git")
+    .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+    .args([
+      "-c",
+      "commit.gpgsign=false",
+      "-c",
+      "init.defaultBranch=main",
+    ])
+    .args(args)
+    .current_dir(repo)
+    .output()
+    .expect("git runs");
+  assert!(
+    output.status.success(),
+    "git {:?} failed: {}",
+    args,
+    String::from_utf8_lossy(&output.stderr)
+  );
+  String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Creates an empty repository on branch `main` in a new temporary directory.
+pub fn init_repo() -> TempDir {
+  let dir = TempDir::new().expect("temp dir");
+  git(dir.path(), &["init", "-q"]);
+  dir
+}
+
+/// Writes `files` (path, content) below `repo`, creating parent directories.
+pub fn write_files(repo: &Path, files: &[(&str, &str)]) {
+  for (path, content) in files {
+    let path = repo.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+  }
+}
+
+/// Stages everything and commits it; returns the new commit hash.
+pub fn commit(repo: &Path, message: &str) -> String {
+  git(repo, &["add", "-A"]);
+  git(repo, &["commit", "-q", "--allow-empty", "-m", message]);
+  git(repo, &["rev-parse", "HEAD"])
+}
+
+/// Writes `files` and commits them; returns the new commit hash.
+pub fn commit_files(repo: &Path, files: &[(&str, &str)], message: &str) -> String {
+  write_files(repo, files);
+  commit(repo, message)
+}
@@ -0,0 +1,157 @@
+// oss-porter-core/src/transform.rs
+use crate::filter::PathFilter;
+use crate::header::{HeaderOutcome, LicenseHeaders};
+use crate::manifest::{
+  inline_workspace_inheritance, is_manifest, rewrite_path_dependencies, set_package_license,
+  WorkspaceRoot,
+};
+use crate::scrub::{Scrubber, StrippedRegion};
+use crate::{DependencyRewrite, PorterError, ProjectConfig, Result};
+use log::{debug, info};
+use std::collections::HashMap;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Result of running the content transforms over one file or a whole directory.
+#[derive(Debug, Default)]
+pub struct TransformReport {
+  pub stripped_regions: Vec<StrippedRegion>,
+  pub headers_changed: Vec<PathBuf>, // License header added or replaced
+  pub headers_missing: Vec<PathBuf>, // License header absent (`verify` mode)
+  pub notes: Vec<String>,            // Human-readable description of every other rewrite
+}
+
+/// Per-file content transforms applied to everything that gets published: the extracted
+/// tree as well as the old and new side of every file in an update patch, so both stay
+/// consistent. Applied in order: scrubbing, license headers, inlining of
+/// workspace-inherited manifest fields, the root manifest's `license`, then Cargo path
+/// dependency rewrites.
+pub struct ContentTransforms {
+  scrubber: Option<Scrubber>,
+  headers: Option<LicenseHeaders>,
+  workspace: Option<WorkspaceRoot>,
+  license: Option<String>, // SPDX expression written to the root Cargo.toml
+  dependency_rewrites: HashMap<String, DependencyRewrite>,
+}
+
+impl ContentTransforms {
+  /// Builds the transforms for the internal repo's working tree.
+  pub fn from_config(config: &ProjectConfig) -> Result<Self> {
+    Self::build(config, None)
+  }
+
+  /// Builds the transforms for the state of the internal repo at commit `rev`
+  /// (the enclosing workspace root manifest is read from that commit).
+  pub fn from_config_at(config: &ProjectConfig, rev: &str) -> Result<Self> {
+    Self::build(config, Some(rev))
+  }
+
+  fn build(config: &ProjectConfig, rev: Option<&str>) -> Result<Self> {
+    Ok(ContentTransforms {
+      scrubber: Scrubber::from_config(config)?,
+      headers: LicenseHeaders::from_config(config)?,
+      workspace: WorkspaceRoot::find(config, rev)?,
+      license: config.license.clone(),
+      dependency_rewrites: config.dependency_rewrites.clone(),
+    })
+  }
+
+  /// Returns true if no transform is configured.
+  pub fn is_noop(&self) -> bool {
+    self.scrubber.is_none()
+      && self.headers.is_none()
+      && self.workspace.is_none()
+      && self.license.is_none()
+      && self.dependency_rewrites.is_empty()
+  }
+
+  /// Transforms the content of the file at `rel_path` (relative to the published root).
+  pub fn apply(&self, rel_path: &Path, content: &str) -> Result<(String, TransformReport)> {
+    let mut report = TransformReport::default();
+    let mut text = content.to_string();
+
+    if let Some(scrubber) = &self.scrubber {
+      let (scrubbed, regions) = scrubber.scrub_text(rel_path, &text)?;
+      text = scrubbed;
+      report.stripped_regions = regions;
+    }
+    if let Some(headers) = &self.headers {
+      let (with_header, outcome) = headers.apply(rel_path, &text);
+      match outcome {
+        HeaderOutcome::Added | HeaderOutcome::Replaced => {
+          report.headers_changed.push(rel_path.to_path_buf())
+        }
+        HeaderOutcome::Missing => report.headers_missing.push(rel_path.to_path_buf()),
+        HeaderOutcome::Unchanged => {}
+      }
+      text = with_header;
+    }
+    if let (Some(workspace), true) = (&self.workspace, is_manifest(rel_path)) {
+      let (inlined, notes) = inline_workspace_inheritance(rel_path, &text, workspace)?;
+      text = inlined;
+      report.notes.extend(notes);
+    }
+    if let (Some(license), true) = (&self.license, rel_path == Path::new("Cargo.toml")) {
+      let (licensed, notes) = set_package_license(rel_path, &text, license)?;
+      text = licensed;
+      report.notes.extend(notes);
+    }
+    if !self.dependency_rewrites.is_empty() && is_manifest(rel_path) {
+      let (rewritten, notes) =
+        rewrite_path_dependencies(rel_path, &text, &self.dependency_rewrites)?;
+      if !notes.is_empty() {
+        text = rewritten;
+        report.notes.extend(notes);
+      }
+    }
+    Ok((text, report))
+  }
+
+  /// Applies the transforms in place to every published text file under `dir`.
+  /// Files rejected by `filter` and `.git` are skipped.
+  pub fn apply_to_dir(&self, dir: &Path, filter: &PathFilter) -> Result<TransformReport> {
+    let mut report = TransformReport::default();
+    if self.is_noop() {
+      return Ok(report);
+    }
+    info!("Applying content transforms in {}", dir.display());
+    let walker = WalkDir::new(dir)
+      .into_iter()
+      .filter_entry(|entry| entry.file_name() != ".git");
+    for entry in walker.filter_map(|e| e.ok()) {
+      if !entry.file_type().is_file() {
+        continue;
+      }
+      let rel_path = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+      if !filter.is_included(rel_path) {
+        continue;
+      }
+      let Ok(content) = fs::read_to_string(entry.path()) else {
+        continue; // Binary or non-UTF-8 files are left untouched
+      };
+      let (transformed, file_report) = self.apply(rel_path, &content)?;
+      report.headers_missing.extend(file_report.headers_missing);
+      if transformed == content {
+        continue;
+      }
+      debug!("Transformed {}", rel_path.display());
+      fs::write(entry.path(), transformed).map_err(|e| PorterError::Io {
+        source: e,
+        path: entry.path().to_path_buf(),
+      })?;
+      report.stripped_regions.extend(file_report.stripped_regions);
+      report.headers_changed.extend(file_report.headers_changed);
+      report.notes.extend(file_report.notes);
+    }
+    info!(
+      "Content transforms completed: {} stripped region(s), {} license header(s) written, {} other rewrite(s).",
+      report.stripped_regions.len(),
+      report.headers_changed.len(),
+      report.notes.len()
+    );
+    Ok(report)
+  }
+}
@@ -1,26 +1,41 @@
 // oss-porter-core/src/update.rs
-use crate::utils::run_git_command;
-use crate::{PorterError, ProjectConfig, Result};
+use crate::filter::PathFilter;
+use crate::identity::{local_identity_args, IdentityMap};
+use crate::message::MessageRules;
+use crate::patch::{parse_diff, parse_hunk_header, BoundaryMove, CommitPatch};
+use crate::relocation::{config_at, former_subdirs};
+use crate::state::{decided_commits, STATE_FILE_NAME};
+use crate::transform::{ContentTransforms, TransformReport};
+use crate::utils::{run_git_command, run_git_command_with_env, run_git_command_with_input};
+use crate::{MergeStrategy, PorterError, ProjectConfig, Result};
 use log::{debug, error, info, warn};
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommitInfo {
   pub hash: String,
   pub subject: String,
+  #[serde(default)]
+  pub message: String, // Full internal message
+  #[serde(default)]
+  pub trailers: Vec<(String, String)>, // `Key: value` trailers closing the message
 }
 
 /// Fetches latest changes for the internal repo and identifies relevant commits.
+/// Commits already decided in an earlier run (applied, edited or skipped, see
+/// `state::decided_commits`) are left out.
 pub fn get_internal_commits_since(
   config: &ProjectConfig,
   since_ref: Option<&str>,
 ) -> Result<VecDeque<CommitInfo>> {
   // Return VecDeque for easy processing order
   let internal_repo = &config.internal_repo_path;
-  let internal_branch = &config.internal_branch;
-  let project_subdir = &config.project_subdir;
 
   info!(
     "Fetching updates for internal repository: {}",
@@ -35,124 +50,759 @@ pub fn get_internal_commits_since(
     ), // Non-fatal?
   }
 
-  // Construct the commit range. Use origin/<branch> after fetch.
-  // If since_ref is None, maybe list all commits on branch touching path? Risky.
-  // Let's require a since_ref for now.
-  let since_commit = since_ref.ok_or_else(|| {
-    PorterError::GitOperation(
-      "Cannot determine update range: no previous sync commit reference provided.".to_string(),
-    )
-  })?;
+  // Without a since_ref (the cursor was moved before the first commit) the whole branch
+  // is listed; everything reviewed before is filtered out as decided.
+  let decided = decided_commits(config)?;
+  let mut commits = VecDeque::new();
+  for commit in list_commits_since(config, since_ref)? {
+    if decided.contains(&commit.hash) {
+      debug!("Not offering {}: already decided.", commit.hash);
+    } else {
+      commits.push_back(commit);
+    }
+  }
 
-  let range = format!("{}..origin/{}", since_commit, internal_branch);
+  info!("Found {} new candidate commits.", commits.len());
+  Ok(commits)
+}
+
+/// Lists the commits on `origin/<internal_branch>` after `since_commit` (all of them if
+/// `None`) that touch `project_subdir` (or where it was before a recorded move), oldest
+/// first. Merged branches are handled per `merge_strategy`.
+fn list_commits_since(
+  config: &ProjectConfig,
+  since_commit: Option<&str>,
+) -> Result<Vec<CommitInfo>> {
+  let internal_repo = &config.internal_repo_path;
+  let internal_branch = &config.internal_branch;
+  let project_subdir = &config.project_subdir;
+
+  // Construct the commit range. Use origin/<branch> after fetch.
+  let range = match since_commit {
+    Some(since_commit) => format!("{}..origin/{}", since_commit, internal_branch),
+    None => format!("origin/{}", internal_branch),
+  };
   info!(
     "Looking for commits in range '{}' affecting subdir '{}'",
     range,
     project_subdir.display()
   );
 
-  // Use --no-merges to simplify history, --first-parent might also be useful sometimes
-  // Format: hash<SEP>subject
-  const HASH_SEP: &str = "<|OSS-PORTER-SEP|>";
-  let _log_format = format!("%H{}%s", HASH_SEP);
-  let log_args = &[
-    "log",
-    &range,
-    "--no-merges",
-    "--first-parent",           // Consider if this is desired - simplifies history
-    "--pretty=format:%H%x00%s", // Use NULL separator for subject safety
-    "--",                       // End of options, start of paths
-    &project_subdir.to_string_lossy(), // Pathspec relative to repo root
-  ];
+  // Commits that only record sync state (e.g. after `extract`) are never candidates.
+  let mut subdirs = former_subdirs(config)?;
+  subdirs.push(project_subdir.clone());
+  let pathspecs: Vec<String> = subdirs
+    .iter()
+    .flat_map(|subdir| {
+      [
+        subdir.to_string_lossy().to_string(), // Pathspec relative to repo root
+        format!(":(exclude){}", subdir.join(STATE_FILE_NAME).display()),
+      ]
+    })
+    .collect();
+  let mut log_args = vec!["log", &range];
+  match config.merge_strategy {
+    // Mainline commits only; changes arriving through merges are not offered
+    MergeStrategy::FirstParent => log_args.extend(["--no-merges", "--first-parent"]),
+    // Every commit of merged branches, parents before children
+    MergeStrategy::Flatten => log_args.extend(["--no-merges", "--full-history", "--topo-order"]),
+    // Mainline commits only; a merge stands for the whole merged branch
+    MergeStrategy::Squash => log_args.push("--first-parent"),
+  }
+  log_args.extend([
+    // NULL-separated fields, records ended by 0x1e (messages span lines)
+    "--pretty=format:%H%x00%s%x00%(trailers:only,unfold)%x00%B%x1e",
+    "--", // End of options, start of paths
+  ]);
+  log_args.extend(pathspecs.iter().map(String::as_str));
 
-  let log_output = run_git_command(log_args, internal_repo)?;
+  let log_output = run_git_command(&log_args, internal_repo)?;
   let stdout = String::from_utf8_lossy(&log_output.stdout);
 
-  let mut commits = VecDeque::new();
+  let mut commits = Vec::new();
   // Process in reverse order so oldest is first
-  for line in stdout.trim().lines().rev() {
-    if line.is_empty() {
+  for record in stdout.split('\x1e').rev() {
+    let record = record.trim_start_matches('\n');
+    if record.is_empty() {
       continue;
     }
-    let parts: Vec<&str> = line.splitn(2, '\x00').collect(); // Split by NULL
-    if parts.len() == 2 {
-      commits.push_back(CommitInfo {
+    let parts: Vec<&str> = record.splitn(4, '\x00').collect(); // Split by NULL
+    if parts.len() == 4 {
+      commits.push(CommitInfo {
         hash: parts[0].to_string(),
         subject: parts[1].to_string(),
+        message: parts[3].trim_end().to_string(),
+        trailers: parse_trailers(parts[2]),
       });
     } else {
-      warn!("Could not parse commit log line: {}", line);
+      warn!("Could not parse commit log record: {}", record);
     }
   }
-
-  info!("Found {} new candidate commits.", commits.len());
   Ok(commits)
 }
 
-/// Gets the formatted diff for a specific commit, relative to the project subdir.
-pub fn get_commit_diff_relative(config: &ProjectConfig, commit_hash: &str) -> Result<String> {
-  let internal_repo = &config.internal_repo_path;
-  let project_subdir = &config.project_subdir;
+/// Where the sync cursor stands after a review session.
+#[derive(Debug)]
+pub struct SyncCursor {
+  pub cursor: Option<String>, // Last commit of the contiguous run of decided commits
+  pub pending: Vec<String>,   // Undecided commits after the cursor, oldest first
+}
 
-  debug!("Getting relative diff for commit {}", commit_hash);
-  // Show diff against parent (commit^!) relative to the subdir
-  // Use color=always for potential terminal display later
-  let diff_args = &[
-    "diff",
-    "--color=always", // Or remove if not needed downstream
-    &format!("{}~..{}", commit_hash, commit_hash), // Diff against parent
-    "--relative",     // Make paths relative to CWD
-    &project_subdir.to_string_lossy(), // Path filter relative to CWD
-  ];
+/// Moves the sync cursor from `since_commit` over the longest run of consecutive commits
+/// that are decided in the state (applied, edited or permanently skipped). It stops at the
+/// first undecided commit, so commits skipped "for now" or never reached are not lost;
+/// they and any other undecided commits after them are returned as pending.
+/// With the `flatten` merge strategy the cursor stays on the first-parent line: it moves to
+/// the last mainline commit whose history holds no pending commit.
+pub fn advance_sync_cursor(
+  config: &ProjectConfig,
+  since_commit: Option<&str>,
+) -> Result<SyncCursor> {
+  let decided = decided_commits(config)?;
+  let flatten = config.merge_strategy == MergeStrategy::Flatten;
+  let mut cursor = since_commit.map(str::to_string);
+  let mut pending = Vec::new();
+  for commit in list_commits_since(config, since_commit)? {
+    if !decided.contains(&commit.hash) {
+      pending.push(commit.hash);
+    } else if pending.is_empty() && !flatten {
+      cursor = Some(commit.hash);
+    }
+  }
+  if flatten {
+    // The flattened list mixes in side-branch commits, which must not become the cursor
+    if let Some(mainline) = last_covered_mainline_commit(config, since_commit, &pending)? {
+      cursor = Some(mainline);
+    }
+  }
+  debug!(
+    "Sync cursor at {:?}, {} commit(s) pending",
+    cursor,
+    pending.len()
+  );
+  Ok(SyncCursor { cursor, pending })
+}
 
-  // Run the command from the internal repo root, paths in diff will be relative to project_subdir
-  let diff_output = run_git_command(diff_args, internal_repo)?;
-  let diff_str = String::from_utf8_lossy(&diff_output.stdout).to_string();
-  Ok(diff_str)
+/// Walks the first-parent line of `origin/<internal_branch>` after `since_commit` and returns
+/// the last commit before the first one that brings in a `pending` commit (by itself or
+/// through a merge), or `None` if the first one already does.
+fn last_covered_mainline_commit(
+  config: &ProjectConfig,
+  since_commit: Option<&str>,
+  pending: &[String],
+) -> Result<Option<String>> {
+  let repo = &config.internal_repo_path;
+  let tip = format!("origin/{}", config.internal_branch);
+  let excluded = since_commit.map(|since| format!("^{}", since));
+  let mut args = vec!["rev-list", "--first-parent", "--reverse", "--parents", &tip];
+  args.extend(excluded.as_deref());
+  let mainline = run_git_command(&args, repo)?;
+
+  let mut covered = None;
+  for line in String::from_utf8_lossy(&mainline.stdout).lines() {
+    let mut hashes = line.split_whitespace();
+    let Some(commit) = hashes.next() else {
+      continue;
+    };
+    let brings_pending = match hashes.next() {
+      // A merge brings in everything not reachable from its first parent
+      Some(first_parent) if hashes.next().is_some() => {
+        let mut args = vec!["rev-list", commit];
+        let first_parent = format!("^{}", first_parent);
+        args.push(&first_parent);
+        args.extend(excluded.as_deref());
+        let merged = run_git_command(&args, repo)?;
+        String::from_utf8_lossy(&merged.stdout)
+          .lines()
+          .any(|merged| pending.iter().any(|p| p == merged))
+      }
+      _ => pending.iter().any(|p| p == commit),
+    };
+    if brings_pending {
+      break;
+    }
+    covered = Some(commit.to_string());
+  }
+  Ok(covered)
+}
+
+/// Parses `git log --format=%(trailers:only,unfold)` output into `(key, value)` pairs.
+fn parse_trailers(text: &str) -> Vec<(String, String)> {
+  text
+    .lines()
+    .filter_map(|line| line.split_once(':'))
+    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+    .collect()
+}
+
+/// Builds the `--relative=<subdir>/` diff option so patch paths are rooted at the project subdir.
+fn relative_arg(config: &ProjectConfig) -> String {
+  if config.project_subdir == Path::new(".") {
+    "--relative".to_string()
+  } else {
+    format!(
+      "--relative={}/",
+      config
+        .project_subdir
+        .to_string_lossy()
+        .trim_end_matches('/')
+    )
+  }
+}
+
+/// Lists the files changed by a commit below `project_subdir` that pass the project's
+/// include/exclude filter, as literal pathspecs relative to the internal repo root.
+/// An empty list means the commit has nothing publishable.
+fn published_pathspecs(config: &ProjectConfig, commit_hash: &str) -> Result<Vec<String>> {
+  let filter = PathFilter::from_config(config)?;
+  let relative = relative_arg(config);
+  let subdir = config.project_subdir.to_string_lossy();
+  let output = run_git_command(
+    &[
+      "diff-tree",
+      "--no-commit-id",
+      "--root",
+      "-r",
+      "-z",
+      "--name-only",
+      "--no-renames",
+      "--diff-merges=first-parent", // A merge publishes what it brought to the mainline
+      &relative,
+      commit_hash,
+      "--",
+      &subdir,
+    ],
+    &config.internal_repo_path,
+  )?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+
+  let mut pathspecs = Vec::new();
+  for rel_path in stdout.split('\x00').filter(|p| !p.is_empty()) {
+    if !filter.is_included(Path::new(rel_path)) {
+      debug!(
+        "Dropping '{}' from commit {} (excluded by filter).",
+        rel_path, commit_hash
+      );
+      continue;
+    }
+    pathspecs.push(format!(":(literal){}", repo_path(config, rel_path)));
+  }
+  Ok(pathspecs)
+}
+
+/// Finds the files `commit_hash` renamed or copied across the `project_subdir` boundary
+/// (copies out of the project are harmless and ignored). The published patch carries
+/// them as additions/deletions; the moves are reported so review can flag them.
+fn detect_boundary_moves(config: &ProjectConfig, commit_hash: &str) -> Result<Vec<BoundaryMove>> {
+  if config.project_subdir == Path::new(".") {
+    return Ok(Vec::new());
+  }
+  let filter = PathFilter::from_config(config)?;
+  let output = run_git_command(
+    &[
+      "diff-tree",
+      "--no-commit-id",
+      "--root",
+      "-r",
+      "-z",
+      "--name-status",
+      "-M",
+      "-C",
+      "--diff-merges=first-parent",
+      commit_hash,
+    ],
+    &config.internal_repo_path,
+  )?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  // True if `path` is a published file inside the project
+  let published = |path: &str| {
+    Path::new(path)
+      .strip_prefix(&config.project_subdir)
+      .ok()
+      .filter(|rel_path| filter.is_included(rel_path))
+      .is_some()
+  };
+
+  let mut moves = Vec::new();
+  let mut fields = stdout.split('\x00').filter(|field| !field.is_empty());
+  while let Some(status) = fields.next() {
+    if !status.starts_with(['R', 'C']) {
+      fields.next(); // Single path
+      continue;
+    }
+    let (Some(from), Some(to)) = (fields.next(), fields.next()) else {
+      break;
+    };
+    let copied = status.starts_with('C');
+    let (from_inside, to_inside) = (published(from), published(to));
+    if from_inside == to_inside || (copied && from_inside) {
+      continue;
+    }
+    moves.push(BoundaryMove {
+      from: from.to_string(),
+      to: to.to_string(),
+      copied,
+      into_project: to_inside,
+    });
+  }
+  if !moves.is_empty() {
+    warn!(
+      "Commit {} moves {} file(s) across the project boundary.",
+      commit_hash,
+      moves.len()
+    );
+  }
+  Ok(moves)
+}
+
+/// Gets the diff for a specific commit, relative to the project subdir, exactly as it
+/// would be published: files excluded by the path filters are left out and
+/// internal-only regions are scrubbed.
+pub fn get_commit_diff_relative(config: &ProjectConfig, commit_hash: &str) -> Result<String> {
+  debug!("Getting relative diff for commit {}", commit_hash);
+  let patch = build_commit_patch(config, commit_hash)?;
+  Ok(patch.diff_text())
   // Error handling: If commit_hash is invalid, run_git_command should return PorterError::GitCommand
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ApplyResult {
-  Success,
+  Success(Option<String>), // Public commit created (None if the patch was empty)
   Conflict,
   Failure(String), // Contains stderr or error message
 }
 
-/// Attempts to apply a specific commit from the internal repo to the output repo using a patch.
-pub fn apply_commit_to_output(config: &ProjectConfig, commit_hash: &str) -> Result<ApplyResult> {
+/// Converts a path relative to `project_subdir` into one relative to the internal repo root.
+fn repo_path(config: &ProjectConfig, rel_path: &str) -> String {
+  if config.project_subdir == Path::new(".") {
+    rel_path.to_string()
+  } else {
+    config
+      .project_subdir
+      .join(rel_path)
+      .to_string_lossy()
+      .to_string()
+  }
+}
+
+/// Reads a file's content at a given commit from the internal repo (`None` if not UTF-8).
+fn read_blob_text(config: &ProjectConfig, rev: &str, rel_path: &str) -> Result<Option<String>> {
+  let spec = format!("{}:{}", rev, repo_path(config, rel_path));
+  let output = run_git_command(&["show", &spec], &config.internal_repo_path)?;
+  Ok(String::from_utf8(output.stdout).ok())
+}
+
+/// Computes the blob hash git would assign to `content`, without writing it.
+fn hash_blob(config: &ProjectConfig, content: &str) -> Result<String> {
+  let output = run_git_command_with_input(
+    &["hash-object", "--stdin"],
+    &config.internal_repo_path,
+    content.as_bytes(),
+  )?;
+  Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Re-diffs a file whose old and/or new content is changed by the content transforms
+/// (scrubbing, manifest rewrites), so the patch carries exactly the published text.
+/// Returns false if nothing publishable is left in the file's diff.
+fn transform_file_diff(
+  config: &ProjectConfig,
+  old_transforms: &ContentTransforms,
+  new_transforms: &ContentTransforms,
+  patch: &mut CommitPatch,
+  index: usize,
+) -> Result<bool> {
+  let file = &patch.files[index];
+  if file.is_binary() || file.hunks.is_empty() {
+    return Ok(true);
+  }
+  let old_text = match (&file.old_path, &patch.parent) {
+    (Some(path), Some(parent)) => read_blob_text(config, parent, path)?,
+    _ => Some(String::new()),
+  };
+  let new_text = match &file.new_path {
+    Some(path) => read_blob_text(config, &patch.commit_hash, path)?,
+    None => Some(String::new()),
+  };
+  let (Some(old_text), Some(new_text)) = (old_text, new_text) else {
+    return Ok(true); // Not text, leave as-is
+  };
+
+  let path = Path::new(file.path());
+  // A missing side (added or deleted file) stays empty.
+  let (old_published, old_report) = match file.old_path {
+    Some(_) => old_transforms.apply(path, &old_text)?,
+    None => (old_text.clone(), TransformReport::default()),
+  };
+  let (new_published, report) = match file.new_path {
+    Some(_) => new_transforms.apply(path, &new_text)?,
+    None => (new_text.clone(), TransformReport::default()),
+  };
+  for missing in &report.headers_missing {
+    patch.transform_notes.push(format!(
+      "{}: missing the expected license header",
+      missing.display()
+    ));
+  }
+  if old_published == old_text && new_published == new_text {
+    return Ok(true);
+  }
+  if old_report.headers_changed.is_empty() {
+    for changed in &report.headers_changed {
+      patch
+        .transform_notes
+        .push(format!("{}: license header added", changed.display()));
+    }
+  }
+  patch.stripped_regions.extend(report.stripped_regions);
+  patch.transform_notes.extend(report.notes);
+  let is_modification = file.old_path.is_some() && file.new_path.is_some();
+  let mode_change = file.header.iter().any(|l| l.starts_with("old mode"));
+  if is_modification && !mode_change && old_published == new_published {
+    debug!(
+      "Change to '{}' in commit {} disappears after content transforms; dropping it.",
+      file.path(),
+      patch.commit_hash
+    );
+    return Ok(false);
+  }
+
+  let diff = TextDiff::from_lines(&old_published, &new_published);
+  let mut hunks = Vec::new();
+  for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+    let text = hunk.to_string();
+    let mut lines = text.lines();
+    if let Some(mut parsed) = lines.next().and_then(parse_hunk_header) {
+      parsed.lines = lines.map(str::to_string).collect();
+      hunks.push(parsed);
+    }
+  }
+  let old_hash = match file.old_path {
+    Some(_) => hash_blob(config, &old_published)?,
+    None => NULL_BLOB.to_string(),
+  };
+  let new_hash = match file.new_path {
+    Some(_) => hash_blob(config, &new_published)?,
+    None => NULL_BLOB.to_string(),
+  };
+  let file = &mut patch.files[index];
+  file.hunks = hunks;
+  file.set_index_hashes(&old_hash, &new_hash);
+  Ok(true)
+}
+
+const NULL_BLOB: &str = "0000000000000000000000000000000000000000";
+
+/// Builds the publishable patch for an internal commit: paths relative to `project_subdir`
+/// (where it was at that commit), files excluded by the path filters dropped, content
+/// transforms (scrubbing, license headers, workspace inheritance, manifest rewrites) applied,
+/// and the author and message rewritten by the identity map and message rules.
+/// The returned patch may be empty if nothing publishable is left.
+pub fn build_commit_patch(config: &ProjectConfig, commit_hash: &str) -> Result<CommitPatch> {
+  // Commits from before a move of the project subdir are read from where it was then
+  let effective_config = config_at(config, commit_hash)?;
+  let config: &ProjectConfig = &effective_config;
   let internal_repo = &config.internal_repo_path;
-  let project_subdir = &config.project_subdir;
+
+  // Commit metadata: parents, author and full message
+  let meta_output = run_git_command(
+    &[
+      "show",
+      "-s",
+      "--format=%P%x00%an%x00%ae%x00%aD%x00%B",
+      commit_hash,
+    ],
+    internal_repo,
+  )?;
+  let meta = String::from_utf8_lossy(&meta_output.stdout).to_string();
+  let fields: Vec<&str> = meta.splitn(5, '\x00').collect();
+  if fields.len() != 5 {
+    return Err(PorterError::GitOperation(format!(
+      "Could not parse metadata of commit {}",
+      commit_hash
+    )));
+  }
+  let mut patch = CommitPatch {
+    commit_hash: commit_hash.to_string(),
+    parent: fields[0].split_whitespace().next().map(str::to_string),
+    author_name: fields[1].to_string(),
+    author_email: fields[2].to_string(),
+    author_date: fields[3].to_string(),
+    message: fields[4].trim_end().to_string(),
+    original_message: None,
+    files: Vec::new(),
+    stripped_regions: Vec::new(),
+    transform_notes: Vec::new(),
+    boundary_moves: Vec::new(),
+  };
+  if let Some(rules) = MessageRules::from_config(config)? {
+    let message = rules.apply(&patch.message);
+    if message != patch.message {
+      patch.original_message = Some(std::mem::replace(&mut patch.message, message));
+    }
+  }
+  if let Some(identities) = IdentityMap::from_config(config)? {
+    let author = identities.map(&patch.author_name, &patch.author_email);
+    if author.name != patch.author_name || author.email != patch.author_email {
+      patch.transform_notes.push(format!(
+        "Author {} <{}> published as {}",
+        patch.author_name, patch.author_email, author
+      ));
+      patch.author_name = author.name;
+      patch.author_email = author.email;
+    }
+  }
+
+  patch.boundary_moves = detect_boundary_moves(config, commit_hash)?;
+
+  // We need the patch content relative to the *subdirectory* so it applies correctly in the output repo where the subdir *is* the root.
+  // Only files passing the include/exclude filters are listed, so excluded files never reach the output.
+  let pathspecs = published_pathspecs(config, commit_hash)?;
+  if pathspecs.is_empty() {
+    return Ok(patch);
+  }
+  let relative = relative_arg(config);
+  let mut diff_args = vec![
+    "-c",
+    "core.quotepath=false",
+    "diff-tree",
+    "-p",
+    "--root",
+    "--full-index", // Full blob hashes let `git am --3way` fall back to a merge
+    "--binary",
+    "--no-renames",
+    "--diff-merges=first-parent",
+    &relative,
+    commit_hash,
+    "--",
+  ];
+  diff_args.extend(pathspecs.iter().map(String::as_str));
+  let diff_output = run_git_command(&diff_args, internal_repo)?;
+  patch.files = parse_diff(&String::from_utf8_lossy(&diff_output.stdout));
+
+  // Each side is transformed as of its own commit, so inherited workspace fields match
+  // what was published for the parent.
+  let new_transforms = ContentTransforms::from_config_at(config, commit_hash)?;
+  let parent_transforms = match &patch.parent {
+    Some(parent) => Some(ContentTransforms::from_config_at(config, parent)?),
+    None => None,
+  };
+  let old_transforms = parent_transforms.as_ref().unwrap_or(&new_transforms);
+  if !new_transforms.is_noop() || !old_transforms.is_noop() {
+    let mut index = 0;
+    while index < patch.files.len() {
+      if transform_file_diff(config, old_transforms, &new_transforms, &mut patch, index)? {
+        index += 1;
+      } else {
+        patch.files.remove(index);
+      }
+    }
+    if !patch.stripped_regions.is_empty() {
+      info!(
+        "Stripped {} internal-only region(s) from commit {}.",
+        patch.stripped_regions.len(),
+        commit_hash
+      );
+    }
+  }
+  Ok(patch)
+}
+
+/// Several internal commits combined into one patch by `update --squash`.
+#[derive(Debug)]
+pub struct SquashedPatch {
+  pub patch: CommitPatch, // The combined change, with a message listing the absorbed commits
+  pub absorbed: Vec<String>, // Internal commits whose changes `patch` carries, oldest first
+  pub already_applied: Vec<(String, String)>, // (internal, public) commits already in the public repo
+}
+
+/// Scratch index (in the output repo's git dir) the patches are stacked in.
+const SQUASH_INDEX_FILE: &str = "oss_porter_squash_index";
+
+/// Combines the publishable changes of `commits` (oldest first) into a single patch against
+/// the output repo's HEAD. Each commit is built exactly like a single update patch; commits
+/// whose change is already in the public repo (by patch-id) are left out. The patches are
+/// stacked in a scratch index, so the output working tree is not touched. Fails if a patch
+/// does not apply on top of the ones before it.
+/// The combined patch has the author (and date) of the most recent commit with a change and a
+/// generated message listing the published subjects of the absorbed commits.
+pub fn build_squashed_patch(
+  config: &ProjectConfig,
+  commits: &[CommitInfo],
+) -> Result<SquashedPatch> {
+  let head = output_head(config)?.ok_or_else(|| {
+    PorterError::GitOperation(format!(
+      "Output repository {} has no commits to squash onto.",
+      config.output_path.display()
+    ))
+  })?;
+  let public_patch_ids = PublicPatchIds::load(config)?;
+  let mut patches = Vec::new();
+  let mut absorbed = Vec::new();
+  let mut already_applied = Vec::new();
+  for commit in commits {
+    let patch = build_commit_patch(config, &commit.hash)?;
+    if let Some(public_commit) = public_patch_ids.find(config, &patch)? {
+      info!(
+        "Commit {} is already in the public repo as {}.",
+        commit.hash, public_commit
+      );
+      already_applied.push((commit.hash.clone(), public_commit.to_string()));
+      continue;
+    }
+    absorbed.push(commit.hash.clone());
+    if patch.is_empty() {
+      debug!("Commit {} has nothing publishable.", commit.hash);
+    } else {
+      patches.push(patch);
+    }
+  }
+
+  let Some(last) = patches.last() else {
+    return Ok(SquashedPatch {
+      patch: CommitPatch {
+        commit_hash: commits
+          .last()
+          .map(|commit| commit.hash.clone())
+          .unwrap_or_default(),
+        parent: None,
+        author_name: String::new(),
+        author_email: String::new(),
+        author_date: String::new(),
+        message: squash_message(&[]),
+        original_message: None,
+        files: Vec::new(),
+        stripped_regions: Vec::new(),
+        transform_notes: Vec::new(),
+        boundary_moves: Vec::new(),
+      },
+      absorbed,
+      already_applied,
+    });
+  };
+  let mut squashed = CommitPatch {
+    commit_hash: last.commit_hash.clone(),
+    parent: patches[0].parent.clone(),
+    author_name: last.author_name.clone(),
+    author_email: last.author_email.clone(),
+    author_date: last.author_date.clone(),
+    message: squash_message(&patches),
+    original_message: None,
+    files: Vec::new(),
+    stripped_regions: Vec::new(),
+    transform_notes: Vec::new(),
+    boundary_moves: Vec::new(),
+  };
+
+  let index_path = output_git_dir(config)?.join(SQUASH_INDEX_FILE);
+  let index = index_path.to_string_lossy().to_string();
+  let tree = stack_patches(config, &head, &patches, &[("GIT_INDEX_FILE", &index)]);
+  if index_path.exists() {
+    fs::remove_file(&index_path).map_err(|e| PorterError::Io {
+      source: e,
+      path: index_path.clone(),
+    })?;
+  }
+  let tree = tree?;
+  let diff_output = run_git_command(
+    &[
+      "-c",
+      "core.quotepath=false",
+      "diff-tree",
+      "-p",
+      "--full-index",
+      "--binary",
+      "--no-renames",
+      &head,
+      &tree,
+    ],
+    &config.output_path,
+  )?;
+  squashed.files = parse_diff(&String::from_utf8_lossy(&diff_output.stdout));
+  for patch in patches {
+    squashed.stripped_regions.extend(patch.stripped_regions);
+    squashed.transform_notes.extend(patch.transform_notes);
+    squashed.boundary_moves.extend(patch.boundary_moves);
+  }
+  info!(
+    "Squashed {} commit(s) into one patch touching {} file(s).",
+    absorbed.len(),
+    squashed.files.len()
+  );
+  Ok(SquashedPatch {
+    patch: squashed,
+    absorbed,
+    already_applied,
+  })
+}
+
+/// Applies `patches` one after the other to `head`'s tree in the index selected by `env`
+/// and returns the resulting tree.
+fn stack_patches(
+  config: &ProjectConfig,
+  head: &str,
+  patches: &[CommitPatch],
+  env: &[(&str, &str)],
+) -> Result<String> {
   let output_path = &config.output_path;
+  run_git_command_with_env(&["read-tree", head], output_path, env, &[])?;
+  for patch in patches {
+    run_git_command_with_env(
+      &["apply", "--cached", "--whitespace=nowarn"],
+      output_path,
+      env,
+      patch.diff_text().as_bytes(),
+    )
+    .map_err(|e| {
+      PorterError::GitOperation(format!(
+        "Commit {} does not apply on top of the commits before it: {}",
+        patch.commit_hash, e
+      ))
+    })?;
+  }
+  let tree = run_git_command_with_env(&["write-tree"], output_path, env, &[])?;
+  Ok(String::from_utf8_lossy(&tree.stdout).trim().to_string())
+}
 
+/// Message of a squashed commit: a summary line and the subject of each included commit.
+fn squash_message(patches: &[CommitPatch]) -> String {
+  let mut message = match patches.len() {
+    1 => "Sync 1 internal commit".to_string(),
+    count => format!("Sync {} internal commits", count),
+  };
+  if !patches.is_empty() {
+    message.push('\n');
+  }
+  for patch in patches {
+    message.push_str(&format!("\n- {}", patch.subject()));
+  }
+  message
+}
+
+/// Attempts to apply a specific commit from the internal repo to the output repo using a patch.
+pub fn apply_commit_to_output(config: &ProjectConfig, commit_hash: &str) -> Result<ApplyResult> {
   info!(
     "Generating patch for commit {} from internal repo relative to subdir '{}'",
     commit_hash,
-    project_subdir.display()
+    config.project_subdir.display()
   );
+  let patch = build_commit_patch(config, commit_hash)?;
+  apply_patch_to_output(config, &patch)
+}
 
-  // 1. Generate Patch relative to the subdirectory
-  // Use `git format-patch` or `git diff` piped to a file. `format-patch` is generally better as it includes commit metadata.
-  // We need the patch content relative to the *subdirectory* so it applies correctly in the output repo where the subdir *is* the root.
-  let patch_args = &[
-    "format-patch",
-    "--stdout",                 // Option
-    "-1",                       // How many commits
-    commit_hash,                // The commit hash
-    "--relative",               // Make paths relative to CWD (repo root)
-    "--",                       // Separator
-    &project_subdir.to_string_lossy(), // Pathspec filter
-  ];
-
-  // Run format-patch from the internal repo root
-  let patch_output = run_git_command(patch_args, internal_repo)?;
-  let patch_content = patch_output.stdout; // Patch content as bytes
+/// Applies a patch built by [`build_commit_patch`] to the output repo with `git am`.
+pub fn apply_patch_to_output(config: &ProjectConfig, patch: &CommitPatch) -> Result<ApplyResult> {
+  let project_subdir = &config.project_subdir;
+  let output_path = &config.output_path;
+  let commit_hash = patch.commit_hash.as_str();
 
-  if patch_content.is_empty() {
-    warn!("Generated empty patch for commit {}. This might mean changes were outside the subdirectory '{}' or only involved merges/empty changes. Skipping application.", commit_hash, project_subdir.display());
+  if patch.is_empty() {
+    warn!("Generated empty patch for commit {}. This might mean changes were outside the subdirectory '{}', excluded by path filters or scrubbing, or only involved merges/empty changes. Skipping application.", commit_hash, project_subdir.display());
     // Treat as success because there's nothing to apply from the relevant subdir.
-    return Ok(ApplyResult::Success);
+    return Ok(ApplyResult::Success(None));
   }
+  let patch_content = patch.to_mbox().into_bytes();
+  let head_before = output_head(config)?;
 
   // 2. Apply Patch using `git am` in the output repo
   // `git am` applies the patch and creates a commit using the metadata from the patch file.
@@ -166,7 +816,8 @@ pub fn apply_commit_to_output(config: &ProjectConfig, commit_hash: &str) -> Resu
   );
 
   let mut apply_cmd = std::process::Command::new("git");
-  apply_cmd.args(&[
+  apply_cmd.args(local_identity_args(config, output_path)?); // Committer under its public identity
+  apply_cmd.args([
     "am",
     "--keep-cr",
     "--committer-date-is-author-date",
@@ -228,7 +879,11 @@ pub fn apply_commit_to_output(config: &ProjectConfig, commit_hash: &str) -> Resu
       "Successfully applied patch for commit {} using 'git am'.",
       commit_hash
     );
-    Ok(ApplyResult::Success)
+    // `git am` succeeds without committing if the change is already there
+    let head = output_head(config)?;
+    Ok(ApplyResult::Success(
+      head.filter(|head| Some(head) != head_before.as_ref()),
+    ))
   } else {
     let stdout = String::from_utf8_lossy(&apply_output.stdout).to_string();
     let stderr = String::from_utf8_lossy(&apply_output.stderr).to_string();
@@ -267,18 +922,429 @@ pub fn apply_commit_to_output(config: &ProjectConfig, commit_hash: &str) -> Resu
   }
 }
 
+/// How many public commits (back from the output repo's HEAD) are compared by patch-id.
+const PATCH_ID_SCAN_DEPTH: usize = 1000;
+
+/// Stable patch-ids of recent public commits, used to recognize internal commits whose change
+/// is already in the output repo (cherry-picked by hand, or applied by an interrupted update).
+pub struct PublicPatchIds {
+  ids: HashMap<String, String>, // patch-id -> most recent public commit with it
+}
+
+impl PublicPatchIds {
+  /// Computes the patch-ids of the last `PATCH_ID_SCAN_DEPTH` non-merge commits of the output repo.
+  pub fn load(config: &ProjectConfig) -> Result<Self> {
+    let mut ids = HashMap::new();
+    if output_head(config)?.is_none() {
+      return Ok(PublicPatchIds { ids });
+    }
+    let depth = PATCH_ID_SCAN_DEPTH.to_string();
+    // Same diff options as `build_commit_patch`, so equal changes get equal ids
+    let log = run_git_command(
+      &[
+        "log",
+        "-p",
+        "--no-merges",
+        "--no-renames",
+        "--full-index",
+        "--binary",
+        "-n",
+        &depth,
+        "HEAD",
+      ],
+      &config.output_path,
+    )?;
+    let output =
+      run_git_command_with_input(&["patch-id", "--stable"], &config.output_path, &log.stdout)?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+      if let Some((patch_id, commit)) = line.split_once(' ') {
+        ids
+          .entry(patch_id.to_string())
+          .or_insert_with(|| commit.to_string());
+      }
+    }
+    debug!("Computed patch-ids of {} public commits.", ids.len());
+    Ok(PublicPatchIds { ids })
+  }
+
+  /// Returns the public commit whose change equals `patch`, if any, provided the change is
+  /// still present at the output HEAD. A change that was published, undone and is now being
+  /// introduced again matches the old public commit by patch-id, but is not a match here.
+  pub fn find(&self, config: &ProjectConfig, patch: &CommitPatch) -> Result<Option<&str>> {
+    if patch.is_empty() || self.ids.is_empty() {
+      return Ok(None);
+    }
+    let input = format!("commit {}\n{}", patch.commit_hash, patch.diff_text());
+    let output = run_git_command_with_input(
+      &["patch-id", "--stable"],
+      &config.output_path,
+      input.as_bytes(),
+    )?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(public_commit) = stdout
+      .split_whitespace()
+      .next()
+      .and_then(|patch_id| self.ids.get(patch_id))
+    else {
+      return Ok(None);
+    };
+    if !present_at_head(config, patch)? {
+      info!(
+        "Commit {} has the patch-id of public commit {}, but its change is not at the output HEAD.",
+        patch.commit_hash, public_commit
+      );
+      return Ok(None);
+    }
+    Ok(Some(public_commit))
+  }
+}
+
+/// Checks with `git apply --check --reverse` whether the output HEAD contains `patch`. Uses a
+/// temporary index, so the output repo's index and working tree are not involved.
+fn present_at_head(config: &ProjectConfig, patch: &CommitPatch) -> Result<bool> {
+  let index_dir = TempDir::new().map_err(|e| PorterError::TempDir { source: e })?;
+  let index_file = index_dir.path().join("index");
+  let index_file = index_file.to_string_lossy();
+  let env = [("GIT_INDEX_FILE", index_file.as_ref())];
+  run_git_command_with_env(&["read-tree", "HEAD"], &config.output_path, &env, &[])?;
+  match run_git_command_with_env(
+    &["apply", "--check", "--reverse", "--cached"],
+    &config.output_path,
+    &env,
+    patch.diff_text().as_bytes(),
+  ) {
+    Ok(_) => Ok(true),
+    Err(PorterError::GitCommand { .. }) => Ok(false),
+    Err(e) => Err(e),
+  }
+}
+
+/// Returns the absolute git directory of the output repo.
+pub(crate) fn output_git_dir(config: &ProjectConfig) -> Result<PathBuf> {
+  let output = run_git_command(&["rev-parse", "--absolute-git-dir"], &config.output_path)?;
+  Ok(PathBuf::from(
+    String::from_utf8_lossy(&output.stdout).trim(),
+  ))
+}
+
+/// Returns the commit checked out in the output repo, or `None` if it has no commits yet.
+pub fn output_head(config: &ProjectConfig) -> Result<Option<String>> {
+  match run_git_command(
+    &["rev-parse", "--verify", "-q", "HEAD"],
+    &config.output_path,
+  ) {
+    Ok(output) => Ok(Some(
+      String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    )),
+    Err(PorterError::GitCommand { .. }) => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+/// Returns whether a `git am` session is in progress in the output repo.
+pub fn apply_in_progress(config: &ProjectConfig) -> Result<bool> {
+  Ok(
+    output_git_dir(config)?
+      .join("rebase-apply")
+      .join("applying")
+      .exists(),
+  )
+}
+
+/// Finishes a `git am` session stopped on a conflict, once the user has resolved the
+/// conflicts and staged the result. Returns `Conflict` if the conflicts are not resolved yet.
+pub fn continue_apply_session(config: &ProjectConfig) -> Result<ApplyResult> {
+  let output_path = &config.output_path;
+  let mut args = local_identity_args(config, output_path)?; // Same committer as a clean apply
+  args.extend(["am".to_string(), "--continue".to_string()]);
+  let args: Vec<&str> = args.iter().map(String::as_str).collect();
+  match run_git_command(&args, output_path) {
+    Ok(_) => {
+      info!(
+        "Finished the 'git am' session in {}.",
+        output_path.display()
+      );
+      Ok(ApplyResult::Success(output_head(config)?))
+    }
+    Err(PorterError::GitCommand { stdout, stderr, .. }) => {
+      warn!("'git am --continue' failed: {}", stderr.trim());
+      if apply_in_progress(config)? {
+        Ok(ApplyResult::Conflict) // Still stopped; the user has more to resolve
+      } else {
+        Ok(ApplyResult::Failure(format!("{}{}", stdout, stderr)))
+      }
+    }
+    Err(e) => Err(e),
+  }
+}
+
 /// Aborts an ongoing apply/am session in the output directory.
 pub fn abort_apply_session(config: &ProjectConfig) -> Result<()> {
-  // Try aborting both cherry-pick and am, as user might have used either manually
+  // Abort whichever operation is in progress (the user might have started one manually)
   warn!(
     "Aborting any ongoing apply/merge/rebase operation in {}",
     config.output_path.display()
   );
-  // Use --quiet to suppress errors if no operation is in progress
-  run_git_command(&["am", "--abort", "--quiet"], &config.output_path)?;
-  run_git_command(&["cherry-pick", "--abort", "--quiet"], &config.output_path)?;
-  run_git_command(&["rebase", "--abort", "--quiet"], &config.output_path)?; // Just in case
-  run_git_command(&["merge", "--abort", "--quiet"], &config.output_path)?; // Just in case
+  let git_dir = output_git_dir(config)?;
+  if git_dir.join("rebase-apply").exists() {
+    // `git am` (or an apply-based rebase)
+    let command = if apply_in_progress(config)? {
+      "am"
+    } else {
+      "rebase"
+    };
+    run_git_command(&[command, "--abort"], &config.output_path)?;
+  }
+  if git_dir.join("rebase-merge").exists() {
+    run_git_command(&["rebase", "--abort"], &config.output_path)?;
+  }
+  if git_dir.join("CHERRY_PICK_HEAD").exists() {
+    run_git_command(&["cherry-pick", "--abort"], &config.output_path)?;
+  }
+  if git_dir.join("MERGE_HEAD").exists() {
+    run_git_command(&["merge", "--abort"], &config.output_path)?;
+  }
   info!("Any potential apply/merge/rebase operation aborted.");
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state::{record_sync_decision, skip_commit, SyncDecision, SyncRecord};
+  use crate::testing::{self, git};
+
+  #[test]
+  fn sync_cursor_stops_at_the_first_undecided_commit() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let first = testing::commit_files(path, &[("crates/foo/a", "1\n")], "First");
+    let second = testing::commit_files(path, &[("crates/foo/a", "2\n")], "Second");
+    testing::commit_files(path, &[("crates/bar/b", "1\n")], "Elsewhere");
+    let third = testing::commit_files(path, &[("crates/foo/a", "3\n")], "Third");
+    let fourth = testing::commit_files(path, &[("crates/foo/a", "4\n")], "Fourth");
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\n",
+      path
+    ))
+    .unwrap();
+
+    record_sync_decision(
+      &config,
+      SyncRecord::now(&second, Some("p1"), SyncDecision::Applied),
+    )
+    .unwrap();
+    skip_commit(&config, &fourth, None).unwrap();
+    let cursor = advance_sync_cursor(&config, Some(&first)).unwrap();
+    assert_eq!(cursor.cursor, Some(second.clone()));
+    assert_eq!(cursor.pending, [third.as_str()]);
+
+    // A skip "for now" is not a decision, and an empty cursor starts at the root.
+    record_sync_decision(
+      &config,
+      SyncRecord::now(&third, None, SyncDecision::Skipped),
+    )
+    .unwrap();
+    let cursor = advance_sync_cursor(&config, None).unwrap();
+    assert_eq!(cursor.cursor, None);
+    assert_eq!(cursor.pending, [first.as_str(), third.as_str()]);
+  }
+
+  #[test]
+  fn public_patch_ids_need_the_change_at_head() {
+    let internal = testing::init_repo();
+    let output = testing::init_repo();
+    testing::commit_files(internal.path(), &[("crates/foo/a", "1\n")], "Add");
+    let change = testing::commit_files(internal.path(), &[("crates/foo/a", "2\n")], "Change");
+    testing::commit_files(output.path(), &[("a", "1\n")], "Initial");
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n",
+      internal.path(),
+      output.path()
+    ))
+    .unwrap();
+    let patch = build_commit_patch(&config, &change).unwrap();
+    assert_eq!(
+      PublicPatchIds::load(&config)
+        .unwrap()
+        .find(&config, &patch)
+        .unwrap(),
+      None
+    );
+
+    // Cherry-picked by hand: matched.
+    let picked = testing::commit_files(output.path(), &[("a", "2\n")], "Picked by hand");
+    let ids = PublicPatchIds::load(&config).unwrap();
+    assert_eq!(ids.find(&config, &patch).unwrap(), Some(picked.as_str()));
+
+    // Undone since: the change is not at HEAD, so re-introducing it is no match.
+    testing::commit_files(output.path(), &[("a", "1\n")], "Revert");
+    let ids = PublicPatchIds::load(&config).unwrap();
+    assert_eq!(ids.find(&config, &patch).unwrap(), None);
+  }
+
+  #[test]
+  fn flattened_sync_cursor_stays_on_the_first_parent_line() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let base = testing::commit_files(path, &[("crates/foo/a", "1\n")], "Base");
+    git(path, &["checkout", "-q", "-b", "side"]);
+    let side_1 = testing::commit_files(path, &[("crates/foo/s", "1\n")], "Side 1");
+    let side_2 = testing::commit_files(path, &[("crates/foo/s", "2\n")], "Side 2");
+    git(path, &["checkout", "-q", "main"]);
+    let main_1 = testing::commit_files(path, &[("crates/foo/a", "2\n")], "Main 1");
+    git(
+      path,
+      &["merge", "-q", "--no-ff", "-m", "Merge side", "side"],
+    );
+    let merge = git(path, &["rev-parse", "HEAD"]);
+    let main_2 = testing::commit_files(path, &[("crates/foo/a", "3\n")], "Main 2");
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\nmerge_strategy = \"flatten\"\n",
+      path
+    ))
+    .unwrap();
+
+    for commit in [&side_2, &main_1] {
+      record_sync_decision(
+        &config,
+        SyncRecord::now(commit, None, SyncDecision::Applied),
+      )
+      .unwrap();
+    }
+    let cursor = advance_sync_cursor(&config, Some(&base)).unwrap();
+    assert_eq!(cursor.cursor, Some(main_1.clone()));
+    assert_eq!(cursor.pending.len(), 2);
+    assert!(cursor.pending.contains(&side_1) && cursor.pending.contains(&main_2));
+
+    // Once the merged branch is decided the cursor moves to the merge, never to a side commit.
+    record_sync_decision(
+      &config,
+      SyncRecord::now(&side_1, None, SyncDecision::Applied),
+    )
+    .unwrap();
+    let cursor = advance_sync_cursor(&config, Some(&base)).unwrap();
+    assert_eq!(cursor.cursor, Some(merge));
+    assert_eq!(cursor.pending, [main_2.as_str()]);
+  }
+
+  #[test]
+  fn boundary_moves_are_renames_across_the_published_tree() {
+    let repo = testing::init_repo();
+    let path = repo.path();
+    let body = |name: &str| format!("// {}\n{}", name, "fn filler() {}\n".repeat(20));
+    let (incoming, outgoing, inner, hidden) =
+      (body("in"), body("out"), body("inner"), body("hidden"));
+    testing::commit_files(
+      path,
+      &[
+        ("crates/bar/in.rs", &incoming),
+        ("crates/foo/out.rs", &outgoing),
+        ("crates/foo/inner.rs", &inner),
+        ("crates/foo/hidden.rs", &hidden),
+      ],
+      "Base",
+    );
+    let renames = [
+      ("crates/bar/in.rs", "crates/foo/in.rs", &incoming),
+      ("crates/foo/out.rs", "crates/bar/out.rs", &outgoing),
+      ("crates/foo/inner.rs", "crates/foo/src/inner.rs", &inner),
+      (
+        "crates/foo/hidden.rs",
+        "crates/foo/internal_docs/hidden.rs",
+        &hidden,
+      ),
+    ];
+    for (from, to, content) in renames {
+      git(path, &["rm", "-q", from]);
+      testing::write_files(path, &[(to, content.as_str())]);
+    }
+    let commit = testing::commit(path, "Move files around");
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = \"out\"\nexclude = [\"internal_docs/\"]\n",
+      path
+    ))
+    .unwrap();
+
+    let mut moves: Vec<String> = detect_boundary_moves(&config, &commit)
+      .unwrap()
+      .iter()
+      .map(ToString::to_string)
+      .collect();
+    moves.sort();
+    assert_eq!(
+      moves,
+      [
+        "'crates/foo/hidden.rs' moved out of the project to 'crates/foo/internal_docs/hidden.rs'",
+        "'crates/foo/in.rs' moved into the project from 'crates/bar/in.rs'",
+        "'crates/foo/out.rs' moved out of the project to 'crates/bar/out.rs'",
+      ]
+    );
+  }
+
+  #[test]
+  fn squashed_patch_stacks_commits_and_lists_their_subjects() {
+    let internal = testing::init_repo();
+    let output = testing::init_repo();
+    let path = internal.path();
+    testing::commit_files(path, &[("crates/foo/a", "1\n")], "Add a");
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    let base = git(path, &["rev-parse", "HEAD"]);
+    testing::commit_files(path, &[("crates/foo/a", "2\n")], "Change a");
+    let picked = testing::commit_files(path, &[("crates/foo/b", "1\n")], "Add b");
+    testing::commit_files(path, &[("crates/bar/c", "1\n")], "Elsewhere");
+    testing::commit_files(path, &[("crates/foo/a", "3\n")], "Change a again");
+    git(path, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    testing::commit_files(output.path(), &[("a", "1\n")], "Initial");
+    let hand_picked = testing::commit_files(output.path(), &[("b", "1\n")], "Picked by hand");
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n",
+      path,
+      output.path()
+    ))
+    .unwrap();
+
+    let commits = list_commits_since(&config, Some(&base)).unwrap();
+    let squashed = build_squashed_patch(&config, &commits).unwrap();
+    assert_eq!(squashed.absorbed.len(), 2);
+    assert_eq!(squashed.already_applied, [(picked, hand_picked)]);
+    assert_eq!(
+      squashed.patch.message,
+      "Sync 2 internal commits\n\n- Change a\n- Change a again"
+    );
+    let diff = squashed.patch.diff_text();
+    assert!(diff.contains("-1\n+3\n"), "{}", diff);
+    assert!(!diff.contains("+2\n"), "{}", diff);
+    // Stacked in a scratch index: the output checkout is left alone.
+    assert_eq!(git(output.path(), &["status", "--porcelain"]), "");
+    assert_eq!(fs::read_to_string(output.path().join("a")).unwrap(), "1\n");
+  }
+
+  #[test]
+  fn squash_message_counts_the_included_commits() {
+    assert_eq!(squash_message(&[]), "Sync 0 internal commits");
+  }
+
+  #[test]
+  fn stacking_fails_on_a_patch_that_does_not_apply() {
+    let internal = testing::init_repo();
+    let output = testing::init_repo();
+    testing::commit_files(internal.path(), &[("crates/foo/a", "1\n")], "Add a");
+    let change = testing::commit_files(internal.path(), &[("crates/foo/a", "2\n")], "Change a");
+    let head = testing::commit_files(output.path(), &[("a", "diverged\n")], "Initial");
+    let config: ProjectConfig = toml::from_str(&format!(
+      "internal_repo_path = {:?}\nproject_subdir = \"crates/foo\"\noutput_path = {:?}\n",
+      internal.path(),
+      output.path()
+    ))
+    .unwrap();
+    let patch = build_commit_patch(&config, &change).unwrap();
+    let scratch = TempDir::new().unwrap();
+    let index = scratch.path().join("index").to_string_lossy().to_string();
+
+    let err = stack_patches(&config, &head, &[patch], &[("GIT_INDEX_FILE", &index)]).unwrap_err();
+    assert!(err.to_string().contains(&change), "{}", err);
+  }
+}
@@ -43,12 +43,140 @@ pub fn run_command_capture(cmd_name: &str, args: &[&str], cwd: &Path) -> Result<
   Ok(output)
 }
 
+/// Runs a command with `input` written to its stdin, capturing output.
+pub fn run_command_with_input(
+  cmd_name: &str,
+  args: &[&str],
+  cwd: &Path,
+  input: &[u8],
+) -> Result<Output> {
+  run_command_with_env(cmd_name, args, cwd, &[], input)
+}
+
+/// Runs a command with extra environment variables and `input` written to its stdin,
+/// capturing output.
+pub fn run_command_with_env(
+  cmd_name: &str,
+  args: &[&str],
+  cwd: &Path,
+  env: &[(&str, &str)],
+  input: &[u8],
+) -> Result<Output> {
+  use std::io::Write;
+  use std::process::Stdio;
+  let cmd_str = format!("{} {}", cmd_name, args.join(" "));
+  debug!(
+    "Running command with stdin: '{}' in directory: {}",
+    cmd_str,
+    cwd.display()
+  );
+
+  let mut child = Command::new(cmd_name)
+    .args(args)
+    .current_dir(cwd)
+    .envs(env.iter().copied())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| PorterError::Io {
+      source: e,
+      path: cwd.to_path_buf(),
+    })?;
+
+  // Write from a separate thread so a full stdout pipe can't deadlock us.
+  let mut stdin = child
+    .stdin
+    .take()
+    .ok_or_else(|| PorterError::GitOperation(format!("Failed to open stdin for '{}'", cmd_str)))?;
+  let input = input.to_vec();
+  let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+  let output = child.wait_with_output().map_err(|e| PorterError::Io {
+    source: e,
+    path: cwd.to_path_buf(),
+  })?;
+  match writer.join() {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => {
+      return Err(PorterError::Io {
+        source: e,
+        path: PathBuf::from("stdin"),
+      })
+    }
+    Err(_) => {
+      return Err(PorterError::GitOperation(format!(
+        "Writing stdin for '{}' panicked",
+        cmd_str
+      )))
+    }
+  }
+
+  if !output.status.success() {
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    error!("Command failed: {}", cmd_str);
+    error!("Stderr: {}", stderr);
+    return Err(PorterError::GitCommand {
+      cmd: cmd_str,
+      cwd: cwd.to_path_buf(),
+      status: output.status.to_string(),
+      stdout,
+      stderr,
+    });
+  }
+  debug!("Command successful: {}", cmd_str);
+  Ok(output)
+}
+
 /// Runs a git command specifically.
 pub fn run_git_command(args: &[&str], cwd: &Path) -> Result<Output> {
   // Could add check_tool_exists("git") here if desired
   run_command_capture("git", args, cwd)
 }
 
+/// Runs a git command with `input` fed to its stdin.
+pub fn run_git_command_with_input(args: &[&str], cwd: &Path, input: &[u8]) -> Result<Output> {
+  run_command_with_input("git", args, cwd, input)
+}
+
+/// Reads the objects named in `revs` (one per line) with a single `git cat-file --batch`
+/// and returns their hash, type and content, in the given order.
+pub(crate) fn read_objects(
+  repo_path: &Path,
+  revs: &[u8],
+) -> Result<Vec<(String, String, Vec<u8>)>> {
+  let batch = run_git_command_with_input(&["cat-file", "--batch"], repo_path, revs)?;
+  let mut objects = Vec::new();
+  let mut rest = batch.stdout.as_slice();
+  while let Some(header_end) = rest.iter().position(|&b| b == b'\n') {
+    let header = String::from_utf8_lossy(&rest[..header_end]).to_string();
+    let unexpected =
+      || PorterError::GitOperation(format!("Unexpected cat-file output '{}'", header));
+    let fields: Vec<&str> = header.split(' ').collect();
+    let [hash, kind, size] = fields.as_slice() else {
+      return Err(unexpected());
+    };
+    let size: usize = size.parse().map_err(|_| unexpected())?;
+    let object = rest
+      .get(header_end + 1..header_end + 1 + size)
+      .ok_or_else(unexpected)?;
+    objects.push((hash.to_string(), kind.to_string(), object.to_vec()));
+    rest = &rest[(header_end + 2 + size).min(rest.len())..];
+  }
+  Ok(objects)
+}
+
+/// Runs a git command with extra environment variables and `input` fed to its stdin.
+pub fn run_git_command_with_env(
+  args: &[&str],
+  cwd: &Path,
+  env: &[(&str, &str)],
+  input: &[u8],
+) -> Result<Output> {
+  run_command_with_env("git", args, cwd, env, input)
+}
+
 // Add check_tool_exists if needed by other modules outside extract.rs
 pub fn check_tool_exists(tool_name: &str) -> Result<()> {
   use std::process::Stdio;
@@ -73,3 +201,79 @@ pub fn check_tool_exists(tool_name: &str) -> Result<()> {
     .then_some(()) // Convert success to Ok(())
     .ok_or_else(|| PorterError::ToolNotFound(format!("Tool '{}' command check failed.", tool_name)))
 }
+
+/// Converts days since the Unix epoch into a (year, month, day) civil date (UTC).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  // Howard Hinnant's `civil_from_days` algorithm.
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+/// Seconds since the Unix epoch.
+fn unix_now() -> i64 {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or_default()
+}
+
+/// Returns the current year (UTC).
+pub fn current_year() -> i64 {
+  civil_from_days(unix_now().div_euclid(86_400)).0
+}
+
+/// Returns the current time as an RFC 3339 UTC timestamp, e.g. `2024-05-01T12:30:00Z`.
+pub fn now_rfc3339() -> String {
+  let secs = unix_now();
+  let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+  let time = secs.rem_euclid(86_400);
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+    year,
+    month,
+    day,
+    time / 3600,
+    time % 3600 / 60,
+    time % 60
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn civil_from_days_converts_known_dates() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    assert_eq!(civil_from_days(19_783), (2024, 3, 1));
+    assert_eq!(civil_from_days(20_088), (2024, 12, 31));
+  }
+
+  #[test]
+  fn civil_from_days_handles_century_leap_rules() {
+    // Neither 1900 nor 2100 has a Feb 29
+    assert_eq!(civil_from_days(-25_509), (1900, 2, 28));
+    assert_eq!(civil_from_days(-25_508), (1900, 3, 1));
+    assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+    assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+  }
+
+  #[test]
+  fn now_rfc3339_is_a_utc_timestamp() {
+    let now = now_rfc3339();
+    assert_eq!(now.len(), 20);
+    assert_eq!((&now[4..5], &now[10..11], &now[19..]), ("-", "T", "Z"));
+    assert_eq!(now[..4].parse::<i64>().unwrap(), current_year());
+  }
+}